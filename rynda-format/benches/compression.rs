@@ -0,0 +1,52 @@
+//! Size and load speed of the volume file with different compression methods on the bundled
+//! test model. Run with `cargo bench -p rynda-format --features lz4,zstd`.
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use rynda_format::{
+    file::{volume_from_bytes, volume_to_bytes, Compression},
+    from_vox::vox_to_rle_volume,
+};
+
+const TEST_MODEL: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../assets/test_model.vox");
+
+fn compressions() -> Vec<(&'static str, Compression)> {
+    #[allow(unused_mut)]
+    let mut methods = vec![("none", Compression::None)];
+    #[cfg(feature = "lz4")]
+    methods.push(("lz4", Compression::Lz4));
+    #[cfg(feature = "zstd")]
+    {
+        methods.push(("zstd-3", Compression::Zstd { level: 3 }));
+        methods.push(("zstd-19", Compression::Zstd { level: 19 }));
+    }
+    methods
+}
+
+fn load_benchmark(c: &mut Criterion) {
+    let volume = vox_to_rle_volume(TEST_MODEL).expect("Test model is loaded");
+    let mut group = c.benchmark_group("load");
+    for (name, compression) in compressions() {
+        let bytes = volume_to_bytes(&volume, compression);
+        println!(
+            "{}: {} bytes, {:.1}% of in-memory size",
+            name,
+            bytes.len(),
+            bytes.len() as f64 * 100.0 / volume.memory_size() as f64
+        );
+        group.bench_with_input(BenchmarkId::from_parameter(name), &bytes, |b, bytes| {
+            b.iter(|| volume_from_bytes(bytes).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn save_benchmark(c: &mut Criterion) {
+    let volume = vox_to_rle_volume(TEST_MODEL).expect("Test model is loaded");
+    let mut group = c.benchmark_group("save");
+    for (name, compression) in compressions() {
+        group.bench_function(name, |b| b.iter(|| volume_to_bytes(&volume, compression)));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, load_benchmark, save_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,34 @@
+fn main() {
+    #[cfg(feature = "ffi")]
+    ffi::build();
+}
+
+/// Generate C header from the `ffi` module and compile the C test program against it
+#[cfg(feature = "ffi")]
+mod ffi {
+    use std::{env, path::PathBuf};
+
+    pub fn build() {
+        let crate_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+        let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+        for file in ["src/ffi.rs", "cbindgen.toml", "ffi/test.c"] {
+            println!("cargo:rerun-if-changed={}", file);
+        }
+
+        let config = cbindgen::Config::from_file(crate_dir.join("cbindgen.toml"))
+            .expect("Valid cbindgen.toml");
+        cbindgen::Builder::new()
+            .with_config(config)
+            .with_src(crate_dir.join("src/ffi.rs"))
+            .generate()
+            .expect("C header is generated")
+            .write_to_file(out_dir.join("rynda_format.h"));
+
+        cc::Build::new()
+            .file(crate_dir.join("ffi/test.c"))
+            .include(&out_dir)
+            .warnings(true)
+            .warnings_into_errors(true)
+            .compile("rynda_ffi_test");
+    }
+}
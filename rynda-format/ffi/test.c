@@ -0,0 +1,76 @@
+/* Checks of the C API that run from `cargo test --features ffi` */
+#include "rynda_format.h"
+
+#define CHECK(n, cond) \
+    if (!(cond)) { \
+        result = (n); \
+        goto done; \
+    }
+
+/* Pack color into 5-6-5 voxel */
+static uint16_t rgb(uint16_t r, uint16_t g, uint16_t b) {
+    return (uint16_t)(r | (g << 5) | (b << 11));
+}
+
+int rynda_ffi_c_test(const char *path) {
+    int result = 0;
+    RyndaVolume *volume = NULL;
+    RyndaVolume *copy = NULL;
+    RyndaVolume *loaded = NULL;
+    RyndaBytes bytes = {NULL, 0};
+    uint32_t sizes[3] = {0, 0, 0};
+    uint16_t voxel = 1;
+    size_t count = 0;
+    size_t size = 0;
+    const uint8_t *pointers;
+    const uint8_t *columns;
+
+    CHECK(1, rynda_api_version() == RYNDA_API_VERSION);
+    CHECK(2, rynda_volume_new(2000, 1, 1) == NULL);
+    volume = rynda_volume_new(4, 5, 6);
+    CHECK(3, volume != NULL);
+    CHECK(4, rynda_volume_dimensions(volume, sizes) == RyndaStatus_Ok);
+    CHECK(5, sizes[0] == 4 && sizes[1] == 5 && sizes[2] == 6);
+    CHECK(6, rynda_volume_axis(volume) == 1);
+
+    CHECK(7, rynda_volume_get(volume, 1, 2, 3, &voxel) == RyndaStatus_Ok && voxel == 0);
+    CHECK(8, rynda_volume_set(volume, 1, 2, 3, rgb(31, 0, 7)) == RyndaStatus_Ok);
+    CHECK(9, rynda_volume_set(volume, 1, 4, 3, rgb(1, 2, 3)) == RyndaStatus_Ok);
+    CHECK(10, rynda_volume_get(volume, 1, 2, 3, &voxel) == RyndaStatus_Ok);
+    CHECK(11, voxel == rgb(31, 0, 7));
+    CHECK(12, rynda_volume_get(volume, 1, 5, 3, &voxel) == RyndaStatus_OutOfBounds);
+    CHECK(13, rynda_volume_set(NULL, 0, 0, 0, 0) == RyndaStatus_NullArgument);
+
+    /* Column 1 + 3 * 4 has ranges skip 2 draw 1 and skip 1 draw 1 */
+    pointers = rynda_volume_pointers(volume, &count);
+    CHECK(14, pointers != NULL && count == 24);
+    {
+        const uint8_t *entry = pointers + 13 * RYNDA_POINTER_COLUMN_SIZE;
+        uint32_t offset = entry[0] | (entry[1] << 8) | (entry[2] << 16) | ((uint32_t)entry[3] << 24);
+        uint16_t rle_count = (uint16_t)(entry[4] | (entry[5] << 8));
+        uint16_t first = (uint16_t)(entry[6] | (entry[7] << 8));
+        CHECK(15, rle_count >= 1 && (first & 0x3FF) == 2 && (first >> 10) == 1);
+        columns = rynda_volume_columns(volume, &size);
+        CHECK(16, columns != NULL && offset + rle_count * 2 + 4 <= size);
+        /* Colors follow the ranges */
+        voxel = (uint16_t)(columns[offset + rle_count * 2] | (columns[offset + rle_count * 2 + 1] << 8));
+        CHECK(17, voxel == rgb(31, 0, 7));
+    }
+
+    CHECK(18, rynda_volume_to_bytes(volume, &bytes) == RyndaStatus_Ok && bytes.len > 0);
+    CHECK(19, rynda_volume_from_bytes(bytes.data, bytes.len, &copy) == RyndaStatus_Ok);
+    CHECK(20, rynda_volume_get(copy, 1, 4, 3, &voxel) == RyndaStatus_Ok && voxel == rgb(1, 2, 3));
+    CHECK(21, rynda_volume_from_bytes(bytes.data, 3, &loaded) == RyndaStatus_InvalidData);
+
+    CHECK(22, rynda_volume_save(copy, path) == RyndaStatus_Ok);
+    CHECK(23, rynda_volume_load(path, &loaded) == RyndaStatus_Ok);
+    CHECK(24, rynda_volume_get(loaded, 1, 2, 3, &voxel) == RyndaStatus_Ok && voxel == rgb(31, 0, 7));
+    CHECK(25, rynda_volume_load("/nonexistent/volume.rvol", &loaded) == RyndaStatus_IoError);
+
+done:
+    rynda_bytes_free(bytes);
+    rynda_volume_free(loaded);
+    rynda_volume_free(copy);
+    rynda_volume_free(volume);
+    return result;
+}
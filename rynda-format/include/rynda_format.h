@@ -0,0 +1,152 @@
+#ifndef RYNDA_FORMAT_H
+#define RYNDA_FORMAT_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit by hand */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Version of the C API, bumped when behaviour of existing functions changes
+#define RYNDA_API_VERSION 1
+
+// Size of a single entry of the pointers map in bytes
+#define RYNDA_POINTER_COLUMN_SIZE 8
+
+// Result of fallible calls
+typedef enum RyndaStatus {
+  RyndaStatus_Ok = 0,
+  // Required pointer argument is null
+  RyndaStatus_NullArgument = 1,
+  // Coordinates or sizes are outside of the volume or supported range
+  RyndaStatus_OutOfBounds = 2,
+  // Bytes or file don't contain a valid volume, or path is not valid UTF-8
+  RyndaStatus_InvalidData = 3,
+  // File can't be read or written
+  RyndaStatus_IoError = 4,
+} RyndaStatus;
+
+// Volume owned by the caller, released with `rynda_volume_free`
+typedef struct RyndaVolume RyndaVolume;
+
+// Bytes allocated by the library, released with `rynda_bytes_free`
+typedef struct RyndaBytes {
+  uint8_t *data;
+  size_t len;
+} RyndaBytes;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Version of the C API the library implements
+uint32_t rynda_api_version(void);
+
+// Make empty volume, returns null when any size is 1024 or more
+struct RyndaVolume *rynda_volume_new(uint32_t xsize, uint32_t ysize, uint32_t zsize);
+
+// Copy the volume, returns null for null volume
+//
+// # Safety
+// `volume` is null or a valid volume.
+struct RyndaVolume *rynda_volume_clone(const struct RyndaVolume *volume);
+
+// Release the volume, null is ignored
+//
+// # Safety
+// `volume` is null or a valid volume that is not used after the call.
+void rynda_volume_free(struct RyndaVolume *volume);
+
+// Write sizes of the volume along model X, Y and Z into `sizes`
+//
+// # Safety
+// `volume` is null or a valid volume, `sizes` is null or points to 3 writable values.
+enum RyndaStatus rynda_volume_dimensions(const struct RyndaVolume *volume, uint32_t *sizes);
+
+// Model axis that columns run along: 0 for X, 1 for Y and 2 for Z. Sizes of the pointers
+// map and columns are given in the volume layout, where this axis is swapped with Y.
+// Returns 255 for null volume.
+//
+// # Safety
+// `volume` is null or a valid volume.
+uint8_t rynda_volume_axis(const struct RyndaVolume *volume);
+
+// Read voxel into `voxel`
+//
+// # Safety
+// `volume` is null or a valid volume, `voxel` is null or writable.
+enum RyndaStatus rynda_volume_get(const struct RyndaVolume *volume,
+                                  uint32_t x,
+                                  uint32_t y,
+                                  uint32_t z,
+                                  uint16_t *voxel);
+
+// Change single voxel. The column is repacked, so prefer building volumes in Rust when
+// many voxels change.
+//
+// # Safety
+// `volume` is null or a valid volume that is not accessed concurrently.
+enum RyndaStatus rynda_volume_set(struct RyndaVolume *volume,
+                                  uint32_t x,
+                                  uint32_t y,
+                                  uint32_t z,
+                                  uint16_t voxel);
+
+// Encode volume in the volume file format without compression. The bytes are released
+// with `rynda_bytes_free`.
+//
+// # Safety
+// `volume` is null or a valid volume, `bytes` is null or writable.
+enum RyndaStatus rynda_volume_to_bytes(const struct RyndaVolume *volume, struct RyndaBytes *bytes);
+
+// Release bytes made by the library
+//
+// # Safety
+// `bytes` is made by the library and is not used after the call.
+void rynda_bytes_free(struct RyndaBytes bytes);
+
+// Decode volume from bytes of the volume file format into `volume`
+//
+// # Safety
+// `data` points to `len` readable bytes, `volume` is null or writable.
+enum RyndaStatus rynda_volume_from_bytes(const uint8_t *data,
+                                         size_t len,
+                                         struct RyndaVolume **volume);
+
+// Load volume file into `volume`
+//
+// # Safety
+// `path` is null or a null terminated string, `volume` is null or writable.
+enum RyndaStatus rynda_volume_load(const char *path, struct RyndaVolume **volume);
+
+// Save volume file without compression
+//
+// # Safety
+// `volume` is null or a valid volume, `path` is null or a null terminated string.
+enum RyndaStatus rynda_volume_save(const struct RyndaVolume *volume, const char *path);
+
+// Read-only pointers map of `count` entries of `RYNDA_POINTER_COLUMN_SIZE` bytes, indexed
+// by `x + z * xsize` in the volume layout. Each entry is the offset of the column in the
+// columns buffer (`uint32_t`), amount of ranges after the first one (`uint16_t`) and the
+// first range (`uint16_t`, skipped voxels in bits 0..10 and drawn voxels in bits 10..16).
+// The pointer is valid until the volume changes or is released, null for empty map.
+//
+// # Safety
+// `volume` is null or a valid volume, `count` is null or writable.
+const uint8_t *rynda_volume_pointers(const struct RyndaVolume *volume, size_t *count);
+
+// Read-only columns buffer of `size` bytes. Each column holds ranges after the first
+// one followed by packed colors of all drawn voxels. The pointer is valid until the
+// volume changes or is released, null for empty buffer.
+//
+// # Safety
+// `volume` is null or a valid volume, `size` is null or writable.
+const uint8_t *rynda_volume_columns(const struct RyndaVolume *volume, size_t *size);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* RYNDA_FORMAT_H */
@@ -0,0 +1,252 @@
+use super::{
+    patch::{PatchError, VolumePatch},
+    types::volume::RleVolume,
+};
+use std::time::Duration;
+
+/// Duration of a single frame when the source doesn't define it, 10 frames per second
+pub const DEFAULT_FRAME_DURATION: Duration = Duration::from_millis(100);
+
+/// Key frame of an animation
+#[derive(Clone, Debug)]
+pub struct AnimationFrame {
+    /// Index of the timeline frame where the volume appears, the volume is shown until
+    /// the start of the next key frame
+    pub start: u32,
+    /// Voxels of the frame
+    pub volume: RleVolume,
+}
+
+/// Sequence of volumes with the same dimensions that are shown one after another
+#[derive(Clone, Debug)]
+pub struct VoxelAnimation {
+    /// Key frames sorted by start
+    pub frames: Vec<AnimationFrame>,
+    /// Duration of a single timeline frame
+    pub frame_duration: Duration,
+}
+
+impl VoxelAnimation {
+    /// Make animation without frames
+    pub fn new(frame_duration: Duration) -> Self {
+        VoxelAnimation {
+            frames: vec![],
+            frame_duration,
+        }
+    }
+
+    /// Add key frame at given timeline frame, frames are kept sorted by start
+    pub fn push(&mut self, start: u32, volume: RleVolume) {
+        if let Some(first) = self.frames.first() {
+            assert_eq!(
+                first.volume.dimensions(),
+                volume.dimensions(),
+                "Frames of animation have the same dimensions"
+            );
+        }
+        let pos = self.frames.partition_point(|f| f.start <= start);
+        self.frames.insert(pos, AnimationFrame { start, volume });
+    }
+
+    /// Amount of key frames
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Check that the animation has no frames
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Amount of timeline frames, the last key frame lasts one frame
+    pub fn timeline_len(&self) -> u32 {
+        self.frames.last().map_or(0, |f| f.start + 1)
+    }
+
+    /// Duration of a single loop of the animation
+    pub fn duration(&self) -> Duration {
+        self.frame_duration * self.timeline_len()
+    }
+
+    /// Index of the key frame that is shown at given time, the animation is looped
+    pub fn frame_index_at(&self, time: Duration) -> Option<usize> {
+        if self.frames.is_empty() {
+            return None;
+        }
+        let ticks = (time.as_nanos() / self.frame_duration.as_nanos().max(1)) as u64;
+        let frame = (ticks % self.timeline_len() as u64) as u32;
+        Some(
+            self.frames
+                .partition_point(|f| f.start <= frame)
+                .saturating_sub(1),
+        )
+    }
+
+    /// Volume that is shown at given time, the animation is looped
+    pub fn volume_at(&self, time: Duration) -> Option<&RleVolume> {
+        self.frame_index_at(time).map(|i| &self.frames[i].volume)
+    }
+
+    /// Amount of memory all frames take in bytes
+    pub fn memory_size(&self) -> usize {
+        self.frames.iter().map(|f| f.volume.memory_size()).sum()
+    }
+
+    /// Keep the first frame and only columns that change between consecutive frames
+    pub fn to_deltas(&self) -> Option<DeltaAnimation> {
+        let first = self.frames.first()?;
+        Some(DeltaAnimation {
+            base: first.volume.clone(),
+            starts: self.frames.iter().map(|f| f.start).collect(),
+            patches: self
+                .frames
+                .windows(2)
+                .map(|w| w[0].volume.diff(&w[1].volume))
+                .collect(),
+            frame_duration: self.frame_duration,
+        })
+    }
+}
+
+/// Animation that keeps the first volume and patches that turn each key frame into the
+/// next one. Takes much less memory than `VoxelAnimation` when only parts of the model move.
+#[derive(Clone, Debug)]
+pub struct DeltaAnimation {
+    /// Volume of the first key frame
+    pub base: RleVolume,
+    /// Timeline frames where key frames start
+    pub starts: Vec<u32>,
+    /// Patch `i` turns key frame `i` into key frame `i + 1`
+    pub patches: Vec<VolumePatch>,
+    /// Duration of a single timeline frame
+    pub frame_duration: Duration,
+}
+
+impl DeltaAnimation {
+    /// Amount of key frames
+    pub fn len(&self) -> usize {
+        self.starts.len()
+    }
+
+    /// Check that the animation has no frames, which never happens for animation made by
+    /// `VoxelAnimation::to_deltas`
+    pub fn is_empty(&self) -> bool {
+        self.starts.is_empty()
+    }
+
+    /// Turn volume of key frame `index` into the next key frame. Playback keeps one
+    /// volume and advances it frame by frame, wrapping to `base` at the end.
+    pub fn advance(&self, volume: &mut RleVolume, index: usize) -> Result<(), PatchError> {
+        match self.patches.get(index) {
+            Some(patch) => volume.apply_patch(patch),
+            None => {
+                *volume = self.base.clone();
+                Ok(())
+            }
+        }
+    }
+
+    /// Restore volume of key frame by applying patches to the base
+    pub fn frame(&self, index: usize) -> Result<RleVolume, PatchError> {
+        let mut volume = self.base.clone();
+        for i in 0..index {
+            self.advance(&mut volume, i)?;
+        }
+        Ok(volume)
+    }
+
+    /// Restore all key frames
+    pub fn to_frames(&self) -> Result<VoxelAnimation, PatchError> {
+        let mut animation = VoxelAnimation::new(self.frame_duration);
+        let mut volume = self.base.clone();
+        for (i, start) in self.starts.iter().enumerate() {
+            if i > 0 {
+                self.advance(&mut volume, i - 1)?;
+            }
+            animation.frames.push(AnimationFrame {
+                start: *start,
+                volume: volume.clone(),
+            });
+        }
+        Ok(animation)
+    }
+
+    /// Approximate amount of memory the base and patches take in bytes
+    pub fn memory_size(&self) -> usize {
+        self.base.memory_size()
+            + self
+                .patches
+                .iter()
+                .flat_map(|p| p.columns.iter())
+                .map(|(_, c)| std::mem::size_of::<(usize, u16, u16)>() + c.bytes.len())
+                .sum::<usize>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::voxel::RgbVoxel;
+    use ndarray::Array3;
+
+    /// Large static floor and a voxel that moves along X
+    fn frame(x: usize) -> RleVolume {
+        let voxels = Array3::from_shape_fn((16, 8, 16), |(vx, vy, _)| {
+            if vy == 0 || (vy == 4 && vx == x) {
+                RgbVoxel::rgb(vx as u8, 10, 3)
+            } else {
+                RgbVoxel::empty()
+            }
+        });
+        voxels.into()
+    }
+
+    fn animation() -> VoxelAnimation {
+        let mut animation = VoxelAnimation::new(DEFAULT_FRAME_DURATION);
+        for (i, x) in [0, 3, 5, 6].iter().enumerate() {
+            animation.push(i as u32 * 2, frame(*x));
+        }
+        animation
+    }
+
+    #[test]
+    fn timing_test() {
+        let animation = animation();
+        assert_eq!(animation.timeline_len(), 7);
+        assert_eq!(animation.duration(), Duration::from_millis(700));
+        let at = |ms| animation.frame_index_at(Duration::from_millis(ms)).unwrap();
+        assert_eq!(at(0), 0);
+        assert_eq!(at(199), 0, "Key frame lasts until the next one");
+        assert_eq!(at(250), 1);
+        assert_eq!(at(650), 3);
+        assert_eq!(at(720), 0, "Animation is looped");
+        assert!(VoxelAnimation::new(DEFAULT_FRAME_DURATION)
+            .volume_at(Duration::ZERO)
+            .is_none());
+    }
+
+    #[test]
+    fn deltas_test() {
+        let animation = animation();
+        let deltas = animation.to_deltas().unwrap();
+        assert_eq!(deltas.len(), animation.len());
+        assert!(
+            deltas.memory_size() < animation.memory_size() / 2,
+            "Only changed columns are kept"
+        );
+        for (i, f) in animation.frames.iter().enumerate() {
+            assert_eq!(deltas.frame(i).unwrap().to_array(), f.volume.to_array());
+        }
+
+        let restored = deltas.to_frames().unwrap();
+        assert_eq!(
+            restored.frames.iter().map(|f| f.start).collect::<Vec<_>>(),
+            vec![0, 2, 4, 6]
+        );
+        let mut playing = deltas.base.clone();
+        for i in 0..deltas.len() {
+            deltas.advance(&mut playing, i).unwrap();
+        }
+        assert_eq!(playing.checksum(), deltas.base.checksum(), "Playback loops");
+    }
+}
@@ -0,0 +1,256 @@
+use super::types::{
+    attribute::AttributeStream, normal::estimate_normals, volume::RleVolume, voxel::RgbVoxel,
+};
+use ndarray::Array3;
+use std::thread;
+
+/// Parameters of ambient occlusion baking
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AoSettings {
+    /// Maximum distance in voxels that rays travel looking for occluders
+    pub radius: f32,
+    /// Amount of rays that are casted from each surface voxel into its hemisphere
+    pub samples: u32,
+    /// How much fully occluded voxel is darkened, 0.0 disables occlusion and 1.0 makes
+    /// fully occluded voxels black.
+    pub strength: f32,
+    /// Amount of threads that bake columns of the volume in parallel, 0 means amount of
+    /// available CPUs.
+    pub threads: usize,
+}
+
+impl Default for AoSettings {
+    fn default() -> Self {
+        AoSettings {
+            radius: 8.0,
+            samples: 32,
+            strength: 1.0,
+            threads: 0,
+        }
+    }
+}
+
+/// Directions that are evenly distributed over hemisphere around the normal
+fn hemisphere_directions(normal: [f32; 3], samples: u32) -> Vec<[f32; 3]> {
+    // Fibonacci sphere with twice as many points, half of them faces the normal
+    let total = samples.max(1) * 2;
+    let golden_angle = std::f32::consts::PI * (3.0 - 5.0f32.sqrt());
+    (0..total)
+        .map(|i| {
+            let y = 1.0 - (i as f32 + 0.5) / total as f32 * 2.0;
+            let r = (1.0 - y * y).sqrt();
+            let theta = golden_angle * i as f32;
+            [r * theta.cos(), y, r * theta.sin()]
+        })
+        .filter(|d| d[0] * normal[0] + d[1] * normal[1] + d[2] * normal[2] > 0.0)
+        .collect()
+}
+
+/// Walk from the voxel center along the direction and check whether it hits a filled voxel
+fn ray_hits(occupancy: &Array3<bool>, from: [u32; 3], dir: [f32; 3], radius: f32) -> bool {
+    let (xsize, ysize, zsize) = occupancy.dim();
+    let step = 0.5;
+    let mut t = step;
+    while t <= radius {
+        let p = [
+            (from[0] as f32 + 0.5 + dir[0] * t).floor(),
+            (from[1] as f32 + 0.5 + dir[1] * t).floor(),
+            (from[2] as f32 + 0.5 + dir[2] * t).floor(),
+        ];
+        if p[0] < 0.0
+            || p[1] < 0.0
+            || p[2] < 0.0
+            || p[0] as usize >= xsize
+            || p[1] as usize >= ysize
+            || p[2] as usize >= zsize
+        {
+            return false;
+        }
+        let p = [p[0] as usize, p[1] as usize, p[2] as usize];
+        if p != [from[0] as usize, from[1] as usize, from[2] as usize]
+            && occupancy[(p[0], p[1], p[2])]
+        {
+            return true;
+        }
+        t += step;
+    }
+    false
+}
+
+/// Check that voxel has at least one empty neighbour by faces
+fn is_surface(occupancy: &Array3<bool>, x: u32, y: u32, z: u32) -> bool {
+    let (xsize, ysize, zsize) = occupancy.dim();
+    let neighbours = [
+        [-1, 0, 0],
+        [1, 0, 0],
+        [0, -1, 0],
+        [0, 1, 0],
+        [0, 0, -1],
+        [0, 0, 1],
+    ];
+    neighbours.iter().any(|d| {
+        let p = [x as i64 + d[0], y as i64 + d[1], z as i64 + d[2]];
+        p[0] < 0
+            || p[1] < 0
+            || p[2] < 0
+            || p[0] as usize >= xsize
+            || p[1] as usize >= ysize
+            || p[2] as usize >= zsize
+            || !occupancy[(p[0] as usize, p[1] as usize, p[2] as usize)]
+    })
+}
+
+/// Bake ambient occlusion of all drawn voxels into a byte stream parallel to the colors.
+/// 255 means fully lit voxel and 0 means fully occluded one. Voxels that are hidden
+/// inside the volume are fully occluded.
+///
+/// Each surface voxel casts rays over the hemisphere around its estimated normal and
+/// counts how much of them are blocked by other voxels within the radius. Space outside
+/// of the volume doesn't occlude anything.
+pub fn bake_ao(volume: &RleVolume, settings: &AoSettings) -> AttributeStream<u8> {
+    let occupancy = volume.to_array().map(|v| !v.is_empty());
+    let normals = estimate_normals(volume);
+    let threads = if settings.threads == 0 {
+        thread::available_parallelism().map_or(1, |n| n.get())
+    } else {
+        settings.threads
+    };
+    let columns = volume.columns_count();
+    let per_thread = columns.div_ceil(threads.max(1)).max(1);
+
+    let baked: Vec<Vec<(usize, u8)>> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..columns)
+            .step_by(per_thread)
+            .map(|start| {
+                let occupancy = &occupancy;
+                let normals = &normals;
+                scope.spawn(move || {
+                    let mut values = vec![];
+                    for i in start..(start + per_thread).min(columns) {
+                        let x = (i % volume.xsize as usize) as u32;
+                        let z = (i / volume.xsize as usize) as u32;
+                        for voxel in volume.drawn_voxels(i) {
+                            // Occupancy and normals are in model coordinates
+                            let [x, y, z] = volume.axis.swap([x, voxel.y, z]);
+                            if !is_surface(occupancy, x, y, z) {
+                                values.push((voxel.slot, 0));
+                                continue;
+                            }
+                            let normal = normals.values[voxel.slot].decode();
+                            let dirs = hemisphere_directions(normal, settings.samples);
+                            let hits = dirs
+                                .iter()
+                                .filter(|d| ray_hits(occupancy, [x, y, z], **d, settings.radius))
+                                .count();
+                            let occlusion = hits as f32 / dirs.len().max(1) as f32;
+                            let light = (1.0 - occlusion * settings.strength).clamp(0.0, 1.0);
+                            values.push((voxel.slot, (light * 255.0).round() as u8));
+                        }
+                    }
+                    values
+                })
+            })
+            .collect();
+        workers.into_iter().map(|w| w.join().unwrap()).collect()
+    });
+
+    let mut stream = AttributeStream::new(volume);
+    for (slot, value) in baked.into_iter().flatten() {
+        stream.values[slot] = value;
+    }
+    stream
+}
+
+/// Bake ambient occlusion right into voxel colors by darkening them
+pub fn darken_by_ao(volume: &RleVolume, ao: &AttributeStream<u8>) -> RleVolume {
+    assert!(ao.matches(volume), "AO stream doesn't match the volume");
+    volume.map_colors(|slot, color| {
+        let light = ao.values[slot] as u16;
+        let scale = |c: u8| ((c as u16 * light + 127) / 255) as u8;
+        let darkened = RgbVoxel::rgb(
+            scale(color.red()),
+            scale(color.green()),
+            scale(color.blue()),
+        );
+        // Darkening must not turn the voxel into an empty one
+        if darkened.is_empty() {
+            RgbVoxel::black()
+        } else {
+            darkened
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::Array3;
+
+    /// Floor with a pillar in the middle
+    fn pillar_volume() -> RleVolume {
+        let voxels = Array3::from_shape_fn((9, 6, 9), |(x, y, z)| {
+            if y == 0 || (x == 4 && z == 4) {
+                RgbVoxel::rgb(31, 63, 31)
+            } else {
+                RgbVoxel::empty()
+            }
+        });
+        voxels.into()
+    }
+
+    fn floor_ao(volume: &RleVolume, ao: &AttributeStream<u8>, x: u32, z: u32) -> u8 {
+        let voxel = volume.drawn_voxels(volume.column_index(x, z))[0];
+        ao.values[voxel.slot]
+    }
+
+    #[test]
+    fn bake_ao_test() {
+        let volume = pillar_volume();
+        let settings = AoSettings {
+            threads: 3,
+            ..AoSettings::default()
+        };
+        let ao = bake_ao(&volume, &settings);
+        assert!(ao.matches(&volume), "AO is parallel to colors");
+
+        let near = floor_ao(&volume, &ao, 5, 4);
+        let far = floor_ao(&volume, &ao, 0, 0);
+        assert!(
+            near < far,
+            "Floor near the pillar is darker than far corner: {} vs {}",
+            near,
+            far
+        );
+        let top = volume.drawn_voxels(volume.column_index(4, 4));
+        assert_eq!(
+            ao.values[top.last().unwrap().slot],
+            255,
+            "Top of the pillar is fully lit"
+        );
+
+        let single = bake_ao(
+            &volume,
+            &AoSettings {
+                threads: 1,
+                ..settings
+            },
+        );
+        assert_eq!(ao, single, "Baking doesn't depend on threads count");
+    }
+
+    #[test]
+    fn darken_by_ao_test() {
+        let volume = pillar_volume();
+        let ao = bake_ao(&volume, &AoSettings::default());
+        let darkened = darken_by_ao(&volume, &ao);
+        let near = darkened.get(5, 0, 4);
+        let far = darkened.get(0, 0, 0);
+        assert!(near.green() < far.green(), "Occluded voxel is darker");
+        assert!(!near.is_empty(), "Darkened voxel is still present");
+        assert_eq!(
+            volume.to_array().map(|v| v.is_empty()),
+            darkened.to_array().map(|v| v.is_empty()),
+            "Darkening keeps occupancy"
+        );
+    }
+}
@@ -0,0 +1,374 @@
+use super::types::{column::RleColumn, volume::RleVolume, voxel::RgbVoxel};
+
+/// Shape of brush in its local coordinates, centered at origin
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BrushShape {
+    /// Ball with given radius
+    Sphere { radius: f32 },
+    /// Box with given half sizes along local axes
+    Box { half_extents: [f32; 3] },
+    /// Cylinder that stands along local Y axis
+    Cylinder { radius: f32, half_height: f32 },
+}
+
+impl BrushShape {
+    /// Check that point in local coordinates is inside the shape
+    fn contains(&self, p: [f32; 3]) -> bool {
+        match self {
+            BrushShape::Sphere { radius } => {
+                p[0] * p[0] + p[1] * p[1] + p[2] * p[2] <= radius * radius
+            }
+            BrushShape::Box { half_extents } => (0..3).all(|i| p[i].abs() <= half_extents[i]),
+            BrushShape::Cylinder {
+                radius,
+                half_height,
+            } => p[0] * p[0] + p[2] * p[2] <= radius * radius && p[1].abs() <= *half_height,
+        }
+    }
+
+    /// Half sizes of box that encloses the shape in local coordinates
+    fn half_extents(&self) -> [f32; 3] {
+        match self {
+            BrushShape::Sphere { radius } => [*radius; 3],
+            BrushShape::Box { half_extents } => *half_extents,
+            BrushShape::Cylinder {
+                radius,
+                half_height,
+            } => [*radius, *half_height, *radius],
+        }
+    }
+}
+
+/// Placement of brush in voxel coordinates of edited volume. Rotation is orthonormal matrix
+/// that is stored by rows and maps local brush axes into volume ones.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BrushTransform {
+    /// Position of brush center in voxels
+    pub translation: [f32; 3],
+    /// Orientation of brush
+    pub rotation: [[f32; 3]; 3],
+}
+
+impl Default for BrushTransform {
+    fn default() -> Self {
+        BrushTransform::at([0.0; 3])
+    }
+}
+
+impl BrushTransform {
+    /// Brush without rotation at given position
+    pub fn at(translation: [f32; 3]) -> Self {
+        BrushTransform {
+            translation,
+            rotation: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+        }
+    }
+
+    /// Replace orientation of the brush
+    pub fn with_rotation(self, rotation: [[f32; 3]; 3]) -> Self {
+        BrushTransform { rotation, ..self }
+    }
+
+    /// Shift brush by the offset
+    pub fn translated(self, offset: [f32; 3]) -> Self {
+        let t = self.translation;
+        BrushTransform {
+            translation: [t[0] + offset[0], t[1] + offset[1], t[2] + offset[2]],
+            ..self
+        }
+    }
+
+    /// Map point from volume coordinates to local coordinates of the brush
+    fn local_point(&self, p: [f32; 3]) -> [f32; 3] {
+        let d = [
+            p[0] - self.translation[0],
+            p[1] - self.translation[1],
+            p[2] - self.translation[2],
+        ];
+        let r = &self.rotation;
+        // Inverse of orthonormal matrix is its transpose
+        [
+            r[0][0] * d[0] + r[1][0] * d[1] + r[2][0] * d[2],
+            r[0][1] * d[0] + r[1][1] * d[1] + r[2][1] * d[2],
+            r[0][2] * d[0] + r[1][2] * d[1] + r[2][2] * d[2],
+        ]
+    }
+}
+
+/// Brush shape placed in volume
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Brush {
+    pub shape: BrushShape,
+    pub transform: BrushTransform,
+}
+
+impl Brush {
+    pub fn new(shape: BrushShape, transform: BrushTransform) -> Self {
+        Brush { shape, transform }
+    }
+
+    /// Check whether voxel is affected by the brush. Voxel is affected when its center is
+    /// inside the brush shape.
+    pub fn covers(&self, x: i32, y: i32, z: i32) -> bool {
+        let center = [x as f32 + 0.5, y as f32 + 0.5, z as f32 + 0.5];
+        self.shape.contains(self.transform.local_point(center))
+    }
+
+    /// Axis aligned bounds of affected voxels in volume coordinates, both ends are inclusive
+    pub fn voxel_bounds(&self) -> ([i32; 3], [i32; 3]) {
+        let e = self.shape.half_extents();
+        let r = &self.transform.rotation;
+        let t = self.transform.translation;
+        let mut min = [0; 3];
+        let mut max = [0; 3];
+        for i in 0..3 {
+            let reach = r[i][0].abs() * e[0] + r[i][1].abs() * e[1] + r[i][2].abs() * e[2];
+            min[i] = (t[i] - reach - 0.5).floor() as i32;
+            max[i] = (t[i] + reach - 0.5).ceil() as i32;
+        }
+        (min, max)
+    }
+}
+
+/// What brush does with voxels it covers
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BrushOp {
+    /// Fill covered voxels with the color, existing voxels are recolored too
+    Add(RgbVoxel),
+    /// Clear covered voxels
+    Remove,
+    /// Recolor only existing covered voxels
+    Paint(RgbVoxel),
+}
+
+impl BrushOp {
+    fn apply(&self, voxel: RgbVoxel) -> RgbVoxel {
+        match self {
+            BrushOp::Add(color) => *color,
+            BrushOp::Remove => RgbVoxel::empty(),
+            BrushOp::Paint(color) => {
+                if voxel.is_empty() {
+                    voxel
+                } else {
+                    *color
+                }
+            }
+        }
+    }
+}
+
+impl RleVolume {
+    /// Bounds of voxels the brush can touch in layout of the volume
+    fn brush_layout_bounds(&self, brush: &Brush) -> ([i32; 3], [i32; 3]) {
+        let (min, max) = brush.voxel_bounds();
+        (self.axis.swap(min), self.axis.swap(max))
+    }
+
+    /// Check whether brush covers voxel given in layout of the volume
+    fn brush_covers(&self, brush: &Brush, p: [i32; 3]) -> bool {
+        let [x, y, z] = self.axis.swap(p);
+        brush.covers(x, y, z)
+    }
+
+    /// Indices (in pointers map) of columns that brush can touch, e.g. to record them in
+    /// edit journal before applying the brush.
+    pub fn brush_columns(&self, brush: &Brush) -> Vec<usize> {
+        let (min, max) = self.brush_layout_bounds(brush);
+        let mut columns = vec![];
+        if self.columns_count() == 0 {
+            return columns;
+        }
+        for z in min[2].max(0)..=max[2].min(self.zsize as i32 - 1) {
+            for x in min[0].max(0)..=max[0].min(self.xsize as i32 - 1) {
+                let covered = (min[1]..=max[1]).any(|y| self.brush_covers(brush, [x, y, z]));
+                if covered {
+                    columns.push(self.column_index(x as u32, z as u32));
+                }
+            }
+        }
+        columns
+    }
+
+    /// Apply brush operation to the volume. Returns sorted indices (in pointers map) of
+    /// columns that were actually changed, so only them need to be uploaded again.
+    pub fn apply_brush(&mut self, brush: &Brush, op: BrushOp) -> Vec<usize> {
+        let (min, max) = self.brush_layout_bounds(brush);
+        let clamp = |v: i32, size: u32| v.clamp(0, size as i32 - 1) as u32;
+        if self.columns_count() == 0
+            || self.ysize == 0
+            || max[0] < 0
+            || max[1] < 0
+            || max[2] < 0
+            || min[0] >= self.xsize as i32
+            || min[1] >= self.ysize as i32
+            || min[2] >= self.zsize as i32
+        {
+            return vec![];
+        }
+
+        let mut changed = vec![];
+        for z in clamp(min[2], self.zsize)..=clamp(max[2], self.zsize) {
+            for x in clamp(min[0], self.xsize)..=clamp(max[0], self.xsize) {
+                let index = self.column_index(x, z);
+                let mut voxels = self.column_at(index).decompress();
+                voxels.resize(self.ysize as usize, RgbVoxel::empty());
+                let mut dirty = false;
+                for y in clamp(min[1], self.ysize)..=clamp(max[1], self.ysize) {
+                    if self.brush_covers(brush, [x as i32, y as i32, z as i32]) {
+                        let voxel = &mut voxels[y as usize];
+                        let new_voxel = op.apply(*voxel);
+                        dirty |= new_voxel != *voxel;
+                        *voxel = new_voxel;
+                    }
+                }
+                if dirty {
+                    changed.push((index, RleColumn::compress(&voxels)));
+                }
+            }
+        }
+
+        let mut dirty: Vec<usize> = changed.iter().map(|(i, _)| *i).collect();
+        dirty.sort_unstable();
+        self.replace_columns(changed);
+        dirty
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::Array3;
+
+    fn filled(volume: &RleVolume) -> usize {
+        volume.to_array().iter().filter(|v| !v.is_empty()).count()
+    }
+
+    #[test]
+    fn add_sphere_test() {
+        let mut volume = RleVolume::empty(8, 8, 8);
+        let color = RgbVoxel::only_red(5);
+        let brush = Brush::new(
+            BrushShape::Sphere { radius: 1.0 },
+            BrushTransform::at([4.5, 4.5, 4.5]),
+        );
+        let dirty = volume.apply_brush(&brush, BrushOp::Add(color));
+        assert_eq!(filled(&volume), 7, "Sphere with radius 1 covers 7 voxels");
+        assert_eq!(volume.get(4, 4, 4), color, "Center voxel is added");
+        assert_eq!(volume.get(4, 5, 4), color, "Upper voxel is added");
+        assert_eq!(
+            dirty,
+            vec![
+                volume.column_index(4, 3),
+                volume.column_index(3, 4),
+                volume.column_index(4, 4),
+                volume.column_index(5, 4),
+                volume.column_index(4, 5),
+            ],
+            "Only touched columns are dirty"
+        );
+
+        let again = volume.apply_brush(&brush, BrushOp::Add(color));
+        assert!(again.is_empty(), "Nothing changes on repeated brush");
+    }
+
+    #[test]
+    fn remove_and_paint_test() {
+        let voxels = Array3::from_shape_fn((6, 6, 6), |(_, y, _)| {
+            if y < 3 {
+                RgbVoxel::only_green(1)
+            } else {
+                RgbVoxel::empty()
+            }
+        });
+        let mut volume: RleVolume = voxels.into();
+
+        let paint = RgbVoxel::only_blue(9);
+        let brush = Brush::new(
+            BrushShape::Cylinder {
+                radius: 1.0,
+                half_height: 10.0,
+            },
+            BrushTransform::at([2.5, 3.0, 2.5]),
+        );
+        let dirty = volume.apply_brush(&brush, BrushOp::Paint(paint));
+        assert_eq!(dirty.len(), 5, "Cylinder covers 5 columns");
+        assert_eq!(volume.get(2, 0, 2), paint, "Existing voxels are painted");
+        assert!(volume.get(2, 4, 2).is_empty(), "Empty voxels stay empty");
+        assert_eq!(filled(&volume), 6 * 6 * 3, "Painting keeps occupancy");
+
+        let brush = Brush::new(
+            BrushShape::Box {
+                half_extents: [1.0, 0.5, 1.0],
+            },
+            BrushTransform::at([1.0, 2.5, 1.0]),
+        );
+        volume.apply_brush(&brush, BrushOp::Remove);
+        assert_eq!(filled(&volume), 6 * 6 * 3 - 4, "Box removes top layer");
+        assert!(volume.get(0, 2, 0).is_empty(), "Removed voxel");
+        assert!(!volume.get(0, 1, 0).is_empty(), "Voxel below is kept");
+    }
+
+    #[test]
+    fn rotated_brush_test() {
+        let mut volume = RleVolume::empty(9, 9, 9);
+        // Cylinder that lies along X axis
+        let brush = Brush::new(
+            BrushShape::Cylinder {
+                radius: 0.5,
+                half_height: 3.0,
+            },
+            BrushTransform::at([4.5, 4.5, 4.5]).with_rotation([
+                [0.0, 1.0, 0.0],
+                [-1.0, 0.0, 0.0],
+                [0.0, 0.0, 1.0],
+            ]),
+        );
+        volume.apply_brush(&brush, BrushOp::Add(RgbVoxel::only_red(1)));
+        assert_eq!(filled(&volume), 7, "Cylinder of 7 voxels");
+        for x in 1..8 {
+            assert!(!volume.get(x, 4, 4).is_empty(), "Voxel {} along X", x);
+        }
+    }
+
+    #[test]
+    fn brush_along_axis_test() {
+        use crate::types::axis::RLE_AXES;
+        let brush = Brush::new(
+            BrushShape::Cylinder {
+                radius: 1.5,
+                half_height: 2.0,
+            },
+            BrushTransform::at([3.0, 2.5, 4.0]),
+        );
+        let mut expected = RleVolume::empty(6, 5, 7);
+        expected.apply_brush(&brush, BrushOp::Add(RgbVoxel::only_red(3)));
+        for axis in RLE_AXES {
+            let mut volume = RleVolume::from_array_with_axis(&Array3::zeros((6, 5, 7)), axis);
+            let columns = volume.brush_columns(&brush);
+            let dirty = volume.apply_brush(&brush, BrushOp::Add(RgbVoxel::only_red(3)));
+            assert_eq!(
+                volume.to_array(),
+                expected.to_array(),
+                "Brush along {:?}",
+                axis
+            );
+            assert!(
+                dirty.iter().all(|i| columns.contains(i)),
+                "Footprint covers dirty columns along {:?}",
+                axis
+            );
+        }
+    }
+
+    #[test]
+    fn brush_outside_volume() {
+        let mut volume = RleVolume::empty(4, 4, 4);
+        let brush = Brush::new(
+            BrushShape::Sphere { radius: 2.0 },
+            BrushTransform::at([-5.0, 0.0, 0.0]),
+        );
+        assert!(volume
+            .apply_brush(&brush, BrushOp::Add(RgbVoxel::only_red(1)))
+            .is_empty());
+    }
+}
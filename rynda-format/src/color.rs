@@ -0,0 +1,296 @@
+use super::types::voxel::RgbVoxel;
+use ndarray::Array3;
+
+/// Maximum values of 5-6-5 channels
+const CHANNEL_MAX: [u8; 3] = [31, 63, 31];
+
+/// Convert 8-bit sRGB encoded channel into linear light in range 0.0 .. 1.0
+pub fn srgb_to_linear(c: u8) -> f32 {
+    let c = c as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Convert linear light in range 0.0 .. 1.0 into 8-bit sRGB encoded channel
+pub fn linear_to_srgb(c: f32) -> u8 {
+    let c = c.clamp(0.0, 1.0);
+    let s = if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+    (s * 255.0).round() as u8
+}
+
+/// Round 8-bit channel to the nearest value of channel with given maximum
+fn quantize_channel(c: f32, max: u8) -> u8 {
+    (c.clamp(0.0, 255.0) * max as f32 / 255.0).round() as u8
+}
+
+/// Expand channel with given maximum to 8 bits
+fn expand_channel(c: u8, max: u8) -> u8 {
+    ((c as u16 * 255 + max as u16 / 2) / max as u16) as u8
+}
+
+impl RgbVoxel {
+    /// The darkest color that is still a drawn voxel, black is reserved for empty voxels
+    pub fn black() -> Self {
+        RgbVoxel::only_green(1)
+    }
+
+    /// Make voxel from 8-bit sRGB color with alpha. Channels are rounded to the nearest
+    /// 5-6-5 value. Fully transparent color is an empty voxel and opaque black becomes
+    /// `RgbVoxel::black`.
+    pub fn from_rgba8(rgba: [u8; 4]) -> Self {
+        quantize([rgba[0] as f32, rgba[1] as f32, rgba[2] as f32], rgba[3])
+    }
+
+    /// Expand voxel to 8-bit sRGB color with alpha, empty voxel is fully transparent
+    pub fn to_rgba8(&self) -> [u8; 4] {
+        if self.is_empty() {
+            return [0; 4];
+        }
+        [
+            expand_channel(self.red(), CHANNEL_MAX[0]),
+            expand_channel(self.green(), CHANNEL_MAX[1]),
+            expand_channel(self.blue(), CHANNEL_MAX[2]),
+            255,
+        ]
+    }
+
+    /// Color in linear light, channels are in range 0.0 .. 1.0
+    pub fn to_linear(&self) -> [f32; 3] {
+        let rgba = self.to_rgba8();
+        [
+            srgb_to_linear(rgba[0]),
+            srgb_to_linear(rgba[1]),
+            srgb_to_linear(rgba[2]),
+        ]
+    }
+
+    /// Make voxel from color in linear light
+    pub fn from_linear(color: [f32; 3]) -> Self {
+        RgbVoxel::from_rgba8([
+            linear_to_srgb(color[0]),
+            linear_to_srgb(color[1]),
+            linear_to_srgb(color[2]),
+            255,
+        ])
+    }
+
+    /// Interpolate between two colors in linear light, `t` = 0.0 gives this color and 1.0
+    /// gives the other one. Empty voxels are treated as black.
+    pub fn lerp(&self, other: RgbVoxel, t: f32) -> Self {
+        let a = self.to_linear();
+        let b = other.to_linear();
+        let t = t.clamp(0.0, 1.0);
+        RgbVoxel::from_linear([0, 1, 2].map(|i| a[i] + (b[i] - a[i]) * t))
+    }
+
+    /// Average colors in linear light with given weights, e.g. for downsampling. Empty
+    /// voxels are skipped, and the result is empty if all voxels are empty.
+    pub fn blend<I>(colors: I) -> Self
+    where
+        I: IntoIterator<Item = (RgbVoxel, f32)>,
+    {
+        let mut sum = [0.0f32; 3];
+        let mut total = 0.0;
+        for (color, weight) in colors {
+            if color.is_empty() || weight <= 0.0 {
+                continue;
+            }
+            let c = color.to_linear();
+            for i in 0..3 {
+                sum[i] += c[i] * weight;
+            }
+            total += weight;
+        }
+        if total == 0.0 {
+            RgbVoxel::empty()
+        } else {
+            RgbVoxel::from_linear(sum.map(|c| c / total))
+        }
+    }
+}
+
+/// Quantize 8-bit color with any precision into voxel
+fn quantize(rgb: [f32; 3], alpha: u8) -> RgbVoxel {
+    if alpha == 0 {
+        return RgbVoxel::empty();
+    }
+    let voxel = RgbVoxel::rgb(
+        quantize_channel(rgb[0], CHANNEL_MAX[0]),
+        quantize_channel(rgb[1], CHANNEL_MAX[1]),
+        quantize_channel(rgb[2], CHANNEL_MAX[2]),
+    );
+    if voxel.is_empty() {
+        RgbVoxel::black()
+    } else {
+        voxel
+    }
+}
+
+/// How to spread quantization error of 8-bit colors when they are converted to 5-6-5 voxels
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Dithering {
+    /// Round each voxel to the nearest color
+    #[default]
+    None,
+    /// Add threshold from 4x4 Bayer matrix that shifts from layer to layer along Y
+    Ordered,
+    /// Floyd-Steinberg error diffusion inside each XZ layer
+    ErrorDiffusion,
+}
+
+/// 4x4 Bayer matrix
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// Convert volume of 8-bit sRGB colors with alpha into voxels. Voxels with zero alpha are
+/// empty, other ones are quantized to 5-6-5 with given dithering.
+pub fn quantize_volume(colors: &Array3<[u8; 4]>, dithering: Dithering) -> Array3<RgbVoxel> {
+    let (xsize, ysize, zsize) = colors.dim();
+    match dithering {
+        Dithering::None => colors.map(|c| RgbVoxel::from_rgba8(*c)),
+        Dithering::Ordered => Array3::from_shape_fn((xsize, ysize, zsize), |(x, y, z)| {
+            let c = colors[(x, y, z)];
+            let threshold = (BAYER[(x + y) % 4][(z + 2 * y) % 4] as f32 + 0.5) / 16.0 - 0.5;
+            let rgb = [0, 1, 2].map(|i| {
+                // Step of the channel measured in 8-bit units
+                let step = 255.0 / CHANNEL_MAX[i] as f32;
+                c[i] as f32 + threshold * step
+            });
+            quantize(rgb, c[3])
+        }),
+        Dithering::ErrorDiffusion => {
+            let mut voxels = Array3::from_elem((xsize, ysize, zsize), RgbVoxel::empty());
+            let mut error = Array3::from_elem((xsize, zsize, 1), [0.0f32; 3]);
+            for y in 0..ysize {
+                error.fill([0.0; 3]);
+                for z in 0..zsize {
+                    for x in 0..xsize {
+                        let c = colors[(x, y, z)];
+                        if c[3] == 0 {
+                            continue;
+                        }
+                        let e = error[(x, z, 0)];
+                        let wanted = [0, 1, 2].map(|i| c[i] as f32 + e[i]);
+                        let voxel = quantize(wanted, c[3]);
+                        let got = voxel.to_rgba8();
+                        let diff = [0, 1, 2].map(|i| wanted[i] - got[i] as f32);
+                        voxels[(x, y, z)] = voxel;
+
+                        let mut spread = |dx: isize, dz: usize, weight: f32| {
+                            let nx = x as isize + dx;
+                            let nz = z + dz;
+                            if nx >= 0 && (nx as usize) < xsize && nz < zsize {
+                                let cell = &mut error[(nx as usize, nz, 0)];
+                                for i in 0..3 {
+                                    cell[i] += diff[i] * weight;
+                                }
+                            }
+                        };
+                        spread(1, 0, 7.0 / 16.0);
+                        spread(-1, 1, 3.0 / 16.0);
+                        spread(0, 1, 5.0 / 16.0);
+                        spread(1, 1, 1.0 / 16.0);
+                    }
+                }
+            }
+            voxels
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srgb_round_trip() {
+        for c in 0..=255u8 {
+            assert_eq!(linear_to_srgb(srgb_to_linear(c)), c, "Channel {}", c);
+        }
+        assert_eq!(srgb_to_linear(0), 0.0);
+        assert!((srgb_to_linear(255) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn rgba8_test() {
+        assert_eq!(
+            RgbVoxel::from_rgba8([255, 255, 255, 255]),
+            RgbVoxel::rgb(31, 63, 31)
+        );
+        assert_eq!(
+            RgbVoxel::from_rgba8([6, 3, 4, 255]),
+            RgbVoxel::rgb(1, 1, 0),
+            "Channels are rounded rather than truncated"
+        );
+        assert_eq!(RgbVoxel::from_rgba8([0, 0, 0, 255]), RgbVoxel::black());
+        assert!(RgbVoxel::from_rgba8([10, 20, 30, 0]).is_empty());
+
+        for v in [RgbVoxel::rgb(31, 63, 31), RgbVoxel::rgb(7, 40, 19)] {
+            assert_eq!(
+                RgbVoxel::from_rgba8(v.to_rgba8()),
+                v,
+                "Round trip of {:?}",
+                v
+            );
+        }
+        assert_eq!(RgbVoxel::empty().to_rgba8(), [0; 4]);
+    }
+
+    #[test]
+    fn lerp_and_blend_test() {
+        let white = RgbVoxel::rgb(31, 63, 31);
+        let red = RgbVoxel::only_red(31);
+        assert_eq!(red.lerp(white, 0.0), red);
+        assert_eq!(red.lerp(white, 1.0), white);
+        let mid = red.lerp(white, 0.5);
+        // Half of linear light is brighter than half of sRGB value
+        assert!(mid.green() > 63 / 2, "Interpolation is in linear light");
+
+        assert_eq!(
+            RgbVoxel::blend([(red, 1.0), (RgbVoxel::empty(), 5.0), (red, 2.0)]),
+            red,
+            "Empty voxels are skipped"
+        );
+        assert!(RgbVoxel::blend([(RgbVoxel::empty(), 1.0)]).is_empty());
+    }
+
+    #[test]
+    fn dithering_test() {
+        // Gray that lies between two 5-6-5 levels
+        let gray = [20u8, 20, 20, 255];
+        let colors = Array3::from_elem((8, 2, 8), gray);
+        let mean_red = |voxels: &Array3<RgbVoxel>| {
+            voxels.iter().map(|v| v.to_rgba8()[0] as f32).sum::<f32>() / voxels.len() as f32
+        };
+
+        let plain = quantize_volume(&colors, Dithering::None);
+        assert!(plain.iter().all(|v| *v == plain[(0, 0, 0)]), "No dithering");
+        for dithering in [Dithering::Ordered, Dithering::ErrorDiffusion] {
+            let dithered = quantize_volume(&colors, dithering);
+            assert!(
+                dithered.iter().any(|v| *v != dithered[(0, 0, 0)]),
+                "{:?} mixes levels",
+                dithering
+            );
+            assert!(
+                (mean_red(&dithered) - 20.0).abs() < (mean_red(&plain) - 20.0).abs(),
+                "{:?} keeps average color closer",
+                dithering
+            );
+        }
+
+        let mut holes = colors.clone();
+        holes[(3, 1, 3)] = [0; 4];
+        let dithered = quantize_volume(&holes, Dithering::ErrorDiffusion);
+        assert!(
+            dithered[(3, 1, 3)].is_empty(),
+            "Transparent voxels stay empty"
+        );
+    }
+}
@@ -0,0 +1,402 @@
+//! C API of the crate, enabled by the `ffi` feature.
+//!
+//! The header `include/rynda_format.h` is generated from this module by cbindgen during
+//! the build, and a test checks that the committed copy is up to date. Build a shared or
+//! static library for C and C++ tools with
+//! `cargo rustc --release --features ffi --crate-type cdylib` (or `staticlib`).
+//!
+//! Voxels are passed as packed 16-bit values: red in bits 0..5, green in bits 5..11 and
+//! blue in bits 11..16, zero is an empty voxel. Coordinates are model coordinates as in
+//! `RleVolume::get`.
+use super::{
+    file::{read_volume, volume_from_bytes, volume_to_bytes, write_volume, Compression},
+    types::{pointermap::PointerColumn, volume::RleVolume, voxel::RgbVoxel},
+};
+use std::{
+    ffi::{c_char, CStr},
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    ptr, slice,
+};
+
+/// Version of the C API, bumped when behaviour of existing functions changes
+pub const RYNDA_API_VERSION: u32 = 1;
+
+/// Size of a single entry of the pointers map in bytes
+pub const RYNDA_POINTER_COLUMN_SIZE: usize = 8;
+
+/// Result of fallible calls
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RyndaStatus {
+    Ok = 0,
+    /// Required pointer argument is null
+    NullArgument = 1,
+    /// Coordinates or sizes are outside of the volume or supported range
+    OutOfBounds = 2,
+    /// Bytes or file don't contain a valid volume, or path is not valid UTF-8
+    InvalidData = 3,
+    /// File can't be read or written
+    IoError = 4,
+}
+
+/// Volume owned by the caller, released with `rynda_volume_free`
+pub struct RyndaVolume(RleVolume);
+
+/// Bytes allocated by the library, released with `rynda_bytes_free`
+#[repr(C)]
+pub struct RyndaBytes {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+/// Largest size of volume along any axis plus one
+const SIZE_LIMIT: u32 = 1024;
+
+fn voxel_to_bits(voxel: RgbVoxel) -> u16 {
+    u16::from_le_bytes(voxel.into_bytes())
+}
+
+fn voxel_from_bits(bits: u16) -> RgbVoxel {
+    RgbVoxel::from_bytes(bits.to_le_bytes())
+}
+
+fn into_handle(volume: RleVolume) -> *mut RyndaVolume {
+    Box::into_raw(Box::new(RyndaVolume(volume)))
+}
+
+fn in_bounds(volume: &RleVolume, x: u32, y: u32, z: u32) -> bool {
+    let [xs, ys, zs] = volume.dimensions();
+    x < xs && y < ys && z < zs
+}
+
+/// Path from C string, `None` for null or non UTF-8 path
+unsafe fn path_arg<'a>(path: *const c_char) -> Option<&'a str> {
+    if path.is_null() {
+        return None;
+    }
+    CStr::from_ptr(path).to_str().ok()
+}
+
+/// Version of the C API the library implements
+#[no_mangle]
+pub extern "C" fn rynda_api_version() -> u32 {
+    RYNDA_API_VERSION
+}
+
+/// Make empty volume, returns null when any size is 1024 or more
+#[no_mangle]
+pub extern "C" fn rynda_volume_new(xsize: u32, ysize: u32, zsize: u32) -> *mut RyndaVolume {
+    if xsize >= SIZE_LIMIT || ysize >= SIZE_LIMIT || zsize >= SIZE_LIMIT {
+        return ptr::null_mut();
+    }
+    into_handle(RleVolume::empty(
+        xsize as usize,
+        ysize as usize,
+        zsize as usize,
+    ))
+}
+
+/// Copy the volume, returns null for null volume
+///
+/// # Safety
+/// `volume` is null or a valid volume.
+#[no_mangle]
+pub unsafe extern "C" fn rynda_volume_clone(volume: *const RyndaVolume) -> *mut RyndaVolume {
+    match volume.as_ref() {
+        Some(v) => into_handle(v.0.clone()),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Release the volume, null is ignored
+///
+/// # Safety
+/// `volume` is null or a valid volume that is not used after the call.
+#[no_mangle]
+pub unsafe extern "C" fn rynda_volume_free(volume: *mut RyndaVolume) {
+    if !volume.is_null() {
+        drop(Box::from_raw(volume));
+    }
+}
+
+/// Write sizes of the volume along model X, Y and Z into `sizes`
+///
+/// # Safety
+/// `volume` is null or a valid volume, `sizes` is null or points to 3 writable values.
+#[no_mangle]
+pub unsafe extern "C" fn rynda_volume_dimensions(
+    volume: *const RyndaVolume,
+    sizes: *mut u32,
+) -> RyndaStatus {
+    match volume.as_ref() {
+        Some(v) if !sizes.is_null() => {
+            slice::from_raw_parts_mut(sizes, 3).copy_from_slice(&v.0.dimensions());
+            RyndaStatus::Ok
+        }
+        _ => RyndaStatus::NullArgument,
+    }
+}
+
+/// Model axis that columns run along: 0 for X, 1 for Y and 2 for Z. Sizes of the pointers
+/// map and columns are given in the volume layout, where this axis is swapped with Y.
+/// Returns 255 for null volume.
+///
+/// # Safety
+/// `volume` is null or a valid volume.
+#[no_mangle]
+pub unsafe extern "C" fn rynda_volume_axis(volume: *const RyndaVolume) -> u8 {
+    volume.as_ref().map_or(u8::MAX, |v| v.0.axis.code())
+}
+
+/// Read voxel into `voxel`
+///
+/// # Safety
+/// `volume` is null or a valid volume, `voxel` is null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn rynda_volume_get(
+    volume: *const RyndaVolume,
+    x: u32,
+    y: u32,
+    z: u32,
+    voxel: *mut u16,
+) -> RyndaStatus {
+    match volume.as_ref() {
+        Some(v) if !voxel.is_null() => {
+            if !in_bounds(&v.0, x, y, z) {
+                return RyndaStatus::OutOfBounds;
+            }
+            *voxel = voxel_to_bits(v.0.get(x, y, z));
+            RyndaStatus::Ok
+        }
+        _ => RyndaStatus::NullArgument,
+    }
+}
+
+/// Change single voxel. The column is repacked, so prefer building volumes in Rust when
+/// many voxels change.
+///
+/// # Safety
+/// `volume` is null or a valid volume that is not accessed concurrently.
+#[no_mangle]
+pub unsafe extern "C" fn rynda_volume_set(
+    volume: *mut RyndaVolume,
+    x: u32,
+    y: u32,
+    z: u32,
+    voxel: u16,
+) -> RyndaStatus {
+    match volume.as_mut() {
+        Some(v) => {
+            if !in_bounds(&v.0, x, y, z) {
+                return RyndaStatus::OutOfBounds;
+            }
+            v.0.set(x, y, z, voxel_from_bits(voxel));
+            RyndaStatus::Ok
+        }
+        None => RyndaStatus::NullArgument,
+    }
+}
+
+/// Encode volume in the volume file format without compression. The bytes are released
+/// with `rynda_bytes_free`.
+///
+/// # Safety
+/// `volume` is null or a valid volume, `bytes` is null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn rynda_volume_to_bytes(
+    volume: *const RyndaVolume,
+    bytes: *mut RyndaBytes,
+) -> RyndaStatus {
+    match volume.as_ref() {
+        Some(v) if !bytes.is_null() => {
+            let data = volume_to_bytes(&v.0, Compression::None).into_boxed_slice();
+            let len = data.len();
+            *bytes = RyndaBytes {
+                data: Box::into_raw(data) as *mut u8,
+                len,
+            };
+            RyndaStatus::Ok
+        }
+        _ => RyndaStatus::NullArgument,
+    }
+}
+
+/// Release bytes made by the library
+///
+/// # Safety
+/// `bytes` is made by the library and is not used after the call.
+#[no_mangle]
+pub unsafe extern "C" fn rynda_bytes_free(bytes: RyndaBytes) {
+    if !bytes.data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+            bytes.data, bytes.len,
+        )));
+    }
+}
+
+/// Decode volume from bytes of the volume file format into `volume`
+///
+/// # Safety
+/// `data` points to `len` readable bytes, `volume` is null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn rynda_volume_from_bytes(
+    data: *const u8,
+    len: usize,
+    volume: *mut *mut RyndaVolume,
+) -> RyndaStatus {
+    if data.is_null() || volume.is_null() {
+        return RyndaStatus::NullArgument;
+    }
+    match volume_from_bytes(slice::from_raw_parts(data, len)) {
+        Ok(v) => {
+            *volume = into_handle(v);
+            RyndaStatus::Ok
+        }
+        Err(_) => RyndaStatus::InvalidData,
+    }
+}
+
+/// Load volume file into `volume`
+///
+/// # Safety
+/// `path` is null or a null terminated string, `volume` is null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn rynda_volume_load(
+    path: *const c_char,
+    volume: *mut *mut RyndaVolume,
+) -> RyndaStatus {
+    if path.is_null() || volume.is_null() {
+        return RyndaStatus::NullArgument;
+    }
+    let Some(path) = path_arg(path) else {
+        return RyndaStatus::InvalidData;
+    };
+    let Ok(file) = File::open(path) else {
+        return RyndaStatus::IoError;
+    };
+    match read_volume(&mut BufReader::new(file)) {
+        Ok(v) => {
+            *volume = into_handle(v);
+            RyndaStatus::Ok
+        }
+        Err(_) => RyndaStatus::InvalidData,
+    }
+}
+
+/// Save volume file without compression
+///
+/// # Safety
+/// `volume` is null or a valid volume, `path` is null or a null terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rynda_volume_save(
+    volume: *const RyndaVolume,
+    path: *const c_char,
+) -> RyndaStatus {
+    let Some(v) = volume.as_ref() else {
+        return RyndaStatus::NullArgument;
+    };
+    if path.is_null() {
+        return RyndaStatus::NullArgument;
+    }
+    let Some(path) = path_arg(path) else {
+        return RyndaStatus::InvalidData;
+    };
+    let Ok(file) = File::create(path) else {
+        return RyndaStatus::IoError;
+    };
+    let mut writer = BufWriter::new(file);
+    match write_volume(&mut writer, &v.0, Compression::None) {
+        Ok(()) if writer.flush().is_ok() => RyndaStatus::Ok,
+        _ => RyndaStatus::IoError,
+    }
+}
+
+/// Read-only pointers map of `count` entries of `RYNDA_POINTER_COLUMN_SIZE` bytes, indexed
+/// by `x + z * xsize` in the volume layout. Each entry is the offset of the column in the
+/// columns buffer (`uint32_t`), amount of ranges after the first one (`uint16_t`) and the
+/// first range (`uint16_t`, skipped voxels in bits 0..10 and drawn voxels in bits 10..16).
+/// The pointer is valid until the volume changes or is released, null for empty map.
+///
+/// # Safety
+/// `volume` is null or a valid volume, `count` is null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn rynda_volume_pointers(
+    volume: *const RyndaVolume,
+    count: *mut usize,
+) -> *const u8 {
+    let Some(v) = volume.as_ref() else {
+        return ptr::null();
+    };
+    let columns = v.0.columns_count();
+    if !count.is_null() {
+        *count = columns;
+    }
+    if columns == 0 {
+        ptr::null()
+    } else {
+        v.0.pointers as *const u8
+    }
+}
+
+/// Read-only columns buffer of `size` bytes. Each column holds ranges after the first
+/// one followed by packed colors of all drawn voxels. The pointer is valid until the
+/// volume changes or is released, null for empty buffer.
+///
+/// # Safety
+/// `volume` is null or a valid volume, `size` is null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn rynda_volume_columns(
+    volume: *const RyndaVolume,
+    size: *mut usize,
+) -> *const u8 {
+    let Some(v) = volume.as_ref() else {
+        return ptr::null();
+    };
+    if !size.is_null() {
+        *size = v.0.columns_size as usize;
+    }
+    if v.0.columns_size == 0 {
+        ptr::null()
+    } else {
+        v.0.columns as *const u8
+    }
+}
+
+// The header promises the layout of the pointers map
+const _: () = assert!(std::mem::size_of::<PointerColumn>() == RYNDA_POINTER_COLUMN_SIZE);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    extern "C" {
+        /// Test program in `ffi/test.c`, returns zero on success or number of the failed
+        /// check
+        fn rynda_ffi_c_test(path: *const c_char) -> i32;
+    }
+
+    #[test]
+    fn header_is_up_to_date() {
+        let generated = include_str!(concat!(env!("OUT_DIR"), "/rynda_format.h"));
+        let committed = include_str!("../include/rynda_format.h");
+        assert!(
+            generated == committed,
+            "Header is outdated, copy it from {}",
+            concat!(env!("OUT_DIR"), "/rynda_format.h")
+        );
+    }
+
+    #[test]
+    fn c_test() {
+        let path = std::env::temp_dir().join(format!("rynda-ffi-{}.rvol", std::process::id()));
+        let c_path = std::ffi::CString::new(path.to_str().unwrap()).unwrap();
+        let result = unsafe { rynda_ffi_c_test(c_path.as_ptr()) };
+        assert_eq!(result, 0, "C check {} failed", result);
+
+        // The file saved by C is readable from Rust
+        let volume = read_volume(&mut BufReader::new(File::open(&path).unwrap())).unwrap();
+        assert_eq!(volume.get(1, 2, 3), RgbVoxel::rgb(31, 0, 7));
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,517 @@
+use super::types::{
+    axis::RleAxis,
+    pointermap::PointerColumn,
+    range::RLE_RANGE_SIZE,
+    view::{RleVolumeView, ViewError},
+    volume::{RleVolume, BLOB_POINTER_SIZE},
+    voxel::RGB_VOXEL_SIZE,
+};
+use std::fmt;
+use std::io::{self, Read, Write};
+
+/// Magic bytes at the start of volume file
+const FILE_MAGIC: &[u8; 4] = b"RVOL";
+
+/// Version of volume file layout
+const FILE_VERSION: u8 = 1;
+
+/// Size of file header in bytes. Pointers map of mappable files starts right after it, so
+/// the size keeps the map aligned.
+pub const FILE_HEADER_SIZE: usize = 32;
+
+/// Payload is the compact blob made by `RleVolume::to_blob`
+const LAYOUT_BLOB: u8 = 0;
+
+/// Payload is the in-memory layout: pointers map followed by the columns buffer
+const LAYOUT_MAPPED: u8 = 1;
+
+/// Second stage compression of the volume blob in a file. Compression is chosen per file
+/// and volume is always decompressed into the normal in-memory layout on load.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Compression {
+    /// Blob is stored as is
+    #[default]
+    None,
+    /// Fast LZ4 block compression
+    #[cfg(feature = "lz4")]
+    Lz4,
+    /// Zstandard compression with given level, 1 is fast and 19 is the smallest
+    #[cfg(feature = "zstd")]
+    Zstd { level: i32 },
+}
+
+impl Compression {
+    fn code(&self) -> u8 {
+        match self {
+            Compression::None => 0,
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => 1,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd { .. } => 2,
+        }
+    }
+
+    /// Compression for decoding by the code from file header. Level of zstd doesn't matter
+    /// for decompression.
+    fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(Compression::None),
+            #[cfg(feature = "lz4")]
+            1 => Some(Compression::Lz4),
+            #[cfg(feature = "zstd")]
+            2 => Some(Compression::Zstd { level: 0 }),
+            _ => None,
+        }
+    }
+
+    fn compress(&self, data: Vec<u8>) -> Result<Vec<u8>, VolumeFileError> {
+        match self {
+            Compression::None => Ok(data),
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => Ok(lz4_flex::block::compress(&data)),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd { level } => Ok(zstd::bulk::compress(&data, *level)?),
+        }
+    }
+
+    fn decompress(&self, data: Vec<u8>, size: usize) -> Result<Vec<u8>, VolumeFileError> {
+        let blob = match self {
+            Compression::None => data,
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => lz4_flex::block::decompress(&data, size)
+                .map_err(|_| VolumeFileError::Corrupted("LZ4 stream is damaged"))?,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd { .. } => zstd::bulk::decompress(&data, size)
+                .map_err(|_| VolumeFileError::Corrupted("zstd stream is damaged"))?,
+        };
+        if blob.len() != size {
+            return Err(VolumeFileError::Corrupted("blob has unexpected size"));
+        }
+        Ok(blob)
+    }
+}
+
+/// Reasons why volume file cannot be written or read
+#[derive(Debug)]
+pub enum VolumeFileError {
+    Io(io::Error),
+    /// File doesn't start with the magic bytes
+    BadMagic,
+    /// File has unknown version
+    UnsupportedVersion(u8),
+    /// File is compressed with method that is unknown or disabled by cargo features
+    UnsupportedCompression(u8),
+    /// Contents of the file don't agree with its header
+    Corrupted(&'static str),
+    /// Mappable file cannot be viewed in place
+    View(ViewError),
+}
+
+impl fmt::Display for VolumeFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VolumeFileError::Io(e) => write!(f, "IO error: {}", e),
+            VolumeFileError::BadMagic => write!(f, "Not a volume file"),
+            VolumeFileError::UnsupportedVersion(v) => {
+                write!(f, "Unsupported volume file version {}", v)
+            }
+            VolumeFileError::UnsupportedCompression(c) => {
+                write!(f, "Unsupported compression method {}", c)
+            }
+            VolumeFileError::Corrupted(reason) => write!(f, "Volume file is corrupted: {}", reason),
+            VolumeFileError::View(e) => write!(f, "Volume file can't be mapped: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for VolumeFileError {}
+
+impl From<io::Error> for VolumeFileError {
+    fn from(e: io::Error) -> Self {
+        VolumeFileError::Io(e)
+    }
+}
+
+/// Header of volume file
+struct FileHeader {
+    axis: RleAxis,
+    compression: Compression,
+    layout: u8,
+    size: [u32; 3],
+    blob_size: u64,
+    payload_size: u32,
+}
+
+impl FileHeader {
+    fn to_bytes(&self) -> [u8; FILE_HEADER_SIZE] {
+        let mut header = Vec::with_capacity(FILE_HEADER_SIZE);
+        header.extend_from_slice(FILE_MAGIC);
+        header.extend_from_slice(&[
+            FILE_VERSION,
+            self.axis.code(),
+            self.compression.code(),
+            self.layout,
+        ]);
+        for s in self.size {
+            header.extend_from_slice(&s.to_le_bytes());
+        }
+        header.extend_from_slice(&self.blob_size.to_le_bytes());
+        header.extend_from_slice(&self.payload_size.to_le_bytes());
+        header.try_into().unwrap()
+    }
+
+    fn from_bytes(header: &[u8; FILE_HEADER_SIZE]) -> Result<Self, VolumeFileError> {
+        if &header[0..4] != FILE_MAGIC {
+            return Err(VolumeFileError::BadMagic);
+        }
+        if header[4] != FILE_VERSION {
+            return Err(VolumeFileError::UnsupportedVersion(header[4]));
+        }
+        let axis =
+            RleAxis::from_code(header[5]).ok_or(VolumeFileError::Corrupted("unknown axis"))?;
+        let compression = Compression::from_code(header[6])
+            .ok_or(VolumeFileError::UnsupportedCompression(header[6]))?;
+        let layout = header[7];
+        if layout != LAYOUT_BLOB && !(layout == LAYOUT_MAPPED && compression == Compression::None) {
+            return Err(VolumeFileError::Corrupted("unknown layout"));
+        }
+        let u32_at = |i: usize| u32::from_le_bytes(header[i..i + 4].try_into().unwrap());
+        Ok(FileHeader {
+            axis,
+            compression,
+            layout,
+            size: [u32_at(8), u32_at(12), u32_at(16)],
+            blob_size: u64::from_le_bytes(header[20..28].try_into().unwrap()),
+            payload_size: u32_at(28),
+        })
+    }
+}
+
+/// Write volume in the file format:
+///
+/// ```text
+/// magic "RVOL", version: u8, axis: u8, compression: u8, layout: u8,
+/// xsize, ysize, zsize: u32, blob size: u64, payload size: u32,
+/// payload: blob of `RleVolume::to_blob` compressed with the given method
+/// ```
+///
+/// All numbers are little endian.
+pub fn write_volume<W: Write>(
+    writer: &mut W,
+    volume: &RleVolume,
+    compression: Compression,
+) -> Result<(), VolumeFileError> {
+    let blob = volume.to_blob();
+    let blob_size = blob.len() as u64;
+    let payload = compression.compress(blob)?;
+    if payload.len() > u32::MAX as usize {
+        return Err(VolumeFileError::Corrupted("payload is too large"));
+    }
+    let header = FileHeader {
+        axis: volume.axis,
+        compression,
+        layout: LAYOUT_BLOB,
+        size: [volume.xsize, volume.ysize, volume.zsize],
+        blob_size,
+        payload_size: payload.len() as u32,
+    };
+    writer.write_all(&header.to_bytes())?;
+    writer.write_all(&payload)?;
+    Ok(())
+}
+
+/// Write volume in the uncompressed mappable layout, that can be viewed in place with
+/// `map_volume`. The header is the same as in `write_volume`, and the payload is the
+/// pointers map followed by the columns buffer exactly as they are in memory.
+pub fn write_mapped_volume<W: Write>(
+    writer: &mut W,
+    volume: &RleVolume,
+) -> Result<(), VolumeFileError> {
+    let view = volume.view();
+    let pointers = view.pointers_bytes();
+    let payload_size = pointers.len() + view.columns.len();
+    if payload_size > u32::MAX as usize {
+        return Err(VolumeFileError::Corrupted("payload is too large"));
+    }
+    let header = FileHeader {
+        axis: volume.axis,
+        compression: Compression::None,
+        layout: LAYOUT_MAPPED,
+        size: [volume.xsize, volume.ysize, volume.zsize],
+        blob_size: payload_size as u64,
+        payload_size: payload_size as u32,
+    };
+    writer.write_all(&header.to_bytes())?;
+    writer.write_all(pointers)?;
+    writer.write_all(view.columns)?;
+    Ok(())
+}
+
+/// View mappable volume file in place without copying, e.g. memory mapped one. The bytes
+/// must be aligned at least for `PointerColumn`, which page aligned mappings always are.
+pub fn map_volume(bytes: &[u8]) -> Result<RleVolumeView<'_>, VolumeFileError> {
+    if bytes.len() < FILE_HEADER_SIZE {
+        return Err(VolumeFileError::Corrupted("header is truncated"));
+    }
+    let header = FileHeader::from_bytes(bytes[..FILE_HEADER_SIZE].try_into().unwrap())?;
+    if header.layout != LAYOUT_MAPPED {
+        return Err(VolumeFileError::Corrupted("volume is not mappable"));
+    }
+    let payload = &bytes[FILE_HEADER_SIZE..];
+    if payload.len() != header.payload_size as usize {
+        return Err(VolumeFileError::Corrupted("payload is truncated"));
+    }
+    let [xsize, _, zsize] = header.size;
+    let pointers_size = (xsize as usize)
+        .saturating_mul(zsize as usize)
+        .saturating_mul(std::mem::size_of::<PointerColumn>());
+    if pointers_size > payload.len() {
+        return Err(VolumeFileError::Corrupted("pointers map is truncated"));
+    }
+    let (pointers, columns) = payload.split_at(pointers_size);
+    RleVolumeView::from_raw_parts(header.size, header.axis, pointers, columns)
+        .map_err(VolumeFileError::View)
+}
+
+/// Largest payload a volume with given layout size can have, where each column head takes
+/// `head_size` bytes. A column has at most one range and one color per voxel. Header that
+/// claims more is damaged, so it must not drive allocations.
+fn max_payload_size(size: [u32; 3], head_size: usize) -> Result<u64, VolumeFileError> {
+    let [xsize, ysize, zsize] = size;
+    if xsize >= 1024 || ysize >= 1024 || zsize >= 1024 {
+        return Err(VolumeFileError::Corrupted("volume is too large"));
+    }
+    let columns = xsize as u64 * zsize as u64;
+    let column_size = ysize as u64 * (RLE_RANGE_SIZE + RGB_VOXEL_SIZE) as u64;
+    Ok(columns * head_size as u64 + columns * column_size)
+}
+
+/// Largest size of blob compressed with any of supported methods, covers worst cases of
+/// both LZ4 and zstd
+fn max_compressed_size(blob_size: u64) -> u64 {
+    blob_size + blob_size / 128 + 64
+}
+
+/// Read volume written by `write_volume`
+pub fn read_volume<R: Read>(reader: &mut R) -> Result<RleVolume, VolumeFileError> {
+    let mut header_bytes = [0u8; FILE_HEADER_SIZE];
+    reader.read_exact(&mut header_bytes)?;
+    let header = FileHeader::from_bytes(&header_bytes)?;
+    let [xsize, ysize, zsize] = header.size;
+    let blob_size = header.blob_size;
+    let payload_size = header.payload_size as usize;
+
+    // Protect from huge allocations by damaged header, decompression preallocates the
+    // blob size
+    let max_payload = if header.layout == LAYOUT_MAPPED {
+        max_payload_size(header.size, std::mem::size_of::<PointerColumn>())?
+    } else {
+        if blob_size > max_payload_size(header.size, BLOB_POINTER_SIZE)? {
+            return Err(VolumeFileError::Corrupted("blob is too large"));
+        }
+        max_compressed_size(blob_size)
+    };
+    if payload_size as u64 > max_payload {
+        return Err(VolumeFileError::Corrupted("payload is too large"));
+    }
+    let mut payload = vec![];
+    reader.take(payload_size as u64).read_to_end(&mut payload)?;
+    if payload.len() != payload_size {
+        return Err(VolumeFileError::Corrupted("payload is truncated"));
+    }
+    if header.layout == LAYOUT_MAPPED {
+        // Copy into aligned buffer to view the in-memory layout
+        let mut aligned = vec![0u64; payload.len().div_ceil(8) + FILE_HEADER_SIZE / 8];
+        let bytes = unsafe {
+            std::slice::from_raw_parts_mut(aligned.as_mut_ptr() as *mut u8, aligned.len() * 8)
+        };
+        bytes[..FILE_HEADER_SIZE].copy_from_slice(&header_bytes);
+        bytes[FILE_HEADER_SIZE..FILE_HEADER_SIZE + payload.len()].copy_from_slice(&payload);
+        return Ok(map_volume(&bytes[..FILE_HEADER_SIZE + payload.len()])?.to_volume());
+    }
+    let blob = header.compression.decompress(payload, blob_size as usize)?;
+    let mut volume =
+        RleVolume::from_blob(xsize, ysize, zsize, &blob).map_err(VolumeFileError::Corrupted)?;
+    volume.axis = header.axis;
+    Ok(volume)
+}
+
+/// Encode volume into bytes of the file format
+pub fn volume_to_bytes(volume: &RleVolume, compression: Compression) -> Vec<u8> {
+    let mut bytes = vec![];
+    write_volume(&mut bytes, volume, compression).expect("Writing to memory doesn't fail");
+    bytes
+}
+
+/// Decode volume from bytes of the file format
+pub fn volume_from_bytes(mut bytes: &[u8]) -> Result<RleVolume, VolumeFileError> {
+    read_volume(&mut bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::voxel::RgbVoxel;
+    use ndarray::Array3;
+
+    fn test_volume() -> RleVolume {
+        let voxels = Array3::from_shape_fn((16, 20, 12), |(x, y, z)| {
+            if y < 4 + (x + z) % 7 {
+                RgbVoxel::rgb(10, (y % 64) as u8, 3)
+            } else {
+                RgbVoxel::empty()
+            }
+        });
+        RleVolume::from_array_with_axis(&voxels, RleAxis::Z)
+    }
+
+    fn check_round_trip(compression: Compression) -> usize {
+        let volume = test_volume();
+        let bytes = volume_to_bytes(&volume, compression);
+        let restored = volume_from_bytes(&bytes).unwrap();
+        assert_eq!(restored.axis, volume.axis, "Axis with {:?}", compression);
+        assert_eq!(
+            restored.to_array(),
+            volume.to_array(),
+            "Voxels with {:?}",
+            compression
+        );
+        assert!(
+            matches!(
+                volume_from_bytes(&bytes[..bytes.len() - 1]),
+                Err(VolumeFileError::Corrupted(_))
+            ),
+            "Truncated file with {:?}",
+            compression
+        );
+        bytes.len()
+    }
+
+    #[test]
+    fn uncompressed_round_trip() {
+        let size = check_round_trip(Compression::None);
+        let volume = test_volume();
+        assert_eq!(
+            size,
+            FILE_HEADER_SIZE + volume.to_blob().len(),
+            "Uncompressed file"
+        );
+        assert!(matches!(
+            volume_from_bytes(b"nope"),
+            Err(VolumeFileError::Io(_))
+        ));
+        let mut bytes = volume_to_bytes(&volume, Compression::None);
+        bytes[6] = 200;
+        assert!(matches!(
+            volume_from_bytes(&bytes),
+            Err(VolumeFileError::UnsupportedCompression(200))
+        ));
+    }
+
+    #[test]
+    fn mapped_round_trip() {
+        let volume = test_volume();
+        let mut bytes = vec![];
+        write_mapped_volume(&mut bytes, &volume).unwrap();
+
+        // Memory maps are page aligned, emulate that with aligned buffer
+        let mut buffer = vec![0u64; bytes.len().div_ceil(8)];
+        let mapped =
+            unsafe { std::slice::from_raw_parts_mut(buffer.as_mut_ptr() as *mut u8, bytes.len()) };
+        mapped.copy_from_slice(&bytes);
+        let view = map_volume(mapped).unwrap();
+        assert_eq!(view.axis, volume.axis);
+        assert_eq!(
+            view.columns.as_ptr(),
+            mapped[FILE_HEADER_SIZE..]
+                .as_ptr()
+                .wrapping_add(volume.columns_count() * std::mem::size_of::<PointerColumn>()),
+            "Columns are borrowed in place"
+        );
+        assert_eq!(view.to_volume().to_array(), volume.to_array());
+
+        let restored = volume_from_bytes(&bytes).unwrap();
+        assert_eq!(
+            restored.to_array(),
+            volume.to_array(),
+            "Mapped file is readable"
+        );
+        assert!(
+            map_volume(&volume_to_bytes(&volume, Compression::None)).is_err(),
+            "Blob file can't be mapped"
+        );
+    }
+
+    #[test]
+    fn oversized_header() {
+        let volume = test_volume();
+        let compressions = [
+            Compression::None,
+            #[cfg(feature = "lz4")]
+            Compression::Lz4,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd { level: 3 },
+        ];
+        for compression in compressions {
+            let bytes = volume_to_bytes(&volume, compression);
+            let mut damaged = bytes.clone();
+            damaged[20..28].copy_from_slice(&(1u64 << 31).to_le_bytes());
+            assert!(
+                matches!(
+                    volume_from_bytes(&damaged),
+                    Err(VolumeFileError::Corrupted("blob is too large"))
+                ),
+                "Huge blob with {:?}",
+                compression
+            );
+            let mut damaged = bytes.clone();
+            damaged[28..32].copy_from_slice(&u32::MAX.to_le_bytes());
+            assert!(
+                matches!(
+                    volume_from_bytes(&damaged),
+                    Err(VolumeFileError::Corrupted("payload is too large"))
+                ),
+                "Huge payload with {:?}",
+                compression
+            );
+        }
+
+        // Header alone that claims 2 GiB blob for tiny volume
+        let mut header = volume_to_bytes(&RleVolume::empty(2, 2, 2), Compression::None);
+        header[20..28].copy_from_slice(&(1u64 << 31).to_le_bytes());
+        header.extend_from_slice(&[0; 8]);
+        assert_eq!(
+            header.len(),
+            FILE_HEADER_SIZE + 8 + 2 * 2 * BLOB_POINTER_SIZE
+        );
+        assert!(matches!(
+            volume_from_bytes(&header),
+            Err(VolumeFileError::Corrupted("blob is too large"))
+        ));
+
+        let mut bytes = vec![];
+        write_mapped_volume(&mut bytes, &volume).unwrap();
+        bytes[28..32].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(
+            volume_from_bytes(&bytes),
+            Err(VolumeFileError::Corrupted("payload is too large"))
+        ));
+    }
+
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn lz4_round_trip() {
+        let size = check_round_trip(Compression::Lz4);
+        assert!(size < check_round_trip(Compression::None), "LZ4 is smaller");
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstd_round_trip() {
+        let size = check_round_trip(Compression::Zstd { level: 3 });
+        assert!(
+            size < check_round_trip(Compression::None),
+            "zstd is smaller"
+        );
+    }
+}
@@ -1,37 +1,413 @@
+use super::animation::{VoxelAnimation, DEFAULT_FRAME_DURATION};
+use super::color::{quantize_volume, Dithering};
+use super::types::attribute::AttributeStream;
+use super::types::material::{Material, MaterialTable, PALETTE_SIZE};
 use super::types::volume::RleVolume;
 use super::types::voxel::RgbVoxel;
-use dot_vox::{self, DotVoxData};
+use dot_vox::{self, DotVoxData, Model};
 use ndarray::Array3;
 
-/// Convert color (LE-encoded into i32) with 8-bit channels into 5-6-5-channel one.
-fn shakal(rgb: u32) -> RgbVoxel {
-    let red = ((rgb & 0xFF) as u8) >> 3;
-    let green = (((rgb >> 8) & 0xFF) as u8) >> 2;
-    let blue = (((rgb >> 16) & 0xFF) as u8) >> 3;
-    RgbVoxel::rgb(red, green, blue)
+/// Split color (LE-encoded into i32) into 8-bit channels. Voxels of the model are always
+/// drawn, so palette alpha is ignored.
+fn palette_rgba(rgb: u32) -> [u8; 4] {
+    let [red, green, blue, _] = rgb.to_le_bytes();
+    [red, green, blue, 255]
 }
 
 pub fn vox_to_rle_volume(filename: &str) -> Result<RleVolume, &str> {
     Ok(dot_vox::load(filename)?.into())
 }
 
+/// Load the first model of the file and quantize its colors with given dithering
+pub fn vox_to_rle_volume_dithered(filename: &str, dithering: Dithering) -> Result<RleVolume, &str> {
+    Ok(vox_data_to_rle_volume(&dot_vox::load(filename)?, dithering))
+}
+
+/// Convert the first model of the data into volume, colors are quantized with given
+/// dithering
+pub fn vox_data_to_rle_volume(data: &DotVoxData, dithering: Dithering) -> RleVolume {
+    let (colors, _) = model_arrays(data);
+    let space: Array3<RgbVoxel> = quantize_volume(&colors, dithering);
+    RleVolume::from(space)
+}
+
+/// Colors and palette indices of the first model in volume layout where Z of the file is up
+fn model_arrays(data: &DotVoxData) -> (Array3<[u8; 4]>, Array3<u8>) {
+    let size = &data.models[0].size;
+    model_arrays_sized(data, &data.models[0], [size.x, size.y, size.z])
+}
+
+/// Colors and palette indices of the model placed into volume of given size (in axes of
+/// the file), voxels outside of the size are dropped
+fn model_arrays_sized(
+    data: &DotVoxData,
+    model: &Model,
+    size: [u32; 3],
+) -> (Array3<[u8; 4]>, Array3<u8>) {
+    let [xsize, ysize, zsize] = size.map(|s| s as usize);
+    let mut colors = Array3::from_elem((xsize, zsize, ysize), [0u8; 4]);
+    let mut indices = Array3::zeros((xsize, zsize, ysize));
+
+    for voxel in model.voxels.iter() {
+        let pos = (voxel.x as usize, voxel.z as usize, voxel.y as usize);
+        if pos.0 >= xsize || pos.1 >= zsize || pos.2 >= ysize {
+            continue;
+        }
+        colors[pos] = palette_rgba(data.palette[voxel.i as usize]);
+        indices[pos] = voxel.i;
+    }
+    (colors, indices)
+}
+
+/// Volume with palette indices of its voxels and materials of the palette, as MagicaVoxel
+/// stores them
+pub struct VoxModel {
+    /// Voxel colors
+    pub volume: RleVolume,
+    /// Palette index of each drawn voxel, parallel to the columns buffer of the volume
+    pub palette_indices: AttributeStream<u8>,
+    /// Materials keyed by palette index
+    pub materials: MaterialTable,
+}
+
+/// Read MATL chunks into material table. Chunk ids are palette indices of the file that
+/// start from one, entries without a chunk are diffuse.
+pub fn vox_materials(data: &DotVoxData) -> MaterialTable {
+    let mut table = MaterialTable::default();
+    for material in data.materials.iter() {
+        let index = material.id as usize;
+        if (1..=PALETTE_SIZE).contains(&index) {
+            table.set(
+                (index - 1) as u8,
+                Material::from_vox_properties(&material.properties),
+            );
+        }
+    }
+    table
+}
+
+/// Convert the first model of the data with its palette indices and materials
+pub fn vox_data_to_model(data: &DotVoxData, dithering: Dithering) -> VoxModel {
+    let (colors, indices) = model_arrays(data);
+    let volume = RleVolume::from(quantize_volume(&colors, dithering));
+    let palette_indices = AttributeStream::from_fn(&volume, |x, y, z, _| {
+        indices[(x as usize, y as usize, z as usize)]
+    });
+    VoxModel {
+        volume,
+        palette_indices,
+        materials: vox_materials(data),
+    }
+}
+
+/// Load the first model of the file with its palette indices and materials
+pub fn vox_to_model(filename: &str, dithering: Dithering) -> Result<VoxModel, &str> {
+    Ok(vox_data_to_model(&dot_vox::load(filename)?, dithering))
+}
+
+/// Model of a shape node with its frame on the animation timeline
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ShapeFrame {
+    /// Index of the model in the file
+    pub model: u32,
+    /// Timeline frame from `_f` attribute of the model, or its position in the node
+    pub frame: u32,
+}
+
+/// Cursor over chunks of .vox file
+struct VoxReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> VoxReader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], &'static str> {
+        if self.bytes.len() < n {
+            return Err("Vox file is truncated");
+        }
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> Result<u32, &'static str> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<&'a str, &'static str> {
+        let len = self.u32()? as usize;
+        std::str::from_utf8(self.take(len)?).map_err(|_| "Vox string is not UTF-8")
+    }
+
+    /// Read dictionary and return value of given key
+    fn dict_value(&mut self, key: &str) -> Result<Option<&'a str>, &'static str> {
+        let mut value = None;
+        for _ in 0..self.u32()? {
+            let k = self.string()?;
+            let v = self.string()?;
+            if k == key {
+                value = Some(v);
+            }
+        }
+        Ok(value)
+    }
+}
+
+/// Find frames of animated shape in raw .vox file. The shape node with the most models
+/// is considered the animation, each of its models has `_f` frame attribute. Returns empty
+/// list for files without scene graph.
+pub fn vox_shape_frames(bytes: &[u8]) -> Result<Vec<ShapeFrame>, &'static str> {
+    let mut reader = VoxReader { bytes };
+    if reader.take(4)? != b"VOX " {
+        return Err("Not a vox file");
+    }
+    reader.u32()?;
+    // MAIN chunk has no content and all other chunks are its children
+    if reader.take(4)? != b"MAIN" {
+        return Err("Vox file has no main chunk");
+    }
+    let content = reader.u32()? as usize;
+    reader.u32()?;
+    reader.take(content)?;
+
+    let mut best: Vec<ShapeFrame> = vec![];
+    while !reader.bytes.is_empty() {
+        let id = reader.take(4)?;
+        let content = reader.u32()? as usize;
+        // Children follow the content, so the tree is read as flat list of chunks
+        reader.u32()?;
+        let mut chunk = VoxReader {
+            bytes: reader.take(content)?,
+        };
+        if id != b"nSHP" {
+            continue;
+        }
+        chunk.u32()?;
+        chunk.dict_value("")?;
+        let count = chunk.u32()?;
+        let mut frames = Vec::with_capacity(count.min(256) as usize);
+        for i in 0..count {
+            let model = chunk.u32()?;
+            let frame = match chunk.dict_value("_f")? {
+                Some(f) => f.trim().parse().map_err(|_| "Bad frame index")?,
+                None => i,
+            };
+            frames.push(ShapeFrame { model, frame });
+        }
+        if frames.len() > best.len() {
+            best = frames;
+        }
+    }
+    Ok(best)
+}
+
+/// Convert models of the data into animation. Each frame becomes a key frame, all of them
+/// get the size that fits every model. Without frames every model becomes a frame in the
+/// order of the file.
+pub fn vox_data_to_animation(
+    data: &DotVoxData,
+    frames: &[ShapeFrame],
+    dithering: Dithering,
+) -> Result<VoxelAnimation, &'static str> {
+    let frames: Vec<ShapeFrame> = if frames.is_empty() {
+        (0..data.models.len() as u32)
+            .map(|i| ShapeFrame { model: i, frame: i })
+            .collect()
+    } else {
+        frames.to_vec()
+    };
+    let mut size = [0u32; 3];
+    for frame in frames.iter() {
+        let model = data
+            .models
+            .get(frame.model as usize)
+            .ok_or("Shape refers to missing model")?;
+        size[0] = size[0].max(model.size.x);
+        size[1] = size[1].max(model.size.y);
+        size[2] = size[2].max(model.size.z);
+    }
+    let mut animation = VoxelAnimation::new(DEFAULT_FRAME_DURATION);
+    for frame in frames.iter() {
+        let (colors, _) = model_arrays_sized(data, &data.models[frame.model as usize], size);
+        let volume = RleVolume::from(quantize_volume(&colors, dithering));
+        animation.push(frame.frame, volume);
+    }
+    Ok(animation)
+}
+
+/// Load all frames of animated model
+pub fn vox_to_animation(filename: &str, dithering: Dithering) -> Result<VoxelAnimation, &str> {
+    let bytes = std::fs::read(filename).map_err(|_| "Can't read vox file")?;
+    let data = dot_vox::load_bytes(&bytes)?;
+    vox_data_to_animation(&data, &vox_shape_frames(&bytes)?, dithering)
+}
+
 impl From<DotVoxData> for RleVolume {
     fn from(data: DotVoxData) -> Self {
-        let model = &data.models[0];
-        let xsize = model.size.x as usize;
-        let ysize = model.size.y as usize;
-        let zsize = model.size.z as usize;
-        let mut space = Array3::from_shape_vec(
-            (xsize, zsize, ysize),
-            vec![RgbVoxel::empty(); xsize * ysize * zsize],
-        )
-        .unwrap();
+        vox_data_to_rle_volume(&data, Dithering::None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::material::MaterialKind;
+    use dot_vox::{Model, Size, Voxel};
+    use std::collections::HashMap;
 
-        for voxel in model.voxels.iter() {
-            space[(voxel.x as usize, voxel.z as usize, voxel.y as usize)] =
-                shakal(data.palette[voxel.i as usize]);
+    #[test]
+    fn vox_materials_test() {
+        let mut palette = vec![0xFF000000; PALETTE_SIZE];
+        palette[4] = 0xFF0000FF;
+        palette[9] = 0xFFFF0000;
+        let glass = HashMap::from([
+            ("_type".to_string(), "_glass".to_string()),
+            ("_trans".to_string(), "0.5".to_string()),
+        ]);
+        let data = DotVoxData {
+            version: 150,
+            models: vec![Model {
+                size: Size { x: 2, y: 1, z: 3 },
+                voxels: vec![
+                    Voxel {
+                        x: 0,
+                        y: 0,
+                        z: 0,
+                        i: 4,
+                    },
+                    Voxel {
+                        x: 1,
+                        y: 0,
+                        z: 2,
+                        i: 9,
+                    },
+                ],
+            }],
+            palette,
+            materials: vec![dot_vox::Material {
+                id: 10,
+                properties: glass,
+            }],
+        };
+        let model = vox_data_to_model(&data, Dithering::None);
+        assert_eq!(model.volume.dimensions(), [2, 3, 1], "Z of the file is up");
+        assert_eq!(model.volume.get(0, 0, 0), RgbVoxel::only_red(31));
+        assert!(model.palette_indices.matches(&model.volume));
+
+        let mut found = vec![];
+        for i in 0..model.volume.columns_count() {
+            for voxel in model.volume.drawn_voxels(i) {
+                found.push(model.palette_indices.values[voxel.slot]);
+            }
         }
+        found.sort();
+        assert_eq!(found, vec![4, 9], "Voxels keep palette indices");
+        assert_eq!(model.materials.get(9).kind, MaterialKind::Glass);
+        assert_eq!(model.materials.get(4).kind, MaterialKind::Diffuse);
+        assert!(model
+            .materials
+            .alpha_stream(&model.palette_indices)
+            .values
+            .iter()
+            .any(|a| a.alpha == 128));
+    }
+
+    /// Append chunk to the children of MAIN chunk of serialised file
+    fn append_chunk(file: &mut Vec<u8>, id: &[u8; 4], content: &[u8]) {
+        file.extend_from_slice(id);
+        file.extend_from_slice(&(content.len() as u32).to_le_bytes());
+        file.extend_from_slice(&0u32.to_le_bytes());
+        file.extend_from_slice(content);
+        // MAIN header: id at 8, content size at 12, children size at 16
+        let children = (file.len() - 20) as u32;
+        file[16..20].copy_from_slice(&children.to_le_bytes());
+    }
+
+    fn vox_string(out: &mut Vec<u8>, s: &str) {
+        out.extend_from_slice(&(s.len() as u32).to_le_bytes());
+        out.extend_from_slice(s.as_bytes());
+    }
 
-        RleVolume::from(space)
+    #[test]
+    fn vox_animation_test() {
+        let model = |x: u8, size: u32| Model {
+            size: Size {
+                x: size,
+                y: 2,
+                z: 2,
+            },
+            voxels: vec![Voxel {
+                x,
+                y: 1,
+                z: 0,
+                i: 4,
+            }],
+        };
+        let mut file = b"VOX ".to_vec();
+        file.extend_from_slice(&150u32.to_le_bytes());
+        file.extend_from_slice(b"MAIN");
+        file.extend_from_slice(&[0; 8]);
+        for m in [model(0, 4), model(2, 4), model(4, 5)] {
+            let size = [m.size.x, m.size.y, m.size.z];
+            append_chunk(&mut file, b"SIZE", &size.map(u32::to_le_bytes).concat());
+            let mut xyzi = (m.voxels.len() as u32).to_le_bytes().to_vec();
+            for v in m.voxels {
+                xyzi.extend_from_slice(&[v.x, v.y, v.z, v.i + 1]);
+            }
+            append_chunk(&mut file, b"XYZI", &xyzi);
+        }
+        append_chunk(&mut file, b"RGBA", &[255, 0, 0, 255].repeat(PALETTE_SIZE));
+        assert!(
+            vox_shape_frames(&file).unwrap().is_empty(),
+            "No scene graph"
+        );
+
+        // Shape node 3 without attributes that shows models 2, 0, 1 at frames 0, 3, 5
+        let mut shape = vec![];
+        shape.extend_from_slice(&3u32.to_le_bytes());
+        shape.extend_from_slice(&0u32.to_le_bytes());
+        shape.extend_from_slice(&3u32.to_le_bytes());
+        for (model, frame) in [(2u32, "0"), (0, "3"), (1, "5")] {
+            shape.extend_from_slice(&model.to_le_bytes());
+            shape.extend_from_slice(&1u32.to_le_bytes());
+            vox_string(&mut shape, "_f");
+            vox_string(&mut shape, frame);
+        }
+        append_chunk(&mut file, b"nSHP", &shape);
+        let frames = vox_shape_frames(&file).unwrap();
+        assert_eq!(
+            frames,
+            vec![
+                ShapeFrame { model: 2, frame: 0 },
+                ShapeFrame { model: 0, frame: 3 },
+                ShapeFrame { model: 1, frame: 5 },
+            ]
+        );
+
+        let data = dot_vox::load_bytes(&file).unwrap();
+        let animation = vox_data_to_animation(&data, &frames, Dithering::None).unwrap();
+        assert_eq!(animation.len(), 3);
+        assert_eq!(animation.timeline_len(), 6);
+        for frame in animation.frames.iter() {
+            assert_eq!(frame.volume.dimensions(), [5, 2, 2], "Frames share size");
+        }
+        let red = RgbVoxel::only_red(31);
+        assert_eq!(animation.frames[0].volume.get(4, 0, 1), red);
+        assert_eq!(animation.frames[1].volume.get(0, 0, 1), red);
+        assert_eq!(animation.frames[2].volume.get(2, 0, 1), red);
+
+        let deltas = animation.to_deltas().unwrap();
+        assert_eq!(
+            deltas.frame(2).unwrap().to_array(),
+            animation.frames[2].volume.to_array()
+        );
+
+        let fallback = vox_data_to_animation(&data, &[], Dithering::None).unwrap();
+        assert_eq!(fallback.len(), 3, "Every model is a frame");
+        assert!(vox_data_to_animation(
+            &data,
+            &[ShapeFrame { model: 9, frame: 0 }],
+            Dithering::None
+        )
+        .is_err());
     }
 }
@@ -0,0 +1,239 @@
+//! Per column summaries of a volume that are read straight from the RLE ranges without
+//! unpacking columns: solid spans, top height and color of the top voxel.
+//!
+//! Columns run along the RLE axis of the volume, so for the default `RleAxis::Y` the
+//! summaries describe the height of the model over its XZ plane. Coordinates are given in
+//! the layout of the volume, as for `RleVolume::column_index`.
+use super::types::{
+    range::{RleRange, RLE_RANGE_SIZE},
+    volume::RleVolume,
+    voxel::{RgbVoxel, RGB_VOXEL_SIZE},
+};
+use ndarray::Array2;
+use std::ops::Range;
+
+impl RleVolume {
+    /// Ranges of the column by its index, including the first range
+    fn column_ranges(&self, index: usize) -> impl Iterator<Item = RleRange> + '_ {
+        let pcol = self.pointer_column(index);
+        let count = pcol.rle_count as usize;
+        let bytes = &self.column_bytes(index)[..count * RLE_RANGE_SIZE];
+        std::iter::once(pcol.first_range).chain(
+            bytes
+                .chunks_exact(RLE_RANGE_SIZE)
+                .map(|r| RleRange::from_bytes([r[0], r[1]])),
+        )
+    }
+
+    /// Spans of consecutive drawn voxels of the column, sorted from the bottom. Adjacent
+    /// ranges are merged, so spans are separated by at least one empty voxel.
+    pub fn column_spans(&self, x: u32, z: u32) -> Vec<Range<u16>> {
+        let mut spans: Vec<Range<u16>> = vec![];
+        let mut y = 0u16;
+        for range in self.column_ranges(self.column_index(x, z)) {
+            y += range.skipped();
+            let end = y + range.drawn() as u16;
+            if range.drawn() > 0 {
+                match spans.last_mut() {
+                    Some(last) if last.end == y => last.end = end,
+                    _ => spans.push(y..end),
+                }
+            }
+            y = end;
+        }
+        spans
+    }
+
+    /// Height of the topmost drawn voxel of the column and amount of drawn voxels below
+    /// and including it, `None` for empty column
+    fn column_top(&self, index: usize) -> Option<(u16, usize)> {
+        let mut y = 0u16;
+        let mut drawn = 0;
+        let mut top = None;
+        for range in self.column_ranges(index) {
+            y += range.skipped() + range.drawn() as u16;
+            drawn += range.drawn() as usize;
+            if range.drawn() > 0 {
+                top = Some((y - 1, drawn));
+            }
+        }
+        top
+    }
+
+    /// Height of the topmost drawn voxel of the column, `None` for empty column
+    pub fn top_height(&self, x: u32, z: u32) -> Option<u16> {
+        self.column_top(self.column_index(x, z)).map(|(y, _)| y)
+    }
+
+    /// Color of the topmost drawn voxel of the column, empty voxel for empty column
+    pub fn top_color(&self, x: u32, z: u32) -> RgbVoxel {
+        let index = self.column_index(x, z);
+        match self.column_top(index) {
+            None => RgbVoxel::empty(),
+            Some((_, drawn)) => {
+                let pcol = self.pointer_column(index);
+                let offset =
+                    pcol.rle_count as usize * RLE_RANGE_SIZE + (drawn - 1) * RGB_VOXEL_SIZE;
+                let bytes = self.column_bytes(index);
+                RgbVoxel::from_bytes([bytes[offset], bytes[offset + 1]])
+            }
+        }
+    }
+
+    /// Heights of all columns indexed by X and Z. Height is one above the topmost drawn
+    /// voxel, so empty columns have zero height.
+    pub fn heightmap(&self) -> Array2<u16> {
+        Array2::from_shape_fn((self.xsize as usize, self.zsize as usize), |(x, z)| {
+            self.top_height(x as u32, z as u32).map_or(0, |y| y + 1)
+        })
+    }
+
+    /// Colors of the topmost voxels of all columns indexed by X and Z, e.g. for minimaps
+    pub fn surface_colormap(&self) -> Array2<RgbVoxel> {
+        Array2::from_shape_fn((self.xsize as usize, self.zsize as usize), |(x, z)| {
+            self.top_color(x as u32, z as u32)
+        })
+    }
+}
+
+/// Export of column summaries as PNG images. X goes to the right and Z goes down.
+#[cfg(feature = "png")]
+pub mod image {
+    use super::*;
+    use std::io::Write;
+
+    /// Write heightmap as 16-bit grayscale image
+    pub fn write_heightmap_png<W: Write>(
+        writer: W,
+        heights: &Array2<u16>,
+    ) -> Result<(), png::EncodingError> {
+        let (width, height) = heights.dim();
+        let mut data = Vec::with_capacity(width * height * 2);
+        for z in 0..height {
+            for x in 0..width {
+                data.extend_from_slice(&heights[(x, z)].to_be_bytes());
+            }
+        }
+        write_png(
+            writer,
+            width,
+            height,
+            png::ColorType::Grayscale,
+            png::BitDepth::Sixteen,
+            &data,
+        )
+    }
+
+    /// Write colormap as 8-bit RGBA image, empty voxels are transparent
+    pub fn write_colormap_png<W: Write>(
+        writer: W,
+        colors: &Array2<RgbVoxel>,
+    ) -> Result<(), png::EncodingError> {
+        let (width, height) = colors.dim();
+        let mut data = Vec::with_capacity(width * height * 4);
+        for z in 0..height {
+            for x in 0..width {
+                data.extend_from_slice(&colors[(x, z)].to_rgba8());
+            }
+        }
+        write_png(
+            writer,
+            width,
+            height,
+            png::ColorType::Rgba,
+            png::BitDepth::Eight,
+            &data,
+        )
+    }
+
+    fn write_png<W: Write>(
+        writer: W,
+        width: usize,
+        height: usize,
+        color: png::ColorType,
+        depth: png::BitDepth,
+        data: &[u8],
+    ) -> Result<(), png::EncodingError> {
+        let mut encoder = png::Encoder::new(writer, width as u32, height as u32);
+        encoder.set_color(color);
+        encoder.set_depth(depth);
+        encoder.write_header()?.write_image_data(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::Array3;
+
+    fn test_volume() -> RleVolume {
+        // Column (x, z) has ground of height x + z, floating voxels at 10 and 11, top at 20
+        // when x is odd and 100 voxels of height to test ranges longer than 63
+        let voxels = Array3::from_shape_fn((4, 120, 3), |(x, y, z)| {
+            let filled = y < x + z
+                || y == 10
+                || y == 11
+                || (x % 2 == 1 && y == 20)
+                || (x == 3 && z == 2 && y < 100);
+            if filled {
+                RgbVoxel::rgb(x as u8, y as u8 % 64, z as u8 + 1)
+            } else {
+                RgbVoxel::empty()
+            }
+        });
+        voxels.into()
+    }
+
+    #[test]
+    fn column_queries_test() {
+        let volume = test_volume();
+        assert_eq!(volume.column_spans(0, 0), vec![10..12]);
+        assert_eq!(volume.column_spans(1, 2), vec![0..3, 10..12, 20..21]);
+        assert_eq!(
+            volume.column_spans(3, 2),
+            vec![0..100],
+            "Long ranges are merged"
+        );
+        assert_eq!(volume.top_height(2, 1), Some(11));
+        assert_eq!(volume.top_height(1, 1), Some(20));
+        assert_eq!(volume.top_color(1, 1), RgbVoxel::rgb(1, 20, 2));
+        assert_eq!(volume.top_color(3, 2), RgbVoxel::rgb(3, 99 % 64, 3));
+
+        let empty = RleVolume::empty(2, 5, 2);
+        assert_eq!(empty.top_height(1, 1), None);
+        assert!(empty.top_color(1, 1).is_empty());
+        assert_eq!(empty.column_spans(0, 1), vec![]);
+
+        let heights = volume.heightmap();
+        let colors = volume.surface_colormap();
+        let voxels = volume.to_array();
+        for x in 0..4 {
+            for z in 0..3 {
+                let top = (0..120).rev().find(|y| !voxels[(x, *y, z)].is_empty());
+                assert_eq!(heights[(x, z)], top.map_or(0, |y| y as u16 + 1));
+                assert_eq!(colors[(x, z)], voxels[(x, top.unwrap(), z)]);
+            }
+        }
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn png_export_test() {
+        let volume = test_volume();
+        let mut bytes = vec![];
+        image::write_heightmap_png(&mut bytes, &volume.heightmap()).unwrap();
+        let decoder = png::Decoder::new(bytes.as_slice());
+        let mut reader = decoder.read_info().unwrap();
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data).unwrap();
+        assert_eq!((info.width, info.height), (4, 3));
+        assert_eq!(info.bit_depth, png::BitDepth::Sixteen);
+        // Pixel at x = 3, z = 2
+        let pixel = (2 * 4 + 3) * 2;
+        assert_eq!(u16::from_be_bytes([data[pixel], data[pixel + 1]]), 100);
+
+        let mut bytes = vec![];
+        image::write_colormap_png(&mut bytes, &volume.surface_colormap()).unwrap();
+        assert_eq!(&bytes[1..4], b"PNG");
+    }
+}
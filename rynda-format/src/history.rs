@@ -0,0 +1,440 @@
+use super::types::{
+    column::RleColumn,
+    range::RleRange,
+    volume::{PackedColumn, RleVolume},
+};
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+
+/// Collection of volumes that can be edited under the journal, e.g. single volume or
+/// chunks of a model.
+pub trait VolumeStore {
+    /// Identifies volume inside the store
+    type Key: Clone + Eq + Hash;
+
+    /// Get volume by key, `None` if there is no such volume (yet)
+    fn volume(&self, key: &Self::Key) -> Option<&RleVolume>;
+
+    /// Get mutable volume by key
+    fn volume_mut(&mut self, key: &Self::Key) -> Option<&mut RleVolume>;
+}
+
+impl VolumeStore for RleVolume {
+    type Key = ();
+
+    fn volume(&self, _: &()) -> Option<&RleVolume> {
+        Some(self)
+    }
+
+    fn volume_mut(&mut self, _: &()) -> Option<&mut RleVolume> {
+        Some(self)
+    }
+}
+
+/// Change of a single column of a volume
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ColumnChange<K> {
+    /// Volume that contains the column
+    pub key: K,
+    /// Index of the column in the pointers map
+    pub index: usize,
+    /// Packed column before the edit, `None` if the volume didn't exist before the edit
+    pub before: Option<PackedColumn>,
+    /// Packed column after the edit
+    pub after: PackedColumn,
+}
+
+impl<K> ColumnChange<K> {
+    fn memory_size(&self) -> usize {
+        self.before.as_ref().map_or(0, |c| c.bytes.len()) + self.after.bytes.len()
+    }
+}
+
+/// Single undoable step that consists of changed columns
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EditStep<K> {
+    pub changes: Vec<ColumnChange<K>>,
+}
+
+impl<K: Clone + Eq + Hash> EditStep<K> {
+    /// Approximate amount of memory the step keeps in bytes
+    pub fn memory_size(&self) -> usize {
+        self.changes.iter().map(|c| c.memory_size()).sum()
+    }
+
+    /// Append later step to this one, keeping the earliest state before and the latest
+    /// state after for each column.
+    fn merge(&mut self, later: EditStep<K>) {
+        for change in later.changes {
+            match self
+                .changes
+                .iter_mut()
+                .find(|c| c.key == change.key && c.index == change.index)
+            {
+                Some(existing) => existing.after = change.after,
+                None => self.changes.push(change),
+            }
+        }
+        self.changes.retain(|c| c.before.as_ref() != Some(&c.after));
+    }
+}
+
+/// Configuration of the edit journal
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct JournalSettings {
+    /// Maximum amount of bytes kept by undo and redo steps. Oldest steps are forgotten
+    /// when the budget is exceeded.
+    pub memory_budget: usize,
+    /// Consecutive steps are merged into one while the merged step stays under that amount
+    /// of bytes. Zero disables merging.
+    pub merge_limit: usize,
+}
+
+impl Default for JournalSettings {
+    fn default() -> Self {
+        JournalSettings {
+            memory_budget: 64 * 1024 * 1024,
+            merge_limit: 0,
+        }
+    }
+}
+
+/// Columns that are recorded between `begin` and `commit`
+struct PendingStep<K> {
+    columns: Vec<(K, usize, Option<PackedColumn>)>,
+}
+
+/// Undo and redo history of voxel edits. Journal keeps packed columns touched by each edit
+/// before and after it:
+///
+/// ```
+/// # use rynda_format::{history::EditJournal, types::{volume::RleVolume, voxel::RgbVoxel}};
+/// # use rynda_format::brush::{Brush, BrushOp, BrushShape, BrushTransform};
+/// let mut volume = RleVolume::empty(8, 8, 8);
+/// let mut journal = EditJournal::default();
+/// let brush = Brush::new(BrushShape::Sphere { radius: 2.0 }, BrushTransform::at([4.0; 3]));
+///
+/// let columns: Vec<_> = volume.brush_columns(&brush).into_iter().map(|i| ((), i)).collect();
+/// journal.begin(&volume, &columns);
+/// volume.apply_brush(&brush, BrushOp::Add(RgbVoxel::only_red(1)));
+/// journal.commit(&volume);
+///
+/// journal.undo(&mut volume);
+/// assert!(volume.get(4, 4, 4).is_empty());
+/// ```
+pub struct EditJournal<K> {
+    settings: JournalSettings,
+    undo_steps: VecDeque<EditStep<K>>,
+    redo_steps: Vec<EditStep<K>>,
+    pending: Option<PendingStep<K>>,
+    /// Whether the last undo step can absorb the next one
+    mergeable: bool,
+}
+
+impl<K: Clone + Eq + Hash> Default for EditJournal<K> {
+    fn default() -> Self {
+        EditJournal::new(JournalSettings::default())
+    }
+}
+
+impl<K: Clone + Eq + Hash> EditJournal<K> {
+    pub fn new(settings: JournalSettings) -> Self {
+        EditJournal {
+            settings,
+            undo_steps: VecDeque::new(),
+            redo_steps: vec![],
+            pending: None,
+            mergeable: false,
+        }
+    }
+
+    /// Remember state of columns that are going to be edited. Columns of volumes that don't
+    /// exist yet are allowed, undo clears them.
+    pub fn begin<S>(&mut self, store: &S, columns: &[(K, usize)])
+    where
+        S: VolumeStore<Key = K>,
+    {
+        self.pending = Some(PendingStep {
+            columns: columns
+                .iter()
+                .map(|(key, index)| {
+                    let before = store.volume(key).map(|v| v.packed_column(*index));
+                    (key.clone(), *index, before)
+                })
+                .collect(),
+        });
+    }
+
+    /// Finish the edit started with `begin` and record it as undoable step. Columns that
+    /// are not changed are not recorded. Recording a new step clears redo history.
+    pub fn commit<S>(&mut self, store: &S)
+    where
+        S: VolumeStore<Key = K>,
+    {
+        let pending = match self.pending.take() {
+            None => return,
+            Some(p) => p,
+        };
+        let changes: Vec<ColumnChange<K>> = pending
+            .columns
+            .into_iter()
+            .filter_map(|(key, index, before)| {
+                let after = store.volume(&key)?.packed_column(index);
+                if before.as_ref() == Some(&after) {
+                    None
+                } else {
+                    Some(ColumnChange {
+                        key,
+                        index,
+                        before,
+                        after,
+                    })
+                }
+            })
+            .collect();
+        if changes.is_empty() {
+            return;
+        }
+        self.redo_steps.clear();
+
+        let step = EditStep { changes };
+        let merge_limit = self.settings.merge_limit;
+        match self.undo_steps.back_mut() {
+            Some(last)
+                if self.mergeable && last.memory_size() + step.memory_size() <= merge_limit =>
+            {
+                last.merge(step);
+            }
+            _ => {
+                self.mergeable = step.memory_size() <= merge_limit;
+                self.undo_steps.push_back(step);
+            }
+        }
+        self.enforce_budget();
+    }
+
+    /// Record edit made by the function for the given columns
+    pub fn record<S, F, R>(&mut self, store: &mut S, columns: &[(K, usize)], edit: F) -> R
+    where
+        S: VolumeStore<Key = K>,
+        F: FnOnce(&mut S) -> R,
+    {
+        self.begin(store, columns);
+        let result = edit(store);
+        self.commit(store);
+        result
+    }
+
+    /// Stop merging following edits into the last step
+    pub fn seal(&mut self) {
+        self.mergeable = false;
+    }
+
+    /// Revert the last step, returns `false` if there is nothing to undo
+    pub fn undo<S>(&mut self, store: &mut S) -> bool
+    where
+        S: VolumeStore<Key = K>,
+    {
+        match self.undo_steps.pop_back() {
+            None => false,
+            Some(step) => {
+                apply_step(store, &step, true);
+                self.redo_steps.push(step);
+                self.mergeable = false;
+                true
+            }
+        }
+    }
+
+    /// Repeat the last undone step, returns `false` if there is nothing to redo
+    pub fn redo<S>(&mut self, store: &mut S) -> bool
+    where
+        S: VolumeStore<Key = K>,
+    {
+        match self.redo_steps.pop() {
+            None => false,
+            Some(step) => {
+                apply_step(store, &step, false);
+                self.undo_steps.push_back(step);
+                self.mergeable = false;
+                true
+            }
+        }
+    }
+
+    /// Amount of steps that can be undone
+    pub fn undo_len(&self) -> usize {
+        self.undo_steps.len()
+    }
+
+    /// Amount of steps that can be redone
+    pub fn redo_len(&self) -> usize {
+        self.redo_steps.len()
+    }
+
+    /// Amount of bytes kept by the journal
+    pub fn memory_size(&self) -> usize {
+        self.undo_steps
+            .iter()
+            .chain(self.redo_steps.iter())
+            .map(|s| s.memory_size())
+            .sum()
+    }
+
+    /// Forget all history
+    pub fn clear(&mut self) {
+        self.undo_steps.clear();
+        self.redo_steps.clear();
+        self.pending = None;
+        self.mergeable = false;
+    }
+
+    fn enforce_budget(&mut self) {
+        while self.memory_size() > self.settings.memory_budget && !self.undo_steps.is_empty() {
+            self.undo_steps.pop_front();
+        }
+    }
+}
+
+/// Write columns of the step into the store
+fn apply_step<S: VolumeStore>(store: &mut S, step: &EditStep<S::Key>, backward: bool) {
+    let mut per_volume: HashMap<S::Key, Vec<(usize, RleColumn)>> = HashMap::new();
+    for change in step.changes.iter() {
+        let column = if backward {
+            match &change.before {
+                Some(c) => c.unpack(),
+                None => {
+                    // Volume didn't exist before the edit, leave empty column there
+                    let ysize = store.volume(&change.key).map_or(0, |v| v.ysize);
+                    RleColumn {
+                        ranges: vec![RleRange::range(ysize as u16, 0)],
+                        colors: vec![],
+                    }
+                }
+            }
+        } else {
+            change.after.unpack()
+        };
+        per_volume
+            .entry(change.key.clone())
+            .or_default()
+            .push((change.index, column));
+    }
+    for (key, columns) in per_volume {
+        if let Some(volume) = store.volume_mut(&key) {
+            volume.replace_columns(columns);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brush::{Brush, BrushOp, BrushShape, BrushTransform};
+    use crate::types::voxel::RgbVoxel;
+
+    fn paint(journal: &mut EditJournal<()>, volume: &mut RleVolume, center: [f32; 3], r: f32) {
+        let brush = Brush::new(BrushShape::Sphere { radius: r }, BrushTransform::at(center));
+        let columns: Vec<((), usize)> = volume
+            .brush_columns(&brush)
+            .into_iter()
+            .map(|i| ((), i))
+            .collect();
+        journal.record(volume, &columns, |v| {
+            v.apply_brush(&brush, BrushOp::Add(RgbVoxel::only_green(3)))
+        });
+    }
+
+    #[test]
+    fn undo_redo_test() {
+        let mut volume = RleVolume::empty(8, 8, 8);
+        let mut journal = EditJournal::default();
+        let original = volume.to_array();
+
+        paint(&mut journal, &mut volume, [2.5, 2.5, 2.5], 1.0);
+        let first = volume.to_array();
+        paint(&mut journal, &mut volume, [5.5, 5.5, 5.5], 1.5);
+        let second = volume.to_array();
+        assert_eq!(journal.undo_len(), 2, "Two steps are recorded");
+
+        assert!(journal.undo(&mut volume));
+        assert_eq!(volume.to_array(), first, "Undo second edit");
+        assert!(journal.undo(&mut volume));
+        assert_eq!(volume.to_array(), original, "Undo first edit");
+        assert!(!journal.undo(&mut volume), "Nothing to undo");
+
+        assert!(journal.redo(&mut volume));
+        assert_eq!(volume.to_array(), first, "Redo first edit");
+        assert!(journal.redo(&mut volume));
+        assert_eq!(volume.to_array(), second, "Redo second edit");
+        assert!(!journal.redo(&mut volume), "Nothing to redo");
+
+        journal.undo(&mut volume);
+        paint(&mut journal, &mut volume, [1.5, 6.5, 1.5], 1.0);
+        assert_eq!(journal.redo_len(), 0, "New edit clears redo");
+    }
+
+    #[test]
+    fn unchanged_edit_is_skipped() {
+        let mut volume = RleVolume::empty(8, 8, 8);
+        let mut journal = EditJournal::default();
+        paint(&mut journal, &mut volume, [2.5, 2.5, 2.5], 1.0);
+        paint(&mut journal, &mut volume, [2.5, 2.5, 2.5], 1.0);
+        assert_eq!(journal.undo_len(), 1, "Repeated edit changes nothing");
+    }
+
+    #[test]
+    fn merge_small_edits() {
+        let mut volume = RleVolume::empty(8, 8, 8);
+        let mut journal = EditJournal::new(JournalSettings {
+            memory_budget: usize::MAX,
+            merge_limit: 1024,
+        });
+        let original = volume.to_array();
+        for i in 0..4 {
+            paint(&mut journal, &mut volume, [1.5 + i as f32, 1.5, 1.5], 0.5);
+        }
+        assert_eq!(journal.undo_len(), 1, "Small edits are merged");
+        journal.seal();
+        paint(&mut journal, &mut volume, [6.5, 6.5, 6.5], 0.5);
+        assert_eq!(journal.undo_len(), 2, "Sealed step is not merged");
+
+        journal.undo(&mut volume);
+        journal.undo(&mut volume);
+        assert_eq!(volume.to_array(), original, "Merged step is undone at once");
+    }
+
+    #[test]
+    fn memory_budget() {
+        // Spheres at different places, so each paint is a separate step of the same size
+        let center = |i: usize| [2.5 + 4.0 * i as f32, 4.0, 4.0];
+        let mut volume = RleVolume::empty(16, 8, 8);
+        let mut probe = EditJournal::default();
+        paint(&mut probe, &mut volume, center(0), 1.0);
+        let step_size = probe.memory_size();
+
+        let budget = step_size * 2 + step_size / 2;
+        let mut volume = RleVolume::empty(16, 8, 8);
+        let mut journal = EditJournal::new(JournalSettings {
+            memory_budget: budget,
+            merge_limit: 0,
+        });
+        for i in 0..2 {
+            paint(&mut journal, &mut volume, center(i), 1.0);
+        }
+        assert_eq!(journal.undo_len(), 2, "Steps within the budget are kept");
+        assert_eq!(journal.memory_size(), step_size * 2);
+
+        for i in 2..4 {
+            paint(&mut journal, &mut volume, center(i), 1.0);
+            assert_eq!(journal.undo_len(), 2, "Oldest step is dropped");
+            assert!(journal.memory_size() <= budget, "Journal fits the budget");
+        }
+        while journal.undo(&mut volume) {}
+        assert!(
+            !volume.get(2, 4, 4).is_empty() && !volume.get(6, 4, 4).is_empty(),
+            "Dropped steps can't be undone"
+        );
+        assert!(volume.get(10, 4, 4).is_empty() && volume.get(14, 4, 4).is_empty());
+    }
+}
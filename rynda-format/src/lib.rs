@@ -1,2 +1,50 @@
+//! Run length encoded voxel volumes.
+//!
+//! The core types in `types` only need an allocator and build under `no_std` when the
+//! default `std` feature is disabled. Import, export and editing tools, and conversions
+//! from `ndarray` arrays, need `std`. MagicaVoxel import needs the `vox` feature.
+//!
+//! The `no_std` build is checked on a target without `std`, e.g. with
+//! `cargo build -p rynda-format --no-default-features --target thumbv7em-none-eabihf`,
+//! and `cargo test -p rynda-format --no-default-features` runs the tests of the core types.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod animation;
+#[cfg(feature = "std")]
+pub mod ao;
+#[cfg(feature = "std")]
+pub mod brush;
+#[cfg(feature = "std")]
+pub mod color;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "std")]
+pub mod file;
+#[cfg(feature = "vox")]
 pub mod from_vox;
+#[cfg(feature = "std")]
+pub mod heightmap;
+#[cfg(feature = "std")]
+pub mod history;
+#[cfg(feature = "std")]
+pub mod mesh;
+#[cfg(feature = "std")]
+pub mod metrics;
+#[cfg(feature = "std")]
+pub mod morphology;
+#[cfg(feature = "std")]
+pub mod nrrd;
+#[cfg(feature = "std")]
+pub mod patch;
+#[cfg(feature = "std")]
+pub mod region;
+#[cfg(feature = "std")]
+pub mod schem;
+#[cfg(feature = "std")]
+pub mod sdf;
+#[cfg(feature = "std")]
+pub mod terrain;
 pub mod types;
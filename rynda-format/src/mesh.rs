@@ -0,0 +1,261 @@
+use super::types::{source::VoxelSource, voxel::RgbVoxel};
+
+/// Single vertex of extracted surface. Layout is stable to be uploaded directly
+/// into vertex buffer: 3 floats of position followed by 3 floats of color.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MeshVertex {
+    /// Position in voxel units relative to the origin of meshed region. Voxel with
+    /// coordinates (x, y, z) has its center at (x + 0.5, y + 0.5, z + 0.5).
+    pub position: [f32; 3],
+    /// Normalized RGB color interpolated from nearby voxels
+    pub color: [f32; 3],
+}
+
+/// Smooth triangle mesh extracted from voxels
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SurfaceMesh {
+    /// Vertices of the mesh
+    pub vertices: Vec<MeshVertex>,
+    /// Triangle list with counter clockwise winding when looking from outside
+    pub indices: Vec<u32>,
+}
+
+impl SurfaceMesh {
+    /// Return amount of triangles in the mesh
+    pub fn triangles_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+}
+
+/// Convert 5-6-5 voxel to normalized float color
+fn voxel_color(voxel: RgbVoxel) -> [f32; 3] {
+    [
+        voxel.red() as f32 / 31.0,
+        voxel.green() as f32 / 63.0,
+        voxel.blue() as f32 / 31.0,
+    ]
+}
+
+/// Extract smooth surface from voxels with surface nets algorithm. Voxels are treated as
+/// binary occupancy field, colors of vertices are averaged from surrounding filled voxels.
+///
+/// Meshed region starts at `origin` and has `size` voxels in each dimension. The source is
+/// sampled one voxel beyond the region in each direction, so meshing neighbour chunks of
+/// the same source produces vertices at the same places and the surface has no seams
+/// between chunks. Surface that crosses the upper border of the region belongs to the
+/// region, the one that crosses the lower border belongs to the neighbour on that side.
+pub fn surface_nets<S: VoxelSource + ?Sized>(
+    source: &S,
+    origin: [i32; 3],
+    size: [usize; 3],
+) -> SurfaceMesh {
+    let mut mesh = SurfaceMesh::default();
+    if size.contains(&0) {
+        return mesh;
+    }
+
+    // Samples with padding of one voxel around the region
+    let dims = [size[0] + 2, size[1] + 2, size[2] + 2];
+    let sample_index = |p: [usize; 3]| p[0] + dims[0] * (p[1] + dims[1] * p[2]);
+    let mut samples = vec![RgbVoxel::empty(); dims[0] * dims[1] * dims[2]];
+    let mut column = vec![RgbVoxel::empty(); dims[1]];
+    for z in 0..dims[2] {
+        for x in 0..dims[0] {
+            source.voxel_column(
+                origin[0] + x as i32 - 1,
+                origin[1] - 1,
+                origin[2] + z as i32 - 1,
+                &mut column,
+            );
+            for (y, voxel) in column.iter().enumerate() {
+                samples[sample_index([x, y, z])] = *voxel;
+            }
+        }
+    }
+    let solid = |p: [usize; 3]| !samples[sample_index(p)].is_empty();
+
+    // Cells are cubes between 8 neighbour samples, cell has the same index as its min corner
+    let cell_dims = [size[0] + 1, size[1] + 1, size[2] + 1];
+    let cell_index = |c: [usize; 3]| c[0] + cell_dims[0] * (c[1] + cell_dims[1] * c[2]);
+    let mut cell_vertices = vec![u32::MAX; cell_dims[0] * cell_dims[1] * cell_dims[2]];
+    for z in 0..cell_dims[2] {
+        for y in 0..cell_dims[1] {
+            for x in 0..cell_dims[0] {
+                let corners: [[usize; 3]; 8] =
+                    core::array::from_fn(|i| [x + (i & 1), y + ((i >> 1) & 1), z + ((i >> 2) & 1)]);
+                let filled = corners.iter().filter(|c| solid(**c)).count();
+                if filled == 0 || filled == 8 {
+                    continue;
+                }
+
+                let mut offset = [0.0f32; 3];
+                let mut crossings = 0;
+                for (i, a) in corners.iter().enumerate() {
+                    for axis in 0..3 {
+                        if i & (1 << axis) != 0 {
+                            continue;
+                        }
+                        let b = corners[i | (1 << axis)];
+                        if solid(*a) != solid(b) {
+                            for (k, o) in offset.iter_mut().enumerate() {
+                                *o += if k == axis {
+                                    0.5
+                                } else {
+                                    (a[k] - [x, y, z][k]) as f32
+                                };
+                            }
+                            crossings += 1;
+                        }
+                    }
+                }
+
+                let mut color = [0.0f32; 3];
+                for c in corners.iter().filter(|c| solid(**c)) {
+                    let vc = voxel_color(samples[sample_index(*c)]);
+                    for k in 0..3 {
+                        color[k] += vc[k] / filled as f32;
+                    }
+                }
+
+                let cell = [x, y, z];
+                let mut position = [0.0f32; 3];
+                for k in 0..3 {
+                    position[k] = cell[k] as f32 - 0.5 + offset[k] / crossings as f32;
+                }
+                cell_vertices[cell_index(cell)] = mesh.vertices.len() as u32;
+                mesh.vertices.push(MeshVertex { position, color });
+            }
+        }
+    }
+
+    // Each edge between two samples that crosses the surface produces a quad of 4 cells
+    // around the edge. The region owns edges that start inside it.
+    for z in 1..size[2] + 1 {
+        for y in 1..size[1] + 1 {
+            for x in 1..size[0] + 1 {
+                let p = [x, y, z];
+                for axis in 0..3 {
+                    let mut q = p;
+                    q[axis] += 1;
+                    let inside = solid(p);
+                    if inside == solid(q) {
+                        continue;
+                    }
+
+                    let u = (axis + 1) % 3;
+                    let v = (axis + 2) % 3;
+                    let mut b = p;
+                    b[u] -= 1;
+                    let mut c = b;
+                    c[v] -= 1;
+                    let mut d = p;
+                    d[v] -= 1;
+                    let quad = [p, b, c, d].map(|cell| cell_vertices[cell_index(cell)]);
+                    if inside {
+                        mesh.indices.extend_from_slice(&[
+                            quad[0], quad[1], quad[2], quad[0], quad[2], quad[3],
+                        ]);
+                    } else {
+                        mesh.indices.extend_from_slice(&[
+                            quad[0], quad[3], quad[2], quad[0], quad[2], quad[1],
+                        ]);
+                    }
+                }
+            }
+        }
+    }
+
+    mesh
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::volume::RleVolume;
+    use ndarray::Array3;
+
+    fn triangle_normal(mesh: &SurfaceMesh, tri: usize) -> [f32; 3] {
+        let p = |i: usize| mesh.vertices[mesh.indices[tri * 3 + i] as usize].position;
+        let (a, b, c) = (p(0), p(1), p(2));
+        let ab = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+        let ac = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+        [
+            ab[1] * ac[2] - ab[2] * ac[1],
+            ab[2] * ac[0] - ab[0] * ac[2],
+            ab[0] * ac[1] - ab[1] * ac[0],
+        ]
+    }
+
+    #[test]
+    fn empty_volume_mesh() {
+        let volume = RleVolume::empty(4, 4, 4);
+        let mesh = surface_nets(&volume, [0, 0, 0], [4, 4, 4]);
+        assert_eq!(mesh, SurfaceMesh::default(), "Empty volume has no surface");
+    }
+
+    #[test]
+    fn single_voxel_mesh() {
+        let mut voxels = Array3::from_elem((3, 3, 3), RgbVoxel::empty());
+        voxels[(1, 1, 1)] = RgbVoxel::only_red(31);
+        let volume: RleVolume = voxels.into();
+        let mesh = surface_nets(&volume, [0, 0, 0], [3, 3, 3]);
+
+        assert_eq!(mesh.vertices.len(), 8, "Single voxel is meshed into a cube");
+        assert_eq!(mesh.triangles_count(), 12, "Cube has 12 triangles");
+        for v in mesh.vertices.iter() {
+            assert_eq!(v.color, [1.0, 0.0, 0.0], "Color is taken from voxel");
+        }
+        for tri in 0..mesh.triangles_count() {
+            let n = triangle_normal(&mesh, tri);
+            let a = mesh.vertices[mesh.indices[tri * 3] as usize].position;
+            let outward = [a[0] - 1.5, a[1] - 1.5, a[2] - 1.5];
+            let dot = n[0] * outward[0] + n[1] * outward[1] + n[2] * outward[2];
+            assert!(dot > 0.0, "Triangle {} is not facing outside", tri);
+        }
+    }
+
+    #[test]
+    fn chunks_without_seams() {
+        let voxels = Array3::from_shape_fn((8, 8, 4), |(x, y, z)| {
+            let d = (x as i32 - 4).pow(2) + (y as i32 - 4).pow(2) + (z as i32 - 2).pow(2);
+            if d < 9 {
+                RgbVoxel::only_green(63)
+            } else {
+                RgbVoxel::empty()
+            }
+        });
+        let volume: RleVolume = voxels.into();
+        let whole = surface_nets(&volume, [0, 0, 0], [8, 8, 4]);
+
+        // Positions are compared on a grid to ignore rounding after chunk offset is added
+        let snap = |p: [f32; 3]| p.map(|v| (v * 1024.0).round() as i32);
+        let mut triangles = 0;
+        let mut positions = vec![];
+        for (ox, oy) in [(0, 0), (4, 0), (0, 4), (4, 4)] {
+            let chunk = surface_nets(&volume, [ox, oy, 0], [4, 4, 4]);
+            triangles += chunk.triangles_count();
+            for i in chunk.indices.iter() {
+                let p = chunk.vertices[*i as usize].position;
+                positions.push(snap([p[0] + ox as f32, p[1] + oy as f32, p[2]]));
+            }
+        }
+        let mut whole_positions: Vec<[i32; 3]> = whole
+            .indices
+            .iter()
+            .map(|i| snap(whole.vertices[*i as usize].position))
+            .collect();
+
+        assert_eq!(
+            triangles,
+            whole.triangles_count(),
+            "Chunks have the same amount of triangles as the whole volume"
+        );
+        positions.sort();
+        whole_positions.sort();
+        assert_eq!(
+            positions, whole_positions,
+            "Chunked mesh matches the whole mesh"
+        );
+    }
+}
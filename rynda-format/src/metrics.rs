@@ -0,0 +1,189 @@
+use super::types::{volume::RleVolume, voxel::RgbVoxel};
+use ndarray::Array3;
+
+/// Differences between two volumes of the same dimensions. Colors are compared in 8-bit
+/// sRGB units, see `RgbVoxel::to_rgba8`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ComparisonReport {
+    /// Drawn voxels of the first volume
+    pub occupied_a: usize,
+    /// Drawn voxels of the second volume
+    pub occupied_b: usize,
+    /// Voxels that are drawn in both volumes
+    pub shared: usize,
+    /// Voxels that are drawn only in the first volume
+    pub only_in_a: usize,
+    /// Voxels that are drawn only in the second volume
+    pub only_in_b: usize,
+    /// Shared voxels with different colors
+    pub color_mismatches: usize,
+    /// Mean squared error of red, green and blue channels over shared voxels
+    pub mse: [f64; 3],
+    /// Maximum absolute error of red, green and blue channels over shared voxels
+    pub max_error: [u8; 3],
+}
+
+impl ComparisonReport {
+    /// Intersection over union of occupancy, 1.0 when both volumes are empty
+    pub fn iou(&self) -> f64 {
+        let union = self.shared + self.only_in_a + self.only_in_b;
+        if union == 0 {
+            1.0
+        } else {
+            self.shared as f64 / union as f64
+        }
+    }
+
+    /// Amount of voxels that differ in occupancy or color
+    pub fn differing_voxels(&self) -> usize {
+        self.only_in_a + self.only_in_b + self.color_mismatches
+    }
+
+    /// Peak signal to noise ratio of each channel in decibels, infinity for exact colors
+    pub fn psnr(&self) -> [f64; 3] {
+        self.mse.map(|mse| {
+            if mse == 0.0 {
+                f64::INFINITY
+            } else {
+                10.0 * (255.0 * 255.0 / mse).log10()
+            }
+        })
+    }
+
+    /// Check that volumes have the same voxels
+    pub fn is_identical(&self) -> bool {
+        self.differing_voxels() == 0
+    }
+}
+
+/// Compare voxels of volumes in model coordinates, so volumes can be encoded along
+/// different axes. Volumes must have the same dimensions.
+pub fn compare_volumes(a: &RleVolume, b: &RleVolume) -> ComparisonReport {
+    assert_eq!(
+        a.dimensions(),
+        b.dimensions(),
+        "Compared volumes have the same dimensions"
+    );
+    let (va, vb) = (a.to_array(), b.to_array());
+    let mut report = ComparisonReport::default();
+    let mut squared = [0u64; 3];
+    for (ca, cb) in va.iter().zip(vb.iter()) {
+        match (ca.is_empty(), cb.is_empty()) {
+            (true, true) => {}
+            (false, true) => report.only_in_a += 1,
+            (true, false) => report.only_in_b += 1,
+            (false, false) => {
+                report.shared += 1;
+                if ca != cb {
+                    report.color_mismatches += 1;
+                }
+                let (pa, pb) = (ca.to_rgba8(), cb.to_rgba8());
+                for i in 0..3 {
+                    let e = pa[i].abs_diff(pb[i]);
+                    squared[i] += e as u64 * e as u64;
+                    report.max_error[i] = report.max_error[i].max(e);
+                }
+            }
+        }
+    }
+    report.occupied_a = report.shared + report.only_in_a;
+    report.occupied_b = report.shared + report.only_in_b;
+    if report.shared > 0 {
+        report.mse = squared.map(|s| s as f64 / report.shared as f64);
+    }
+    report
+}
+
+/// Color of voxels that are drawn only in the first volume
+pub const ONLY_IN_A_COLOR: [u8; 3] = [31, 0, 0];
+/// Color of voxels that are drawn only in the second volume
+pub const ONLY_IN_B_COLOR: [u8; 3] = [0, 63, 0];
+
+/// Make volume that highlights differences: voxels drawn only in the first volume are red,
+/// only in the second are green, and shared voxels with other colors are yellow with
+/// brightness growing with the error. Matching voxels are empty.
+pub fn difference_volume(a: &RleVolume, b: &RleVolume) -> RleVolume {
+    assert_eq!(
+        a.dimensions(),
+        b.dimensions(),
+        "Compared volumes have the same dimensions"
+    );
+    let (va, vb) = (a.to_array(), b.to_array());
+    let diff: Array3<RgbVoxel> = ndarray::Zip::from(&va).and(&vb).map_collect(|ca, cb| {
+        match (ca.is_empty(), cb.is_empty()) {
+            (false, true) => {
+                let [r, g, b] = ONLY_IN_A_COLOR;
+                RgbVoxel::rgb(r, g, b)
+            }
+            (true, false) => {
+                let [r, g, b] = ONLY_IN_B_COLOR;
+                RgbVoxel::rgb(r, g, b)
+            }
+            (false, false) if ca != cb => {
+                let (pa, pb) = (ca.to_rgba8(), cb.to_rgba8());
+                let error = (0..3).map(|i| pa[i].abs_diff(pb[i])).max().unwrap();
+                // Even the smallest error stays visible
+                let level = 8 + error as u32 * 23 / 255;
+                RgbVoxel::rgb(level as u8, (level * 2) as u8, 0)
+            }
+            _ => RgbVoxel::empty(),
+        }
+    });
+    RleVolume::from_array_with_axis(&diff, a.axis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::axis::RleAxis;
+
+    fn volume(f: impl Fn(usize, usize, usize) -> RgbVoxel) -> RleVolume {
+        Array3::from_shape_fn((6, 5, 4), |(x, y, z)| f(x, y, z)).into()
+    }
+
+    #[test]
+    fn compare_test() {
+        let a = volume(|x, y, _| {
+            if y <= x % 3 {
+                RgbVoxel::rgb(10, 20, 30)
+            } else {
+                RgbVoxel::empty()
+            }
+        });
+        let same = compare_volumes(&a, &a);
+        assert!(same.is_identical());
+        assert_eq!(same.iou(), 1.0);
+        assert_eq!(same.psnr(), [f64::INFINITY; 3]);
+
+        // One voxel removed, one added and one recolored
+        let mut voxels = a.to_array();
+        voxels[(0, 0, 0)] = RgbVoxel::empty();
+        voxels[(0, 4, 0)] = RgbVoxel::only_blue(1);
+        voxels[(2, 0, 3)] = RgbVoxel::rgb(11, 20, 30);
+        let b = RleVolume::from_array_with_axis(&voxels, RleAxis::X);
+
+        let report = compare_volumes(&a, &b);
+        assert_eq!(report.only_in_a, 1);
+        assert_eq!(report.only_in_b, 1);
+        assert_eq!(report.color_mismatches, 1);
+        assert_eq!(report.differing_voxels(), 3);
+        assert_eq!(report.occupied_a, 48);
+        assert_eq!(report.shared, 47);
+        assert!((report.iou() - 47.0 / 49.0).abs() < 1e-9);
+        // 10 and 11 of 5-bit channel expand to 82 and 90
+        assert_eq!(report.max_error, [8, 0, 0]);
+        assert!((report.mse[0] - 64.0 / 47.0).abs() < 1e-9);
+        assert!(report.psnr()[0] > 40.0 && report.psnr()[1].is_infinite());
+
+        let diff = difference_volume(&a, &b);
+        assert_eq!(diff.axis, a.axis);
+        assert_eq!(diff.get(0, 0, 0), RgbVoxel::only_red(31));
+        assert_eq!(diff.get(0, 4, 0), RgbVoxel::only_green(63));
+        assert!(!diff.get(2, 0, 3).is_empty());
+        assert!(diff.get(1, 0, 0).is_empty(), "Matching voxels are empty");
+        assert_eq!(
+            compare_volumes(&diff, &RleVolume::empty(6, 5, 4)).occupied_a,
+            3
+        );
+    }
+}
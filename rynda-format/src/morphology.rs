@@ -0,0 +1,397 @@
+use super::types::{column::RleColumn, volume::RleVolume, voxel::RgbVoxel};
+
+/// Shape that defines neighbourhood of morphological operations
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StructuringElement {
+    /// Cube with given radius, i.e. side of `2 * radius + 1` voxels
+    Box(u32),
+    /// Ball with given radius in voxels
+    Sphere(u32),
+}
+
+impl StructuringElement {
+    /// Offsets in XZ plane that the element covers with half height of the element along Y
+    /// at that offset. Offsets are sorted by distance from the center.
+    fn footprint(&self) -> Vec<(i64, i64, i64)> {
+        let r = match self {
+            StructuringElement::Box(r) => *r as i64,
+            StructuringElement::Sphere(r) => *r as i64,
+        };
+        let mut offsets = vec![];
+        for dz in -r..=r {
+            for dx in -r..=r {
+                match self {
+                    StructuringElement::Box(_) => offsets.push((dx, dz, r)),
+                    StructuringElement::Sphere(_) => {
+                        let rest = r * r - dx * dx - dz * dz;
+                        if rest >= 0 {
+                            offsets.push((dx, dz, (rest as f64).sqrt().floor() as i64));
+                        }
+                    }
+                }
+            }
+        }
+        offsets.sort_by_key(|(dx, dz, _)| dx * dx + dz * dz);
+        offsets
+    }
+}
+
+/// Defines which color newly created voxels get
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorRule {
+    /// Take color of the nearest voxel of the source volume
+    Nearest,
+    /// Fill all new voxels with the same color
+    Constant(RgbVoxel),
+}
+
+/// Half open interval of filled voxels in a column
+type Span = (i64, i64);
+
+/// Sort and merge overlapping or touching spans
+fn merge_spans(mut spans: Vec<Span>) -> Vec<Span> {
+    spans.sort();
+    let mut merged: Vec<Span> = Vec::with_capacity(spans.len());
+    for span in spans {
+        match merged.last_mut() {
+            Some(last) if span.0 <= last.1 => last.1 = last.1.max(span.1),
+            _ => merged.push(span),
+        }
+    }
+    merged
+}
+
+/// Filled spans of each column of the volume
+fn volume_spans(volume: &RleVolume) -> Vec<Vec<Span>> {
+    (0..volume.columns_count())
+        .map(|i| {
+            let mut spans = vec![];
+            let mut y = 0;
+            for range in volume.column_at(i).ranges {
+                y += range.skipped() as i64;
+                if range.drawn() > 0 {
+                    spans.push((y, y + range.drawn() as i64));
+                }
+                y += range.drawn() as i64;
+            }
+            merge_spans(spans)
+        })
+        .collect()
+}
+
+/// Empty spans of the column. When `height` is given, only empty space inside the column is
+/// returned, otherwise infinite space above and below the volume is empty too.
+fn complement_spans(spans: &[Span], height: Option<i64>) -> Vec<Span> {
+    let (min, max) = height.map_or((i64::MIN / 2, i64::MAX / 2), |h| (0, h));
+    let mut result = vec![];
+    let mut start = min;
+    for span in spans {
+        if span.0 > start {
+            result.push((start, span.0.min(max)));
+        }
+        start = start.max(span.1);
+    }
+    if start < max {
+        result.push((start, max));
+    }
+    result
+}
+
+/// Dilate occupancy of columns. Each column takes spans of neighbour columns under the
+/// element footprint and widens them by half height of the element at that offset. When
+/// `outside_filled` is set, space outside of the volume is considered to be filled.
+fn dilate_spans(
+    volume: &RleVolume,
+    spans: &[Vec<Span>],
+    element: StructuringElement,
+    outside_filled: bool,
+) -> Vec<Vec<Span>> {
+    let (xsize, zsize) = (volume.xsize as i64, volume.zsize as i64);
+    let footprint = element.footprint();
+    let full = vec![(i64::MIN / 2, i64::MAX / 2)];
+    (0..volume.columns_count())
+        .map(|i| {
+            let x = i as i64 % xsize;
+            let z = i as i64 / xsize;
+            let mut widened = vec![];
+            for (dx, dz, h) in footprint.iter() {
+                let (nx, nz) = (x + dx, z + dz);
+                let neighbour = if nx < 0 || nz < 0 || nx >= xsize || nz >= zsize {
+                    if outside_filled {
+                        &full
+                    } else {
+                        continue;
+                    }
+                } else {
+                    &spans[(nx + nz * xsize) as usize]
+                };
+                widened.extend(neighbour.iter().map(|(s, e)| (s - h, e + h)));
+            }
+            merge_spans(widened)
+        })
+        .collect()
+}
+
+/// Erode occupancy of columns as complement of dilated complement. When `outside_filled`
+/// is not set, space outside of the volume is empty and voxels near the borders are eroded.
+fn erode_spans(
+    volume: &RleVolume,
+    spans: &[Vec<Span>],
+    element: StructuringElement,
+    outside_filled: bool,
+) -> Vec<Vec<Span>> {
+    let height = if outside_filled {
+        Some(volume.ysize as i64)
+    } else {
+        None
+    };
+    let complement: Vec<Vec<Span>> = spans.iter().map(|s| complement_spans(s, height)).collect();
+    dilate_spans(volume, &complement, element, !outside_filled)
+        .iter()
+        .map(|s| complement_spans(s, None))
+        .collect()
+}
+
+/// Check whether the height is covered by spans
+fn spans_contain(spans: &[Span], y: i64) -> bool {
+    spans.iter().any(|(s, e)| *s <= y && y < *e)
+}
+
+/// Build volume from the occupancy spans. Voxels that were filled in the source keep their
+/// colors, new voxels are colored by the rule.
+fn build_volume(
+    source: &RleVolume,
+    spans: &[Vec<Span>],
+    element: StructuringElement,
+    rule: ColorRule,
+) -> RleVolume {
+    let ysize = source.ysize as i64;
+    let xsize = source.xsize as i64;
+    let source_spans = volume_spans(source);
+    let source_columns: Vec<Vec<RgbVoxel>> = (0..source.columns_count())
+        .map(|i| source.column_at(i).decompress())
+        .collect();
+    let footprint = element.footprint();
+
+    // Color of the nearest source voxel within the element
+    let nearest = |x: i64, y: i64, z: i64| {
+        let mut best: Option<(i64, RgbVoxel)> = None;
+        for (dx, dz, h) in footprint.iter() {
+            let (nx, nz) = (x + dx, z + dz);
+            if nx < 0 || nz < 0 || nx >= xsize || nz >= source.zsize as i64 {
+                continue;
+            }
+            let index = (nx + nz * xsize) as usize;
+            for (s, e) in source_spans[index].iter() {
+                let ny = y.clamp(*s, e - 1);
+                let dy = (ny - y).abs();
+                if dy > *h {
+                    continue;
+                }
+                let d = dx * dx + dz * dz + dy * dy;
+                if best.is_none_or(|(bd, _)| d < bd) {
+                    best = Some((d, source_columns[index][ny as usize]));
+                }
+            }
+        }
+        best.map_or(RgbVoxel::black(), |(_, color)| color)
+    };
+
+    let columns = spans.iter().enumerate().map(|(i, column_spans)| {
+        let x = i as i64 % xsize;
+        let z = i as i64 / xsize;
+        let mut voxels = vec![RgbVoxel::empty(); ysize as usize];
+        for (s, e) in column_spans.iter() {
+            for y in (*s).max(0)..(*e).min(ysize) {
+                voxels[y as usize] = if spans_contain(&source_spans[i], y) {
+                    source_columns[i][y as usize]
+                } else {
+                    match rule {
+                        ColorRule::Constant(color) => color,
+                        ColorRule::Nearest => nearest(x, y, z),
+                    }
+                };
+            }
+        }
+        RleColumn::compress(&voxels)
+    });
+
+    // Elements are symmetric, so the result is the same in layout of any RLE axis
+    let mut volume = RleVolume::from_columns(
+        source.xsize as usize,
+        source.ysize as usize,
+        source.zsize as usize,
+        columns,
+    );
+    volume.axis = source.axis;
+    volume
+}
+
+/// Grow filled voxels by the structuring element. New voxels are colored by the rule.
+pub fn dilate(volume: &RleVolume, element: StructuringElement, rule: ColorRule) -> RleVolume {
+    let spans = dilate_spans(volume, &volume_spans(volume), element, false);
+    build_volume(volume, &spans, element, rule)
+}
+
+/// Shrink filled voxels by the structuring element. Voxel survives only when the whole
+/// element placed at it fits into filled voxels, outside of the volume is empty.
+pub fn erode(volume: &RleVolume, element: StructuringElement) -> RleVolume {
+    let spans = erode_spans(volume, &volume_spans(volume), element, false);
+    build_volume(volume, &spans, element, ColorRule::Nearest)
+}
+
+/// Erosion followed by dilation. Removes details smaller than the element, remaining
+/// voxels keep their colors.
+pub fn open(volume: &RleVolume, element: StructuringElement) -> RleVolume {
+    let eroded = erode_spans(volume, &volume_spans(volume), element, false);
+    let spans = dilate_spans(volume, &eroded, element, false);
+    build_volume(volume, &spans, element, ColorRule::Nearest)
+}
+
+/// Dilation followed by erosion. Fills holes and gaps smaller than the element, new voxels
+/// are colored by the rule. Voxels of the source volume are never removed.
+pub fn close(volume: &RleVolume, element: StructuringElement, rule: ColorRule) -> RleVolume {
+    let dilated = dilate_spans(volume, &volume_spans(volume), element, false);
+    // Dilation is clipped by the volume bounds, so the outside is treated as filled to not
+    // erode voxels at the borders
+    let spans = erode_spans(volume, &dilated, element, true);
+    build_volume(volume, &spans, element, rule)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::Array3;
+
+    fn occupancy(volume: &RleVolume) -> Array3<bool> {
+        volume.to_array().map(|v| !v.is_empty())
+    }
+
+    /// Dense reference implementation of dilation occupancy
+    fn dense_dilate(voxels: &Array3<bool>, element: StructuringElement) -> Array3<bool> {
+        let (xsize, ysize, zsize) = voxels.dim();
+        let r = match element {
+            StructuringElement::Box(r) | StructuringElement::Sphere(r) => r as i64,
+        };
+        Array3::from_shape_fn((xsize, ysize, zsize), |(x, y, z)| {
+            for dz in -r..=r {
+                for dy in -r..=r {
+                    for dx in -r..=r {
+                        if let StructuringElement::Sphere(_) = element {
+                            if dx * dx + dy * dy + dz * dz > r * r {
+                                continue;
+                            }
+                        }
+                        let p = (x as i64 + dx, y as i64 + dy, z as i64 + dz);
+                        if p.0 >= 0
+                            && p.1 >= 0
+                            && p.2 >= 0
+                            && (p.0 as usize) < xsize
+                            && (p.1 as usize) < ysize
+                            && (p.2 as usize) < zsize
+                            && voxels[(p.0 as usize, p.1 as usize, p.2 as usize)]
+                        {
+                            return true;
+                        }
+                    }
+                }
+            }
+            false
+        })
+    }
+
+    fn sample_volume() -> Array3<RgbVoxel> {
+        Array3::from_shape_fn((9, 10, 8), |(x, y, z)| {
+            if (x == 4 && z == 3 && y == 5) || (x < 3 && y < 3 && z < 4) {
+                RgbVoxel::rgb(x as u8 + 1, y as u8, z as u8)
+            } else {
+                RgbVoxel::empty()
+            }
+        })
+    }
+
+    #[test]
+    fn dilate_matches_dense() {
+        let voxels = sample_volume();
+        let volume: RleVolume = voxels.clone().into();
+        let dense = voxels.map(|v| !v.is_empty());
+        for element in [StructuringElement::Box(1), StructuringElement::Sphere(2)] {
+            let dilated = dilate(&volume, element, ColorRule::Nearest);
+            assert_eq!(
+                occupancy(&dilated),
+                dense_dilate(&dense, element),
+                "Dilation with {:?}",
+                element
+            );
+        }
+    }
+
+    #[test]
+    fn dilate_colors() {
+        let volume: RleVolume = sample_volume().into();
+        let dilated = dilate(&volume, StructuringElement::Box(1), ColorRule::Nearest);
+        assert_eq!(
+            dilated.get(4, 6, 3),
+            RgbVoxel::rgb(5, 5, 3),
+            "Nearest color is taken"
+        );
+        assert_eq!(dilated.get(0, 0, 0), volume.get(0, 0, 0), "Colors are kept");
+
+        let fill = RgbVoxel::only_blue(7);
+        let dilated = dilate(
+            &volume,
+            StructuringElement::Box(1),
+            ColorRule::Constant(fill),
+        );
+        assert_eq!(dilated.get(4, 6, 3), fill, "Constant color is used");
+        assert_eq!(dilated.get(4, 5, 3), volume.get(4, 5, 3), "Colors are kept");
+    }
+
+    #[test]
+    fn erode_and_open() {
+        let volume: RleVolume = sample_volume().into();
+        let eroded = erode(&volume, StructuringElement::Box(1));
+        let occ = occupancy(&eroded);
+        assert_eq!(
+            occ.iter().filter(|v| **v).count(),
+            2,
+            "Only inner voxels of the block survive"
+        );
+        assert!(
+            occ[(1, 1, 1)] && occ[(1, 1, 2)],
+            "Inner voxels of the block"
+        );
+
+        let opened = open(&volume, StructuringElement::Box(1));
+        let occ = occupancy(&opened);
+        assert!(!occ[(4, 5, 3)], "Single voxel noise is removed");
+        let block = occupancy(&volume).map(|v| *v) & occ.map(|v| *v);
+        assert_eq!(block, occ, "Opening doesn't add voxels");
+        assert_eq!(opened.get(0, 0, 0), volume.get(0, 0, 0), "Colors are kept");
+    }
+
+    #[test]
+    fn close_fills_gap() {
+        let voxels = Array3::from_shape_fn((7, 3, 3), |(x, _, _)| {
+            if x != 3 {
+                RgbVoxel::only_green(5)
+            } else {
+                RgbVoxel::empty()
+            }
+        });
+        let volume: RleVolume = voxels.into();
+        let closed = close(&volume, StructuringElement::Box(1), ColorRule::Nearest);
+        assert_eq!(
+            closed.get(3, 1, 1),
+            RgbVoxel::only_green(5),
+            "Gap is filled"
+        );
+        assert!(
+            occupancy(&volume)
+                .iter()
+                .zip(occupancy(&closed).iter())
+                .all(|(a, b)| !*a || *b),
+            "Closing doesn't remove voxels"
+        );
+    }
+}
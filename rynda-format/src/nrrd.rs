@@ -0,0 +1,638 @@
+//! Dense raw volumes with NRRD headers that scientific tools like ParaView and 3D Slicer
+//! read and write.
+//!
+//! The first NRRD axis is the fastest one and maps to model X, the second to model Y and
+//! the third to model Z. Color volumes have an extra leading axis of 3 or 4 channels.
+//! Only `raw` encoding is supported.
+use super::types::{volume::RleVolume, voxel::RgbVoxel};
+use ndarray::Array3;
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader, Read, Write},
+    path::Path,
+};
+
+#[derive(Debug)]
+pub enum NrrdError {
+    Io(io::Error),
+    /// File doesn't start with NRRD magic
+    NotNrrd,
+    /// Header misses required field or has malformed one
+    InvalidHeader(String),
+    /// Header describes data that we can't read
+    Unsupported(String),
+    /// Data is shorter than header describes
+    NotEnoughData {
+        expected: usize,
+        found: usize,
+    },
+    /// Volume doesn't fit into single `RleVolume`, which is smaller than 1024 voxels along
+    /// each axis
+    TooLarge {
+        sizes: [usize; 3],
+    },
+}
+
+impl fmt::Display for NrrdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NrrdError::Io(e) => write!(f, "IO error: {}", e),
+            NrrdError::NotNrrd => write!(f, "File is not NRRD"),
+            NrrdError::InvalidHeader(reason) => write!(f, "Invalid NRRD header: {}", reason),
+            NrrdError::Unsupported(what) => write!(f, "Unsupported NRRD {}", what),
+            NrrdError::NotEnoughData { expected, found } => write!(
+                f,
+                "Expected {} bytes of volume data, found {}",
+                expected, found
+            ),
+            NrrdError::TooLarge { sizes } => write!(
+                f,
+                "Volume of {}x{}x{} voxels is too large, RLE volume is smaller than 1024 along each axis",
+                sizes[0], sizes[1], sizes[2]
+            ),
+        }
+    }
+}
+
+impl std::error::Error for NrrdError {}
+
+impl From<io::Error> for NrrdError {
+    fn from(e: io::Error) -> Self {
+        NrrdError::Io(e)
+    }
+}
+
+/// Type of values in raw data
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScalarType {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float,
+    Double,
+}
+
+impl ScalarType {
+    /// Size of a single value in bytes
+    pub fn size(&self) -> usize {
+        match self {
+            ScalarType::Int8 | ScalarType::UInt8 => 1,
+            ScalarType::Int16 | ScalarType::UInt16 => 2,
+            ScalarType::Int32 | ScalarType::UInt32 | ScalarType::Float => 4,
+            ScalarType::Double => 8,
+        }
+    }
+
+    /// Parse any of type names that NRRD allows
+    pub fn from_nrrd_name(name: &str) -> Option<Self> {
+        let ty = match name {
+            "signed char" | "int8" | "int8_t" => ScalarType::Int8,
+            "uchar" | "unsigned char" | "uint8" | "uint8_t" => ScalarType::UInt8,
+            "short" | "short int" | "signed short" | "signed short int" | "int16" | "int16_t" => {
+                ScalarType::Int16
+            }
+            "ushort" | "unsigned short" | "unsigned short int" | "uint16" | "uint16_t" => {
+                ScalarType::UInt16
+            }
+            "int" | "signed int" | "int32" | "int32_t" => ScalarType::Int32,
+            "uint" | "unsigned int" | "uint32" | "uint32_t" => ScalarType::UInt32,
+            "float" => ScalarType::Float,
+            "double" => ScalarType::Double,
+            _ => return None,
+        };
+        Some(ty)
+    }
+
+    fn decode(&self, bytes: &[u8], endian: Endian) -> f32 {
+        macro_rules! num {
+            ($t:ty, $n:expr) => {{
+                let arr: [u8; $n] = bytes.try_into().unwrap();
+                match endian {
+                    Endian::Little => <$t>::from_le_bytes(arr),
+                    Endian::Big => <$t>::from_be_bytes(arr),
+                }
+            }};
+        }
+        match self {
+            ScalarType::Int8 => bytes[0] as i8 as f32,
+            ScalarType::UInt8 => bytes[0] as f32,
+            ScalarType::Int16 => num!(i16, 2) as f32,
+            ScalarType::UInt16 => num!(u16, 2) as f32,
+            ScalarType::Int32 => num!(i32, 4) as f32,
+            ScalarType::UInt32 => num!(u32, 4) as f32,
+            ScalarType::Float => num!(f32, 4),
+            ScalarType::Double => num!(f64, 8) as f32,
+        }
+    }
+}
+
+/// Byte order of multi-byte values
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Endian {
+    #[default]
+    Little,
+    Big,
+}
+
+/// Description of headerless raw data
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RawLayout {
+    /// Amount of voxels along model X, Y and Z
+    pub sizes: [usize; 3],
+    /// Values per voxel, 1 for scalar data and 3 or 4 for colors
+    pub channels: usize,
+    pub scalar: ScalarType,
+    pub endian: Endian,
+}
+
+impl RawLayout {
+    /// Size of the whole data in bytes, `None` if it overflows
+    pub fn byte_size(&self) -> Option<usize> {
+        self.sizes
+            .iter()
+            .try_fold(self.channels * self.scalar.size(), |acc, s| {
+                acc.checked_mul(*s)
+            })
+    }
+}
+
+/// Maps scalar values to colors by linear interpolation between sorted stops
+#[derive(Clone, Debug, PartialEq)]
+pub struct TransferFunction {
+    pub stops: Vec<(f32, RgbVoxel)>,
+}
+
+impl TransferFunction {
+    /// Make function from stops in any order
+    pub fn new(mut stops: Vec<(f32, RgbVoxel)>) -> Self {
+        assert!(!stops.is_empty(), "Transfer function has at least one stop");
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        TransferFunction { stops }
+    }
+
+    /// Black at `min` to white at `max`
+    pub fn grayscale(min: f32, max: f32) -> Self {
+        Self::new(vec![
+            (min, RgbVoxel::black()),
+            (max, RgbVoxel::rgb(31, 63, 31)),
+        ])
+    }
+
+    /// Color of the value, values outside of stops get color of the nearest stop
+    pub fn color(&self, value: f32) -> RgbVoxel {
+        let i = self.stops.partition_point(|(v, _)| *v <= value);
+        if i == 0 {
+            return self.stops[0].1;
+        }
+        if i == self.stops.len() {
+            return self.stops[i - 1].1;
+        }
+        let ((v0, c0), (v1, c1)) = (self.stops[i - 1], self.stops[i]);
+        c0.lerp(c1, (value - v0) / (v1 - v0))
+    }
+}
+
+/// Dense volume of values read from raw data, values of all types are converted to `f32`
+#[derive(Clone, Debug, PartialEq)]
+pub struct DenseVolume {
+    /// Amount of voxels along model X, Y and Z
+    pub sizes: [usize; 3],
+    /// Values per voxel
+    pub channels: usize,
+    /// Values with channel being the fastest axis, then X, Y and Z
+    pub values: Vec<f32>,
+}
+
+impl DenseVolume {
+    /// Value of a channel of the voxel
+    pub fn value(&self, x: usize, y: usize, z: usize, channel: usize) -> f32 {
+        let [xs, ys, _] = self.sizes;
+        self.values[((z * ys + y) * xs + x) * self.channels + channel]
+    }
+
+    /// Make volume of voxels whose value is at least `threshold`. Scalar values are
+    /// colored by the transfer function. Color data is taken as 8-bit sRGB channels, the
+    /// fourth channel is compared with the threshold when present and the mean of color
+    /// channels otherwise. Fails with `NrrdError::TooLarge` when any size is 1024 or more.
+    pub fn to_rle_volume(
+        &self,
+        threshold: f32,
+        transfer: &TransferFunction,
+    ) -> Result<RleVolume, NrrdError> {
+        let [xs, ys, zs] = self.sizes;
+        if self.sizes.iter().any(|s| *s >= 1024) {
+            return Err(NrrdError::TooLarge { sizes: self.sizes });
+        }
+        let voxels = Array3::from_shape_fn((xs, ys, zs), |(x, y, z)| {
+            let v = |c| self.value(x, y, z, c);
+            match self.channels {
+                1 if v(0) >= threshold => transfer.color(v(0)),
+                3 | 4 => {
+                    let level = if self.channels == 4 {
+                        v(3)
+                    } else {
+                        (v(0) + v(1) + v(2)) / 3.0
+                    };
+                    if level >= threshold {
+                        let c = |i| v(i).round().clamp(0.0, 255.0) as u8;
+                        RgbVoxel::from_rgba8([c(0), c(1), c(2), 255])
+                    } else {
+                        RgbVoxel::empty()
+                    }
+                }
+                _ => RgbVoxel::empty(),
+            }
+        });
+        Ok(voxels.into())
+    }
+}
+
+/// Read headerless raw data. Buffer grows with the data actually read, so sizes of damaged
+/// header don't cause huge allocations.
+pub fn read_raw<R: Read>(mut reader: R, layout: RawLayout) -> Result<DenseVolume, NrrdError> {
+    let expected = layout
+        .byte_size()
+        .ok_or_else(|| NrrdError::InvalidHeader("data size overflows".to_owned()))?;
+    let mut bytes = vec![];
+    reader
+        .by_ref()
+        .take(expected as u64)
+        .read_to_end(&mut bytes)?;
+    if bytes.len() < expected {
+        return Err(NrrdError::NotEnoughData {
+            expected,
+            found: bytes.len(),
+        });
+    }
+    let size = layout.scalar.size();
+    Ok(DenseVolume {
+        sizes: layout.sizes,
+        channels: layout.channels,
+        values: bytes
+            .chunks_exact(size)
+            .map(|b| layout.scalar.decode(b, layout.endian))
+            .collect(),
+    })
+}
+
+/// Fields of NRRD header that we use
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NrrdHeader {
+    pub layout: RawLayout,
+    /// Bytes to skip before the data
+    pub byte_skip: usize,
+    /// File with data for detached headers, relative to the header file
+    pub data_file: Option<String>,
+}
+
+/// Parse header up to the empty line that separates it from attached data
+pub fn read_nrrd_header<R: BufRead>(reader: &mut R) -> Result<NrrdHeader, NrrdError> {
+    let invalid = |s: &str| NrrdError::InvalidHeader(s.to_owned());
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if !line.starts_with("NRRD000") {
+        return Err(NrrdError::NotNrrd);
+    }
+    let (mut scalar, mut dimension, mut sizes) = (None, None, None);
+    let mut endian = Endian::Little;
+    let mut byte_skip = 0;
+    let mut data_file = None;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        let text = line.trim_end_matches(['\r', '\n']);
+        if text.is_empty() {
+            break;
+        }
+        if text.starts_with('#') || text.contains(":=") {
+            continue;
+        }
+        let (key, value) = text
+            .split_once(": ")
+            .ok_or_else(|| invalid("field has no value"))?;
+        match key {
+            "type" => {
+                scalar = Some(
+                    ScalarType::from_nrrd_name(value)
+                        .ok_or_else(|| NrrdError::Unsupported(format!("type {}", value)))?,
+                )
+            }
+            "dimension" => dimension = Some(value.parse::<usize>().map_err(|_| invalid(key))?),
+            "sizes" => {
+                sizes = Some(
+                    value
+                        .split_whitespace()
+                        .map(|s| s.parse::<usize>())
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|_| invalid(key))?,
+                )
+            }
+            "encoding" if value != "raw" => {
+                return Err(NrrdError::Unsupported(format!("encoding {}", value)))
+            }
+            "endian" => {
+                endian = match value {
+                    "little" => Endian::Little,
+                    "big" => Endian::Big,
+                    _ => return Err(invalid(key)),
+                }
+            }
+            "byte skip" | "byteskip" => {
+                byte_skip = value
+                    .parse::<usize>()
+                    .map_err(|_| NrrdError::Unsupported(format!("byte skip {}", value)))?
+            }
+            "data file" | "datafile" => data_file = Some(value.to_owned()),
+            _ => {}
+        }
+    }
+    let scalar = scalar.ok_or_else(|| invalid("no type"))?;
+    let sizes = sizes.ok_or_else(|| invalid("no sizes"))?;
+    if dimension.is_some_and(|d| d != sizes.len()) {
+        return Err(invalid("sizes don't match dimension"));
+    }
+    let (channels, sizes) = match sizes[..] {
+        [x, y, z] => (1, [x, y, z]),
+        [c @ (1 | 3 | 4), x, y, z] => (c, [x, y, z]),
+        _ => {
+            return Err(NrrdError::Unsupported(format!(
+                "sizes {:?}, expected 3 axes and optional color axis",
+                sizes
+            )))
+        }
+    };
+    let layout = RawLayout {
+        sizes,
+        channels,
+        scalar,
+        endian,
+    };
+    if layout.byte_size().is_none() {
+        return Err(invalid("data size overflows"));
+    }
+    Ok(NrrdHeader {
+        layout,
+        byte_skip,
+        data_file,
+    })
+}
+
+/// Read NRRD with attached data
+pub fn read_nrrd_from<R: BufRead>(mut reader: R) -> Result<DenseVolume, NrrdError> {
+    let header = read_nrrd_header(&mut reader)?;
+    if header.data_file.is_some() {
+        return Err(NrrdError::Unsupported(
+            "detached data without header path".to_owned(),
+        ));
+    }
+    io::copy(
+        &mut reader.by_ref().take(header.byte_skip as u64),
+        &mut io::sink(),
+    )?;
+    read_raw(reader, header.layout)
+}
+
+/// Read NRRD file with attached or detached data
+pub fn read_nrrd<P: AsRef<Path>>(path: P) -> Result<DenseVolume, NrrdError> {
+    let path = path.as_ref();
+    let mut reader = BufReader::new(File::open(path)?);
+    let header = read_nrrd_header(&mut reader)?;
+    let mut data: Box<dyn Read> = match &header.data_file {
+        None => Box::new(reader),
+        Some(name) => {
+            let dir = path.parent().unwrap_or_else(|| Path::new(""));
+            Box::new(BufReader::new(File::open(dir.join(name))?))
+        }
+    };
+    io::copy(
+        &mut data.by_ref().take(header.byte_skip as u64),
+        &mut io::sink(),
+    )?;
+    read_raw(data, header.layout)
+}
+
+/// What is written for each voxel on export
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NrrdChannels {
+    /// Single `uint8`, 255 for drawn voxels and 0 for empty ones
+    #[default]
+    Occupancy,
+    /// Red, green and blue as 8-bit sRGB values
+    Rgb,
+}
+
+impl NrrdChannels {
+    fn count(&self) -> usize {
+        match self {
+            NrrdChannels::Occupancy => 1,
+            NrrdChannels::Rgb => 3,
+        }
+    }
+}
+
+/// Dense `uint8` data of the volume in NRRD order
+pub fn volume_to_raw(volume: &RleVolume, channels: NrrdChannels) -> Vec<u8> {
+    let voxels = volume.to_array();
+    let (xs, ys, zs) = voxels.dim();
+    let mut bytes = Vec::with_capacity(xs * ys * zs * channels.count());
+    for z in 0..zs {
+        for y in 0..ys {
+            for x in 0..xs {
+                let voxel = voxels[(x, y, z)];
+                match channels {
+                    NrrdChannels::Occupancy => bytes.push(if voxel.is_empty() { 0 } else { 255 }),
+                    NrrdChannels::Rgb => bytes.extend_from_slice(&voxel.to_rgba8()[..3]),
+                }
+            }
+        }
+    }
+    bytes
+}
+
+/// Text of NRRD header for `uint8` data of the volume, `data_file` makes detached header
+pub fn nrrd_header(volume: &RleVolume, channels: NrrdChannels, data_file: Option<&str>) -> String {
+    let [xs, ys, zs] = volume.dimensions();
+    let mut header = String::from("NRRD0004\n# Exported by rynda\ntype: uint8\n");
+    match channels {
+        NrrdChannels::Occupancy => header += &format!(
+            "dimension: 3\nsizes: {} {} {}\nkinds: domain domain domain\nspacings: 1 1 1\n",
+            xs, ys, zs
+        ),
+        NrrdChannels::Rgb => header += &format!(
+            "dimension: 4\nsizes: 3 {} {} {}\nkinds: RGB-color domain domain domain\nspacings: nan 1 1 1\n",
+            xs, ys, zs
+        ),
+    }
+    header += "encoding: raw\n";
+    if let Some(name) = data_file {
+        header += &format!("data file: {}\n", name);
+    }
+    header
+}
+
+/// Write NRRD with attached data
+pub fn write_nrrd<W: Write>(
+    mut writer: W,
+    volume: &RleVolume,
+    channels: NrrdChannels,
+) -> io::Result<()> {
+    writer.write_all(nrrd_header(volume, channels, None).as_bytes())?;
+    writer.write_all(b"\n")?;
+    writer.write_all(&volume_to_raw(volume, channels))
+}
+
+/// Write detached `.nhdr` header and `.raw` data next to it
+pub fn write_nrrd_detached<P: AsRef<Path>>(
+    header_path: P,
+    volume: &RleVolume,
+    channels: NrrdChannels,
+) -> io::Result<()> {
+    let header_path = header_path.as_ref();
+    let raw_path = header_path.with_extension("raw");
+    let raw_name = raw_path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid header path"))?;
+    std::fs::write(header_path, nrrd_header(volume, channels, Some(raw_name)))?;
+    std::fs::write(&raw_path, volume_to_raw(volume, channels))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn test_volume() -> RleVolume {
+        let voxels = Array3::from_shape_fn((5, 4, 3), |(x, y, z)| {
+            if (x + y + z) % 3 == 0 {
+                RgbVoxel::rgb(x as u8 * 6, y as u8 * 20, z as u8 * 10 + 1)
+            } else {
+                RgbVoxel::empty()
+            }
+        });
+        voxels.into()
+    }
+
+    #[test]
+    fn roundtrip_test() {
+        let volume = test_volume();
+        let mut bytes = vec![];
+        write_nrrd(&mut bytes, &volume, NrrdChannels::Rgb).unwrap();
+        let dense = read_nrrd_from(Cursor::new(bytes)).unwrap();
+        assert_eq!((dense.sizes, dense.channels), ([5, 4, 3], 3));
+        let restored = dense
+            .to_rle_volume(1.0, &TransferFunction::grayscale(0.0, 255.0))
+            .unwrap();
+        assert_eq!(restored.to_array(), volume.to_array());
+
+        let mut bytes = vec![];
+        write_nrrd(&mut bytes, &volume, NrrdChannels::Occupancy).unwrap();
+        let dense = read_nrrd_from(Cursor::new(bytes)).unwrap();
+        assert_eq!(dense.value(3, 0, 0, 0), 255.0);
+        assert_eq!(dense.value(1, 0, 0, 0), 0.0);
+        let white = TransferFunction::new(vec![(0.0, RgbVoxel::only_red(31))]);
+        let occupancy = dense.to_rle_volume(128.0, &white).unwrap();
+        for (a, b) in occupancy.to_array().iter().zip(volume.to_array().iter()) {
+            assert_eq!(a.is_empty(), b.is_empty());
+        }
+    }
+
+    #[test]
+    fn detached_test() {
+        let dir = std::env::temp_dir().join(format!("rynda-nrrd-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let header = dir.join("volume.nhdr");
+        let volume = test_volume();
+        write_nrrd_detached(&header, &volume, NrrdChannels::Rgb).unwrap();
+        assert!(dir.join("volume.raw").exists());
+        let dense = read_nrrd(&header).unwrap();
+        let restored = dense
+            .to_rle_volume(1.0, &TransferFunction::grayscale(0.0, 1.0))
+            .unwrap();
+        assert_eq!(restored.to_array(), volume.to_array());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn scalar_import_test() {
+        // CT-like big endian 16-bit data, value grows along X
+        let mut bytes = b"NRRD0005\n# comment\ntype: short\ndimension: 3\nsizes: 4 2 1\n\
+            endian: big\nencoding: raw\nspace:=ignored\n\n"
+            .to_vec();
+        for _ in 0..2 {
+            for v in [-1000i16, 0, 500, 1000] {
+                bytes.extend_from_slice(&v.to_be_bytes());
+            }
+        }
+        let dense = read_nrrd_from(Cursor::new(bytes.clone())).unwrap();
+        assert_eq!(dense.value(2, 1, 0, 0), 500.0);
+        let transfer = TransferFunction::new(vec![
+            (1000.0, RgbVoxel::rgb(31, 63, 31)),
+            (0.0, RgbVoxel::black()),
+        ]);
+        let volume = dense.to_rle_volume(0.0, &transfer).unwrap();
+        assert!(volume.get(0, 0, 0).is_empty(), "Below threshold");
+        assert_eq!(volume.get(1, 0, 0), RgbVoxel::black());
+        assert_eq!(volume.get(3, 1, 0), RgbVoxel::rgb(31, 63, 31));
+        let gray = volume.get(2, 0, 0);
+        assert!(gray.red() > 0 && gray.red() < 31);
+
+        bytes.truncate(bytes.len() - 1);
+        assert!(matches!(
+            read_nrrd_from(Cursor::new(bytes)),
+            Err(NrrdError::NotEnoughData {
+                expected: 16,
+                found: 15
+            })
+        ));
+        let gzip = b"NRRD0004\ntype: uint8\nsizes: 1 1 1\nencoding: gzip\n\n\0";
+        assert!(matches!(
+            read_nrrd_from(&gzip[..]),
+            Err(NrrdError::Unsupported(_))
+        ));
+    }
+    #[test]
+    fn oversized_header_test() {
+        let overflow =
+            b"NRRD0004\ntype: double\nsizes: 4294967296 4294967296 4294967296\nencoding: raw\n\n";
+        assert!(matches!(
+            read_nrrd_from(&overflow[..]),
+            Err(NrrdError::InvalidHeader(_))
+        ));
+        let layout = RawLayout {
+            sizes: [usize::MAX, 2, 1],
+            channels: 1,
+            scalar: ScalarType::UInt8,
+            endian: Endian::Little,
+        };
+        assert!(matches!(
+            read_raw(&[0u8; 4][..], layout),
+            Err(NrrdError::InvalidHeader(_))
+        ));
+
+        // Huge but representable size is not allocated before the data is there
+        let huge = b"NRRD0004\ntype: uint8\nsizes: 100000 100000 100000\nencoding: raw\n\n\x01\x02";
+        assert!(matches!(
+            read_nrrd_from(&huge[..]),
+            Err(NrrdError::NotEnoughData { found: 2, .. })
+        ));
+
+        let dense = DenseVolume {
+            sizes: [1024, 1, 1],
+            channels: 1,
+            values: vec![1.0; 1024],
+        };
+        assert!(matches!(
+            dense.to_rle_volume(0.0, &TransferFunction::grayscale(0.0, 1.0)),
+            Err(NrrdError::TooLarge {
+                sizes: [1024, 1, 1]
+            })
+        ));
+    }
+}
@@ -0,0 +1,363 @@
+use super::types::{
+    range::{RleRange, RLE_RANGE_SIZE},
+    volume::{PackedColumn, RleVolume},
+};
+use std::fmt;
+
+/// Magic bytes at the start of serialised patch
+const PATCH_MAGIC: &[u8; 4] = b"RVPT";
+
+/// Version of serialised patch layout
+const PATCH_VERSION: u8 = 1;
+
+/// Size of patch header: magic, version, dimensions, checksums and columns count
+const PATCH_HEADER_SIZE: usize = 4 + 1 + 3 * 4 + 2 * 8 + 4;
+
+/// Size of header of each column: index, rle count, first range and bytes length
+const COLUMN_HEADER_SIZE: usize = 4 + 2 + RLE_RANGE_SIZE + 4;
+
+/// Reasons why patch cannot be decoded or applied
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PatchError {
+    /// Serialised patch doesn't start with the magic bytes
+    BadMagic,
+    /// Serialised patch has unknown version
+    UnsupportedVersion(u8),
+    /// Serialised patch ends unexpectedly
+    Truncated,
+    /// Serialised patch has bytes after the last column
+    TrailingBytes(usize),
+    /// Patch is made for volume with other dimensions
+    DimensionsMismatch,
+    /// Volume is not the version the patch was made for
+    BaseMismatch { expected: u64, actual: u64 },
+    /// Patch refers to column outside of the volume
+    ColumnOutOfRange(usize),
+    /// Packed column bytes don't agree with its ranges
+    MalformedColumn(usize),
+    /// Volume after patching differs from the one the patch was made from
+    TargetMismatch,
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatchError::BadMagic => write!(f, "Not a volume patch"),
+            PatchError::UnsupportedVersion(v) => write!(f, "Unsupported patch version {}", v),
+            PatchError::Truncated => write!(f, "Patch is truncated"),
+            PatchError::TrailingBytes(n) => write!(f, "Patch has {} trailing bytes", n),
+            PatchError::DimensionsMismatch => write!(f, "Patch is made for other dimensions"),
+            PatchError::BaseMismatch { expected, actual } => write!(
+                f,
+                "Patch base checksum {:016x} doesn't match volume checksum {:016x}",
+                expected, actual
+            ),
+            PatchError::ColumnOutOfRange(i) => write!(f, "Patch column {} is out of range", i),
+            PatchError::MalformedColumn(i) => write!(f, "Patch column {} is malformed", i),
+            PatchError::TargetMismatch => write!(f, "Patched volume has unexpected checksum"),
+        }
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+/// Set of changed columns that turns one version of volume into another
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VolumePatch {
+    /// Dimensions of patched volume
+    pub size: [u32; 3],
+    /// Checksum of the volume the patch applies to
+    pub base_checksum: u64,
+    /// Checksum of the volume after patching
+    pub target_checksum: u64,
+    /// Changed columns by their indices in the pointers map, sorted by index
+    pub columns: Vec<(usize, PackedColumn)>,
+}
+
+/// 64 bit FNV-1a hash
+pub(crate) struct Fnv64(pub(crate) u64);
+
+impl Fnv64 {
+    pub(crate) fn new() -> Self {
+        Fnv64(0xcbf2_9ce4_8422_2325)
+    }
+
+    pub(crate) fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+}
+
+impl RleVolume {
+    /// Checksum of dimensions, RLE axis and contents of the volume that identifies its version
+    pub fn checksum(&self) -> u64 {
+        let mut hash = Fnv64::new();
+        hash.write(&self.xsize.to_le_bytes());
+        hash.write(&self.ysize.to_le_bytes());
+        hash.write(&self.zsize.to_le_bytes());
+        hash.write(&[self.axis.code()]);
+        for i in 0..self.columns_count() {
+            let pcol = self.pointer_column(i);
+            hash.write(&{ pcol.rle_count }.to_le_bytes());
+            hash.write(&{ pcol.first_range }.into_bytes());
+            hash.write(self.column_bytes(i));
+        }
+        hash.0
+    }
+
+    /// Find columns that differ in `other` volume. Applying the patch to this volume
+    /// produces `other` one. Both volumes must have the same dimensions.
+    pub fn diff(&self, other: &RleVolume) -> VolumePatch {
+        assert!(
+            self.xsize == other.xsize && self.ysize == other.ysize && self.zsize == other.zsize,
+            "Diff of volumes with different dimensions"
+        );
+        assert_eq!(
+            self.axis, other.axis,
+            "Diff of volumes with different RLE axes"
+        );
+        let columns = (0..self.columns_count())
+            .filter_map(|i| {
+                let new = other.packed_column(i);
+                if self.packed_column(i) == new {
+                    None
+                } else {
+                    Some((i, new))
+                }
+            })
+            .collect();
+        VolumePatch {
+            size: [self.xsize, self.ysize, self.zsize],
+            base_checksum: self.checksum(),
+            target_checksum: other.checksum(),
+            columns,
+        }
+    }
+
+    /// Apply patch made by `diff`. Volume is left untouched when the patch is rejected.
+    pub fn apply_patch(&mut self, patch: &VolumePatch) -> Result<(), PatchError> {
+        if patch.size != [self.xsize, self.ysize, self.zsize] {
+            return Err(PatchError::DimensionsMismatch);
+        }
+        let actual = self.checksum();
+        if actual != patch.base_checksum {
+            return Err(PatchError::BaseMismatch {
+                expected: patch.base_checksum,
+                actual,
+            });
+        }
+        for (index, column) in patch.columns.iter() {
+            if *index >= self.columns_count() {
+                return Err(PatchError::ColumnOutOfRange(*index));
+            }
+            if !column.is_valid(self.ysize) {
+                return Err(PatchError::MalformedColumn(*index));
+            }
+        }
+        let mut patched = self.clone();
+        patched.replace_columns(patch.columns.iter().map(|(i, c)| (*i, c.unpack())));
+        if patched.checksum() != patch.target_checksum {
+            return Err(PatchError::TargetMismatch);
+        }
+        *self = patched;
+        Ok(())
+    }
+}
+
+/// Cursor over serialised patch
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], PatchError> {
+        if self.bytes.len() < n {
+            return Err(PatchError::Truncated);
+        }
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn u16(&mut self) -> Result<u16, PatchError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, PatchError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, PatchError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+impl VolumePatch {
+    /// Check that patch changes nothing
+    pub fn is_empty(&self) -> bool {
+        self.columns.is_empty()
+    }
+
+    /// Serialise patch into compact little endian binary form:
+    ///
+    /// ```text
+    /// magic "RVPT", version: u8, xsize, ysize, zsize: u32,
+    /// base checksum, target checksum: u64, columns count: u32,
+    /// columns: [index: u32, rle count: u16, first range: 2 bytes, length: u32, bytes]
+    /// ```
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let body: usize = self
+            .columns
+            .iter()
+            .map(|(_, c)| COLUMN_HEADER_SIZE + c.bytes.len())
+            .sum();
+        let mut out = Vec::with_capacity(PATCH_HEADER_SIZE + body);
+        out.extend_from_slice(PATCH_MAGIC);
+        out.push(PATCH_VERSION);
+        for s in self.size {
+            out.extend_from_slice(&s.to_le_bytes());
+        }
+        out.extend_from_slice(&self.base_checksum.to_le_bytes());
+        out.extend_from_slice(&self.target_checksum.to_le_bytes());
+        out.extend_from_slice(&(self.columns.len() as u32).to_le_bytes());
+        for (index, column) in self.columns.iter() {
+            out.extend_from_slice(&(*index as u32).to_le_bytes());
+            out.extend_from_slice(&column.rle_count.to_le_bytes());
+            out.extend_from_slice(&column.first_range.into_bytes());
+            out.extend_from_slice(&(column.bytes.len() as u32).to_le_bytes());
+            out.extend_from_slice(&column.bytes);
+        }
+        out
+    }
+
+    /// Decode patch serialised with `to_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PatchError> {
+        let mut reader = Reader { bytes };
+        if reader.take(4)? != PATCH_MAGIC {
+            return Err(PatchError::BadMagic);
+        }
+        let version = reader.take(1)?[0];
+        if version != PATCH_VERSION {
+            return Err(PatchError::UnsupportedVersion(version));
+        }
+        let size = [reader.u32()?, reader.u32()?, reader.u32()?];
+        let base_checksum = reader.u64()?;
+        let target_checksum = reader.u64()?;
+        let count = reader.u32()? as usize;
+        // Don't trust the count for preallocation, it can be damaged
+        let mut columns = Vec::with_capacity(count.min(reader.bytes.len() / COLUMN_HEADER_SIZE));
+        for _ in 0..count {
+            let index = reader.u32()? as usize;
+            let rle_count = reader.u16()?;
+            let range = reader.take(RLE_RANGE_SIZE)?;
+            let first_range = RleRange::from_bytes([range[0], range[1]]);
+            let len = reader.u32()? as usize;
+            let column = PackedColumn {
+                rle_count,
+                first_range,
+                bytes: reader.take(len)?.to_vec(),
+            };
+            if !column.is_valid(size[1]) {
+                return Err(PatchError::MalformedColumn(index));
+            }
+            columns.push((index, column));
+        }
+        if !reader.bytes.is_empty() {
+            return Err(PatchError::TrailingBytes(reader.bytes.len()));
+        }
+        Ok(VolumePatch {
+            size,
+            base_checksum,
+            target_checksum,
+            columns,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brush::{Brush, BrushOp, BrushShape, BrushTransform};
+    use crate::types::voxel::RgbVoxel;
+
+    fn edited(volume: &RleVolume, center: [f32; 3]) -> RleVolume {
+        let mut other = volume.clone();
+        let brush = Brush::new(
+            BrushShape::Sphere { radius: 1.5 },
+            BrushTransform::at(center),
+        );
+        other.apply_brush(&brush, BrushOp::Add(RgbVoxel::only_blue(7)));
+        other
+    }
+
+    #[test]
+    fn diff_and_patch_test() {
+        let base = RleVolume::empty(8, 8, 8);
+        let target = edited(&base, [3.5, 3.5, 3.5]);
+        let patch = base.diff(&target);
+        assert_eq!(
+            patch.columns.len(),
+            9,
+            "Only changed columns are in the patch"
+        );
+        assert!(base.diff(&base).is_empty(), "Same volumes give empty patch");
+
+        let decoded = VolumePatch::from_bytes(&patch.to_bytes()).unwrap();
+        assert_eq!(decoded, patch, "Patch survives serialisation");
+
+        let mut replica = base.clone();
+        replica.apply_patch(&decoded).unwrap();
+        assert_eq!(replica.to_array(), target.to_array(), "Patched replica");
+        assert_eq!(replica.checksum(), target.checksum());
+    }
+
+    #[test]
+    fn wrong_base_test() {
+        let base = RleVolume::empty(8, 8, 8);
+        let target = edited(&base, [3.5, 3.5, 3.5]);
+        let patch = base.diff(&target);
+
+        let mut other = edited(&base, [6.5, 6.5, 6.5]);
+        let before = other.to_array();
+        assert!(matches!(
+            other.apply_patch(&patch),
+            Err(PatchError::BaseMismatch { .. })
+        ));
+        assert_eq!(other.to_array(), before, "Rejected patch changes nothing");
+
+        let mut small = RleVolume::empty(4, 8, 8);
+        assert_eq!(
+            small.apply_patch(&patch),
+            Err(PatchError::DimensionsMismatch)
+        );
+    }
+
+    #[test]
+    fn damaged_patch_test() {
+        let base = RleVolume::empty(8, 8, 8);
+        let patch = base.diff(&edited(&base, [3.5, 3.5, 3.5]));
+        let bytes = patch.to_bytes();
+
+        assert_eq!(
+            VolumePatch::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(PatchError::Truncated)
+        );
+        let mut long = bytes.clone();
+        long.extend_from_slice(&[0, 0]);
+        assert_eq!(
+            VolumePatch::from_bytes(&long),
+            Err(PatchError::TrailingBytes(2))
+        );
+        let mut bad = bytes.clone();
+        bad[0] = b'X';
+        assert_eq!(VolumePatch::from_bytes(&bad), Err(PatchError::BadMagic));
+        let mut bad = bytes;
+        bad[4] = 99;
+        assert_eq!(
+            VolumePatch::from_bytes(&bad),
+            Err(PatchError::UnsupportedVersion(99))
+        );
+    }
+}
@@ -0,0 +1,786 @@
+//! Region files keep a fixed grid of chunks of a large world in a single file. The file is
+//! split into sectors, every chunk is stored as volume file (see `file` module) in a run
+//! of sectors and has a checksum.
+//!
+//! The offset table is kept in two header slots with a generation counter. Saving never
+//! overwrites sectors that the active table refers to: new chunk data goes to free
+//! sectors, then the table is written into the inactive slot. A crash at any point leaves
+//! at least one slot that is valid and refers to intact data.
+use super::{
+    file::{volume_from_bytes, volume_to_bytes, Compression, VolumeFileError},
+    patch::Fnv64,
+    types::volume::RleVolume,
+};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Amount of chunks in a region along each axis
+pub const REGION_SIZE: u32 = 8;
+
+/// Amount of chunks in a region
+const REGION_CHUNKS: usize = (REGION_SIZE * REGION_SIZE * REGION_SIZE) as usize;
+
+/// Size of allocation unit of region file in bytes
+pub const SECTOR_SIZE: u64 = 4096;
+
+/// Magic bytes at the start of header slot
+const REGION_MAGIC: &[u8; 4] = b"RREG";
+
+/// Version of region file layout
+const REGION_VERSION: u8 = 1;
+
+/// Size of table entry: first sector, sectors count, data length and checksum
+const ENTRY_SIZE: usize = 4 + 4 + 4 + 8;
+
+/// Size of header slot: magic, version, reserved bytes, generation, table and checksum
+const SLOT_SIZE: usize = 4 + 1 + 3 + 8 + REGION_CHUNKS * ENTRY_SIZE + 8;
+
+/// Amount of sectors each header slot takes
+const SLOT_SECTORS: u32 = (SLOT_SIZE as u64).div_ceil(SECTOR_SIZE) as u32;
+
+/// Chunk data starts after both header slots
+const HEADER_SECTORS: u32 = 2 * SLOT_SECTORS;
+
+/// Reasons why region cannot be read or written
+#[derive(Debug)]
+pub enum RegionError {
+    /// Storage can't be read or written
+    Io(io::Error),
+    /// Chunk data cannot be encoded or decoded
+    Volume(VolumeFileError),
+    /// Neither of header slots is valid
+    NoValidHeader,
+    /// Header has unknown version
+    UnsupportedVersion(u8),
+    /// Table refers to sectors outside of the file or several chunks share sectors
+    Corrupted(&'static str),
+    /// Chunk coordinates are not less than `REGION_SIZE`
+    OutOfRegion([u32; 3]),
+    /// Stored chunk data doesn't match its checksum
+    ChecksumMismatch([u32; 3]),
+}
+
+impl fmt::Display for RegionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegionError::Io(e) => write!(f, "IO error: {}", e),
+            RegionError::Volume(e) => write!(f, "Chunk can't be decoded: {}", e),
+            RegionError::NoValidHeader => write!(f, "Region file has no valid header"),
+            RegionError::UnsupportedVersion(v) => {
+                write!(f, "Unsupported region file version {}", v)
+            }
+            RegionError::Corrupted(reason) => write!(f, "Region file is corrupted: {}", reason),
+            RegionError::OutOfRegion(c) => write!(f, "Chunk {:?} is outside of region", c),
+            RegionError::ChecksumMismatch(c) => write!(f, "Chunk {:?} is damaged", c),
+        }
+    }
+}
+
+impl std::error::Error for RegionError {}
+
+impl From<io::Error> for RegionError {
+    fn from(e: io::Error) -> Self {
+        RegionError::Io(e)
+    }
+}
+
+impl From<VolumeFileError> for RegionError {
+    fn from(e: VolumeFileError) -> Self {
+        RegionError::Volume(e)
+    }
+}
+
+/// Storage that region file lives in, e.g. file on disk or buffer in memory
+pub trait RegionStorage: Read + Write + Seek {
+    /// Truncate or extend the storage
+    fn set_len(&mut self, len: u64) -> io::Result<()>;
+
+    /// Make sure that everything written before reaches the storage
+    fn sync(&mut self) -> io::Result<()>;
+}
+
+impl RegionStorage for File {
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        File::set_len(self, len)
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        self.sync_data()
+    }
+}
+
+impl RegionStorage for Cursor<Vec<u8>> {
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        self.get_mut().resize(len as usize, 0);
+        Ok(())
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Location of chunk data in region file, chunk is absent when `sectors` is zero
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct RegionEntry {
+    first_sector: u32,
+    sectors: u32,
+    length: u32,
+    checksum: u64,
+}
+
+impl RegionEntry {
+    fn is_present(&self) -> bool {
+        self.sectors > 0
+    }
+
+    fn end(&self) -> u32 {
+        self.first_sector + self.sectors
+    }
+}
+
+fn checksum(bytes: &[u8]) -> u64 {
+    let mut hash = Fnv64::new();
+    hash.write(bytes);
+    hash.0
+}
+
+fn sectors_for(length: usize) -> u32 {
+    (length as u64).div_ceil(SECTOR_SIZE) as u32
+}
+
+/// Index of chunk in the table by its coordinates inside the region
+fn entry_index(local: [u32; 3]) -> Result<usize, RegionError> {
+    if local.iter().any(|c| *c >= REGION_SIZE) {
+        return Err(RegionError::OutOfRegion(local));
+    }
+    Ok((local[0] + local[1] * REGION_SIZE + local[2] * REGION_SIZE * REGION_SIZE) as usize)
+}
+
+/// Single region file with lazy access to its chunks
+pub struct RegionFile<S: RegionStorage> {
+    storage: S,
+    compression: Compression,
+    /// Header slot that holds the active table
+    active_slot: usize,
+    generation: u64,
+    table: Vec<RegionEntry>,
+}
+
+impl RegionFile<File> {
+    /// Open region file, it is created when missing
+    pub fn open<P: AsRef<Path>>(path: P, compression: Compression) -> Result<Self, RegionError> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        RegionFile::new(file, compression)
+    }
+}
+
+impl<S: RegionStorage> RegionFile<S> {
+    /// Read region from storage, empty storage is initialised as region without chunks.
+    /// Chunks are written with given compression, stored chunks can use any.
+    pub fn new(mut storage: S, compression: Compression) -> Result<Self, RegionError> {
+        let len = storage.seek(SeekFrom::End(0))?;
+        if len == 0 {
+            let mut region = RegionFile {
+                storage,
+                compression,
+                active_slot: 1,
+                generation: 0,
+                table: vec![RegionEntry::default(); REGION_CHUNKS],
+            };
+            region
+                .storage
+                .set_len(HEADER_SECTORS as u64 * SECTOR_SIZE)?;
+            region.commit(region.table.clone())?;
+            return Ok(region);
+        }
+
+        let mut best: Option<(usize, u64, Vec<RegionEntry>)> = None;
+        let mut version_error = None;
+        for slot in 0..2 {
+            match Self::read_slot(&mut storage, slot) {
+                Ok(Some((generation, table))) => {
+                    if best.as_ref().is_none_or(|b| generation > b.1) {
+                        best = Some((slot, generation, table));
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => version_error = Some(e),
+            }
+        }
+        let (active_slot, generation, table) = match (best, version_error) {
+            (Some(best), _) => best,
+            (None, Some(e)) => return Err(e),
+            (None, None) => return Err(RegionError::NoValidHeader),
+        };
+        let sectors = len.div_ceil(SECTOR_SIZE);
+        if table.iter().any(|e| {
+            e.is_present()
+                && (e.first_sector < HEADER_SECTORS
+                    || e.first_sector as u64 + e.sectors as u64 > sectors
+                    || e.length as u64 > e.sectors as u64 * SECTOR_SIZE)
+        }) {
+            return Err(RegionError::Corrupted("chunk is outside of sectors"));
+        }
+        // Overlapping runs break free space accounting and allocation could hand out
+        // sectors of a live chunk
+        let mut runs: Vec<(u32, u32)> = table
+            .iter()
+            .filter(|e| e.is_present())
+            .map(|e| (e.first_sector, e.end()))
+            .collect();
+        runs.sort_unstable();
+        if runs.windows(2).any(|w| w[1].0 < w[0].1) {
+            return Err(RegionError::Corrupted("chunks share sectors"));
+        }
+        Ok(RegionFile {
+            storage,
+            compression,
+            active_slot,
+            generation,
+            table,
+        })
+    }
+
+    /// Read header slot, `None` when the slot is damaged or was never written
+    fn read_slot(
+        storage: &mut S,
+        slot: usize,
+    ) -> Result<Option<(u64, Vec<RegionEntry>)>, RegionError> {
+        let mut bytes = vec![0; SLOT_SIZE];
+        storage.seek(SeekFrom::Start(
+            slot as u64 * SLOT_SECTORS as u64 * SECTOR_SIZE,
+        ))?;
+        match storage.read_exact(&mut bytes) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        let (body, sum) = bytes.split_at(SLOT_SIZE - 8);
+        if &body[0..4] != REGION_MAGIC
+            || checksum(body) != u64::from_le_bytes(sum.try_into().unwrap())
+        {
+            return Ok(None);
+        }
+        if body[4] != REGION_VERSION {
+            return Err(RegionError::UnsupportedVersion(body[4]));
+        }
+        let generation = u64::from_le_bytes(body[8..16].try_into().unwrap());
+        let table = body[16..]
+            .chunks_exact(ENTRY_SIZE)
+            .map(|e| RegionEntry {
+                first_sector: u32::from_le_bytes(e[0..4].try_into().unwrap()),
+                sectors: u32::from_le_bytes(e[4..8].try_into().unwrap()),
+                length: u32::from_le_bytes(e[8..12].try_into().unwrap()),
+                checksum: u64::from_le_bytes(e[12..20].try_into().unwrap()),
+            })
+            .collect();
+        Ok(Some((generation, table)))
+    }
+
+    /// Write table into inactive slot and make it active
+    fn commit(&mut self, table: Vec<RegionEntry>) -> Result<(), RegionError> {
+        let slot = 1 - self.active_slot;
+        let generation = self.generation + 1;
+        let mut bytes = Vec::with_capacity(SLOT_SIZE);
+        bytes.extend_from_slice(REGION_MAGIC);
+        bytes.extend_from_slice(&[REGION_VERSION, 0, 0, 0]);
+        bytes.extend_from_slice(&generation.to_le_bytes());
+        for e in table.iter() {
+            bytes.extend_from_slice(&e.first_sector.to_le_bytes());
+            bytes.extend_from_slice(&e.sectors.to_le_bytes());
+            bytes.extend_from_slice(&e.length.to_le_bytes());
+            bytes.extend_from_slice(&e.checksum.to_le_bytes());
+        }
+        bytes.extend_from_slice(&checksum(&bytes).to_le_bytes());
+
+        // Chunk data must reach the storage before the table that refers to it
+        self.storage.sync()?;
+        self.storage.seek(SeekFrom::Start(
+            slot as u64 * SLOT_SECTORS as u64 * SECTOR_SIZE,
+        ))?;
+        self.storage.write_all(&bytes)?;
+        self.storage.sync()?;
+        self.active_slot = slot;
+        self.generation = generation;
+        self.table = table;
+        Ok(())
+    }
+
+    /// Check that the chunk is stored in the region, chunks outside of the region are never
+    /// stored
+    pub fn contains(&self, local: [u32; 3]) -> bool {
+        entry_index(local).is_ok_and(|i| self.table[i].is_present())
+    }
+
+    /// Coordinates of stored chunks inside the region
+    pub fn chunks(&self) -> Vec<[u32; 3]> {
+        (0..REGION_CHUNKS)
+            .filter(|i| self.table[*i].is_present())
+            .map(|i| {
+                let i = i as u32;
+                [
+                    i % REGION_SIZE,
+                    i / REGION_SIZE % REGION_SIZE,
+                    i / (REGION_SIZE * REGION_SIZE),
+                ]
+            })
+            .collect()
+    }
+
+    /// Load chunk by its coordinates inside the region
+    pub fn read_chunk(&mut self, local: [u32; 3]) -> Result<Option<RleVolume>, RegionError> {
+        let entry = self.table[entry_index(local)?];
+        if !entry.is_present() {
+            return Ok(None);
+        }
+        let mut bytes = vec![0; entry.length as usize];
+        self.storage
+            .seek(SeekFrom::Start(entry.first_sector as u64 * SECTOR_SIZE))?;
+        self.storage.read_exact(&mut bytes)?;
+        if checksum(&bytes) != entry.checksum {
+            return Err(RegionError::ChecksumMismatch(local));
+        }
+        Ok(Some(volume_from_bytes(&bytes)?))
+    }
+
+    /// Save chunk, it is replaced atomically
+    pub fn write_chunk(&mut self, local: [u32; 3], volume: &RleVolume) -> Result<(), RegionError> {
+        self.write_chunks([(local, volume)])
+    }
+
+    /// Save several chunks, they are all replaced in a single atomic step
+    pub fn write_chunks<'a, I>(&mut self, chunks: I) -> Result<(), RegionError>
+    where
+        I: IntoIterator<Item = ([u32; 3], &'a RleVolume)>,
+    {
+        let chunks = chunks
+            .into_iter()
+            .map(|(local, volume)| Ok((entry_index(local)?, volume)))
+            .collect::<Result<Vec<_>, RegionError>>()?;
+        let mut table = self.table.clone();
+        // Sectors of the new data must not be reused inside the batch either
+        let mut reserved = vec![];
+        for (index, volume) in chunks {
+            let bytes = volume_to_bytes(volume, self.compression);
+            let sectors = sectors_for(bytes.len());
+            let first_sector = self.allocate(sectors, &reserved);
+            self.storage
+                .seek(SeekFrom::Start(first_sector as u64 * SECTOR_SIZE))?;
+            self.storage.write_all(&bytes)?;
+            let padding = (sectors as u64 * SECTOR_SIZE) as usize - bytes.len();
+            self.storage.write_all(&vec![0; padding])?;
+            let entry = RegionEntry {
+                first_sector,
+                sectors,
+                length: bytes.len() as u32,
+                checksum: checksum(&bytes),
+            };
+            reserved.push(entry);
+            table[index] = entry;
+        }
+        self.commit(table)
+    }
+
+    /// Remove chunk from the region, its sectors become free
+    pub fn remove_chunk(&mut self, local: [u32; 3]) -> Result<(), RegionError> {
+        let index = entry_index(local)?;
+        if !self.table[index].is_present() {
+            return Ok(());
+        }
+        let mut table = self.table.clone();
+        table[index] = RegionEntry::default();
+        self.commit(table)
+    }
+
+    /// Runs of used sectors sorted by start, including the header
+    fn used_runs(&self, reserved: &[RegionEntry]) -> Vec<(u32, u32)> {
+        let mut runs: Vec<(u32, u32)> = self
+            .table
+            .iter()
+            .chain(reserved.iter())
+            .filter(|e| e.is_present())
+            .map(|e| (e.first_sector, e.end()))
+            .collect();
+        runs.push((0, HEADER_SECTORS));
+        runs.sort_unstable();
+        runs
+    }
+
+    /// Find the first run of free sectors that fits given amount. Free sectors are not
+    /// referred by the active table, so writing there can't damage saved data.
+    fn allocate(&self, sectors: u32, reserved: &[RegionEntry]) -> u32 {
+        let mut end = 0;
+        for (start, run_end) in self.used_runs(reserved) {
+            if start >= end + sectors {
+                return end;
+            }
+            end = end.max(run_end);
+        }
+        end
+    }
+
+    /// Amount of sectors the file takes
+    pub fn total_sectors(&mut self) -> Result<u32, RegionError> {
+        Ok(self.storage.seek(SeekFrom::End(0))?.div_ceil(SECTOR_SIZE) as u32)
+    }
+
+    /// Amount of sectors that are not used by chunks or header
+    pub fn free_sectors(&mut self) -> Result<u32, RegionError> {
+        let used: u32 = self
+            .table
+            .iter()
+            .filter(|e| e.is_present())
+            .map(|e| e.sectors)
+            .sum();
+        Ok(self.total_sectors()? - used - HEADER_SECTORS)
+    }
+
+    /// Move chunks into free sectors closer to the start of the file and truncate the file.
+    /// Each move is committed separately, so a crash during compaction is harmless.
+    pub fn compact(&mut self) -> Result<(), RegionError> {
+        let mut order: Vec<usize> = (0..REGION_CHUNKS)
+            .filter(|i| self.table[*i].is_present())
+            .collect();
+        order.sort_by_key(|i| self.table[*i].first_sector);
+        for index in order {
+            let entry = self.table[index];
+            let target = self.allocate(entry.sectors, &[]);
+            if target >= entry.first_sector {
+                continue;
+            }
+            let mut bytes = vec![0; (entry.sectors as u64 * SECTOR_SIZE) as usize];
+            self.storage
+                .seek(SeekFrom::Start(entry.first_sector as u64 * SECTOR_SIZE))?;
+            self.storage.read_exact(&mut bytes)?;
+            self.storage
+                .seek(SeekFrom::Start(target as u64 * SECTOR_SIZE))?;
+            self.storage.write_all(&bytes)?;
+            let mut table = self.table.clone();
+            table[index].first_sector = target;
+            self.commit(table)?;
+        }
+        let end = self
+            .used_runs(&[])
+            .iter()
+            .map(|r| r.1)
+            .max()
+            .unwrap_or(HEADER_SECTORS);
+        self.storage.set_len(end as u64 * SECTOR_SIZE)?;
+        self.storage.sync()?;
+        Ok(())
+    }
+
+    /// Give back the storage
+    pub fn into_inner(self) -> S {
+        self.storage
+    }
+}
+
+/// Split chunk coordinates into coordinates of region and of chunk inside the region
+pub fn region_coords(chunk: [i32; 3]) -> ([i32; 3], [u32; 3]) {
+    let size = REGION_SIZE as i32;
+    (
+        chunk.map(|c| c.div_euclid(size)),
+        chunk.map(|c| c.rem_euclid(size) as u32),
+    )
+}
+
+/// Directory of region files that stores chunks of a world by their coordinates. Region
+/// files are opened on first access.
+pub struct RegionStore {
+    dir: PathBuf,
+    compression: Compression,
+    regions: HashMap<[i32; 3], RegionFile<File>>,
+}
+
+impl RegionStore {
+    /// Use directory for region files, it is created when missing
+    pub fn open<P: AsRef<Path>>(dir: P, compression: Compression) -> Result<Self, RegionError> {
+        fs::create_dir_all(dir.as_ref())?;
+        Ok(RegionStore {
+            dir: dir.as_ref().to_path_buf(),
+            compression,
+            regions: HashMap::new(),
+        })
+    }
+
+    /// Path of region file with given region coordinates
+    pub fn region_path(&self, region: [i32; 3]) -> PathBuf {
+        self.dir
+            .join(format!("r.{}.{}.{}.rreg", region[0], region[1], region[2]))
+    }
+
+    fn region(
+        &mut self,
+        region: [i32; 3],
+        create: bool,
+    ) -> Result<Option<&mut RegionFile<File>>, RegionError> {
+        if !self.regions.contains_key(&region) {
+            let path = self.region_path(region);
+            if !create && !path.exists() {
+                return Ok(None);
+            }
+            let file = RegionFile::open(path, self.compression)?;
+            self.regions.insert(region, file);
+        }
+        Ok(self.regions.get_mut(&region))
+    }
+
+    /// Load chunk by its world coordinates, `None` if it was never saved
+    pub fn load_chunk(&mut self, chunk: [i32; 3]) -> Result<Option<RleVolume>, RegionError> {
+        let (region, local) = region_coords(chunk);
+        match self.region(region, false)? {
+            Some(file) => file.read_chunk(local),
+            None => Ok(None),
+        }
+    }
+
+    /// Save chunk by its world coordinates
+    pub fn save_chunk(&mut self, chunk: [i32; 3], volume: &RleVolume) -> Result<(), RegionError> {
+        let (region, local) = region_coords(chunk);
+        self.region(region, true)?
+            .expect("Region is created")
+            .write_chunk(local, volume)
+    }
+
+    /// Save several chunks, chunks of each region are replaced in a single atomic step
+    pub fn save_chunks<'a, I>(&mut self, chunks: I) -> Result<(), RegionError>
+    where
+        I: IntoIterator<Item = ([i32; 3], &'a RleVolume)>,
+    {
+        let mut by_region: HashMap<[i32; 3], Vec<([u32; 3], &RleVolume)>> = HashMap::new();
+        for (chunk, volume) in chunks {
+            let (region, local) = region_coords(chunk);
+            by_region.entry(region).or_default().push((local, volume));
+        }
+        for (region, chunks) in by_region {
+            self.region(region, true)?
+                .expect("Region is created")
+                .write_chunks(chunks)?;
+        }
+        Ok(())
+    }
+
+    /// Remove chunk by its world coordinates
+    pub fn remove_chunk(&mut self, chunk: [i32; 3]) -> Result<(), RegionError> {
+        let (region, local) = region_coords(chunk);
+        match self.region(region, false)? {
+            Some(file) => file.remove_chunk(local),
+            None => Ok(()),
+        }
+    }
+
+    /// Compact all opened region files
+    pub fn compact(&mut self) -> Result<(), RegionError> {
+        for file in self.regions.values_mut() {
+            file.compact()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::voxel::RgbVoxel;
+    use ndarray::Array3;
+
+    fn chunk(seed: usize, height: usize) -> RleVolume {
+        let voxels = Array3::from_shape_fn((16, 32, 16), |(x, y, z)| {
+            if y < height + (x * seed + z) % 5 {
+                RgbVoxel::rgb((x + seed) as u8 % 32, y as u8, z as u8)
+            } else {
+                RgbVoxel::empty()
+            }
+        });
+        voxels.into()
+    }
+
+    fn memory_region() -> RegionFile<Cursor<Vec<u8>>> {
+        RegionFile::new(Cursor::new(vec![]), Compression::None).unwrap()
+    }
+
+    #[test]
+    fn chunks_round_trip() {
+        let mut region = memory_region();
+        let a = chunk(1, 3);
+        let b = chunk(2, 20);
+        region.write_chunk([0, 0, 0], &a).unwrap();
+        region
+            .write_chunks([([7, 1, 2], &b), ([3, 3, 3], &a)])
+            .unwrap();
+        assert_eq!(region.chunks(), vec![[0, 0, 0], [7, 1, 2], [3, 3, 3]]);
+        assert!(region.read_chunk([1, 0, 0]).unwrap().is_none());
+
+        let mut reopened = RegionFile::new(region.into_inner(), Compression::None).unwrap();
+        let loaded = reopened.read_chunk([7, 1, 2]).unwrap().unwrap();
+        assert_eq!(loaded.to_array(), b.to_array());
+        assert_eq!(
+            reopened.read_chunk([0, 0, 0]).unwrap().unwrap().to_array(),
+            a.to_array()
+        );
+
+        reopened.remove_chunk([7, 1, 2]).unwrap();
+        assert!(!reopened.contains([7, 1, 2]));
+    }
+
+    #[test]
+    fn crash_safety() {
+        let mut region = memory_region();
+        let old = chunk(1, 3);
+        region.write_chunk([1, 1, 1], &old).unwrap();
+        let saved = region.into_inner().into_inner();
+
+        // Crash while the new table is written: slot is damaged, old one is used
+        let mut region = RegionFile::new(Cursor::new(saved.clone()), Compression::None).unwrap();
+        region.write_chunk([1, 1, 1], &chunk(5, 25)).unwrap();
+        let mut crashed = region.into_inner().into_inner();
+        let slot = region_slot_of_newest(&crashed);
+        crashed[slot * SLOT_SECTORS as usize * SECTOR_SIZE as usize + 100] ^= 0xFF;
+        let mut recovered = RegionFile::new(Cursor::new(crashed), Compression::None).unwrap();
+        assert_eq!(
+            recovered.read_chunk([1, 1, 1]).unwrap().unwrap().to_array(),
+            old.to_array(),
+            "Previous version survives"
+        );
+
+        // Damaged chunk data is detected
+        let mut damaged = saved;
+        damaged[HEADER_SECTORS as usize * SECTOR_SIZE as usize + 40] ^= 0xFF;
+        let mut region = RegionFile::new(Cursor::new(damaged), Compression::None).unwrap();
+        assert!(matches!(
+            region.read_chunk([1, 1, 1]),
+            Err(RegionError::ChecksumMismatch([1, 1, 1]))
+        ));
+
+        let mut garbage = vec![0xAB; 3 * SLOT_SIZE];
+        garbage[0] = 0;
+        assert!(matches!(
+            RegionFile::new(Cursor::new(garbage), Compression::None),
+            Err(RegionError::NoValidHeader)
+        ));
+    }
+
+    #[test]
+    fn overlapping_chunks() {
+        let mut region = memory_region();
+        let a = chunk(1, 20);
+        region
+            .write_chunks([([0, 0, 0], &a), ([1, 0, 0], &a)])
+            .unwrap();
+        let mut table = region.table.clone();
+        let (first, second) = (
+            entry_index([0, 0, 0]).unwrap(),
+            entry_index([1, 0, 0]).unwrap(),
+        );
+        assert!(table[first].sectors > 1, "Chunk takes several sectors");
+        table[second].first_sector = table[first].end() - 1;
+        region.commit(table).unwrap();
+        assert!(matches!(
+            RegionFile::new(region.into_inner(), Compression::None),
+            Err(RegionError::Corrupted(_))
+        ));
+    }
+
+    #[test]
+    fn chunk_out_of_region() {
+        let mut region = memory_region();
+        let outside = [0, REGION_SIZE, 0];
+        assert!(!region.contains(outside));
+        assert!(matches!(
+            region.read_chunk(outside),
+            Err(RegionError::OutOfRegion(c)) if c == outside
+        ));
+        assert!(matches!(
+            region.write_chunks([([1, 1, 1], &chunk(1, 3)), (outside, &chunk(1, 3))]),
+            Err(RegionError::OutOfRegion(_))
+        ));
+        assert!(!region.contains([1, 1, 1]), "Nothing of the batch is saved");
+        assert!(matches!(
+            region.remove_chunk(outside),
+            Err(RegionError::OutOfRegion(_))
+        ));
+    }
+
+    /// Slot with the highest generation
+    fn region_slot_of_newest(bytes: &[u8]) -> usize {
+        let generation = |slot: usize| {
+            let start = slot * SLOT_SECTORS as usize * SECTOR_SIZE as usize + 8;
+            u64::from_le_bytes(bytes[start..start + 8].try_into().unwrap())
+        };
+        if generation(0) > generation(1) {
+            0
+        } else {
+            1
+        }
+    }
+
+    #[test]
+    fn compaction() {
+        let mut region = memory_region();
+        let chunks: Vec<RleVolume> = (0..6).map(|i| chunk(i, 4 * i + 2)).collect();
+        for (i, c) in chunks.iter().enumerate() {
+            region.write_chunk([i as u32, 0, 0], c).unwrap();
+        }
+        // Rewriting a chunk can't reuse its own sectors, so the file grows
+        region.write_chunk([5, 0, 0], &chunks[0]).unwrap();
+        for i in 0..3 {
+            region.remove_chunk([i, 0, 0]).unwrap();
+        }
+        let before = region.total_sectors().unwrap();
+        assert!(region.free_sectors().unwrap() > 0);
+
+        region.compact().unwrap();
+        assert_eq!(
+            region.free_sectors().unwrap(),
+            0,
+            "No gaps after compaction"
+        );
+        assert!(
+            region.total_sectors().unwrap() < before,
+            "File is truncated"
+        );
+        let mut reopened = RegionFile::new(region.into_inner(), Compression::None).unwrap();
+        for i in 3..5 {
+            assert_eq!(
+                reopened.read_chunk([i, 0, 0]).unwrap().unwrap().to_array(),
+                chunks[i as usize].to_array()
+            );
+        }
+        assert_eq!(
+            reopened.read_chunk([5, 0, 0]).unwrap().unwrap().to_array(),
+            chunks[0].to_array()
+        );
+    }
+
+    #[test]
+    fn store_test() {
+        let dir = std::env::temp_dir().join(format!("rynda-region-{}", std::process::id()));
+        let mut store = RegionStore::open(&dir, Compression::None).unwrap();
+        let a = chunk(3, 10);
+        assert!(store.load_chunk([-1, 0, 9]).unwrap().is_none());
+        store
+            .save_chunks([([-1, 0, 9], &a), ([0, 0, 0], &a), ([8, 0, 0], &a)])
+            .unwrap();
+        assert_eq!(region_coords([-1, 0, 9]), ([-1, 0, 1], [7, 0, 1]));
+        assert!(store.region_path([-1, 0, 1]).exists());
+
+        let mut reopened = RegionStore::open(&dir, Compression::None).unwrap();
+        assert_eq!(
+            reopened.load_chunk([-1, 0, 9]).unwrap().unwrap().to_array(),
+            a.to_array()
+        );
+        reopened.remove_chunk([8, 0, 0]).unwrap();
+        assert!(reopened.load_chunk([8, 0, 0]).unwrap().is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,688 @@
+//! Import of Sponge schematics (`.schem`) that store Minecraft builds as a block palette
+//! and varint encoded block indices inside gzip compressed NBT.
+//!
+//! Versions 1 to 3 of the format are supported. Minecraft Y axis points up as the model Y
+//! axis, so blocks map to voxels one to one. Reading compressed files requires the `schem`
+//! feature, already decompressed NBT can be parsed without it.
+use super::types::{volume::RleVolume, voxel::RgbVoxel};
+use ndarray::Array3;
+use std::{collections::HashMap, fmt, io};
+
+#[derive(Debug)]
+pub enum SchemError {
+    Io(io::Error),
+    /// NBT data ends too early or has unknown tags
+    Nbt(&'static str),
+    /// Schematic misses required field or the field has wrong type
+    MissingField(&'static str),
+    /// Block data doesn't match dimensions or refers to ids outside of the palette
+    InvalidBlockData(&'static str),
+    /// Schematic doesn't fit into single `RleVolume`, which is smaller than 1024 voxels along
+    /// each axis. Use `Schematic::to_chunks` for it.
+    TooLarge {
+        size: [usize; 3],
+    },
+    /// Chunk size must be from 1 to 1023
+    InvalidChunkSize(usize),
+}
+
+impl fmt::Display for SchemError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemError::Io(e) => write!(f, "IO error: {}", e),
+            SchemError::Nbt(reason) => write!(f, "Malformed NBT: {}", reason),
+            SchemError::MissingField(name) => write!(f, "Schematic has no valid {}", name),
+            SchemError::InvalidBlockData(reason) => write!(f, "Invalid block data: {}", reason),
+            SchemError::TooLarge { size } => write!(
+                f,
+                "Schematic of {}x{}x{} blocks is too large for single volume, split it into chunks",
+                size[0], size[1], size[2]
+            ),
+            SchemError::InvalidChunkSize(size) => write!(f, "Invalid chunk size {}", size),
+        }
+    }
+}
+
+impl std::error::Error for SchemError {}
+
+impl From<io::Error> for SchemError {
+    fn from(e: io::Error) -> Self {
+        SchemError::Io(e)
+    }
+}
+
+/// Value of NBT tag
+#[derive(Clone, Debug, PartialEq)]
+pub enum NbtTag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<u8>),
+    String(String),
+    List(Vec<NbtTag>),
+    Compound(HashMap<String, NbtTag>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl NbtTag {
+    /// Field of compound tag
+    pub fn get(&self, name: &str) -> Option<&NbtTag> {
+        match self {
+            NbtTag::Compound(fields) => fields.get(name),
+            _ => None,
+        }
+    }
+
+    /// Value of any integer tag
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            NbtTag::Byte(v) => Some(*v as i64),
+            NbtTag::Short(v) => Some(*v as i64),
+            NbtTag::Int(v) => Some(*v as i64),
+            NbtTag::Long(v) => Some(*v),
+            _ => None,
+        }
+    }
+}
+
+/// Reader of big endian NBT values
+struct NbtReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> NbtReader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], SchemError> {
+        if self.bytes.len() < n {
+            return Err(SchemError::Nbt("unexpected end of data"));
+        }
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], SchemError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn length(&mut self) -> Result<usize, SchemError> {
+        let len = i32::from_be_bytes(self.array()?);
+        usize::try_from(len).map_err(|_| SchemError::Nbt("negative length"))
+    }
+
+    fn string(&mut self) -> Result<String, SchemError> {
+        let len = u16::from_be_bytes(self.array()?) as usize;
+        // Modified UTF-8 differs only for null and supplementary characters
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+
+    fn payload(&mut self, tag: u8, depth: usize) -> Result<NbtTag, SchemError> {
+        if depth > 512 {
+            return Err(SchemError::Nbt("nesting is too deep"));
+        }
+        let value = match tag {
+            1 => NbtTag::Byte(self.take(1)?[0] as i8),
+            2 => NbtTag::Short(i16::from_be_bytes(self.array()?)),
+            3 => NbtTag::Int(i32::from_be_bytes(self.array()?)),
+            4 => NbtTag::Long(i64::from_be_bytes(self.array()?)),
+            5 => NbtTag::Float(f32::from_be_bytes(self.array()?)),
+            6 => NbtTag::Double(f64::from_be_bytes(self.array()?)),
+            7 => {
+                let len = self.length()?;
+                NbtTag::ByteArray(self.take(len)?.to_vec())
+            }
+            8 => NbtTag::String(self.string()?),
+            9 => {
+                let item = self.take(1)?[0];
+                let len = self.length()?;
+                let mut items = Vec::with_capacity(len.min(self.bytes.len()));
+                for _ in 0..len {
+                    items.push(self.payload(item, depth + 1)?);
+                }
+                NbtTag::List(items)
+            }
+            10 => {
+                let mut fields = HashMap::new();
+                loop {
+                    let field = self.take(1)?[0];
+                    if field == 0 {
+                        break;
+                    }
+                    let name = self.string()?;
+                    fields.insert(name, self.payload(field, depth + 1)?);
+                }
+                NbtTag::Compound(fields)
+            }
+            11 => {
+                let len = self.length()?;
+                let bytes = self.take(len.checked_mul(4).ok_or(SchemError::Nbt("too long"))?)?;
+                NbtTag::IntArray(
+                    bytes
+                        .chunks_exact(4)
+                        .map(|b| i32::from_be_bytes(b.try_into().unwrap()))
+                        .collect(),
+                )
+            }
+            12 => {
+                let len = self.length()?;
+                let bytes = self.take(len.checked_mul(8).ok_or(SchemError::Nbt("too long"))?)?;
+                NbtTag::LongArray(
+                    bytes
+                        .chunks_exact(8)
+                        .map(|b| i64::from_be_bytes(b.try_into().unwrap()))
+                        .collect(),
+                )
+            }
+            _ => return Err(SchemError::Nbt("unknown tag")),
+        };
+        Ok(value)
+    }
+}
+
+/// Parse uncompressed NBT into name and value of the root tag
+pub fn parse_nbt(bytes: &[u8]) -> Result<(String, NbtTag), SchemError> {
+    let mut reader = NbtReader { bytes };
+    let tag = reader.take(1)?[0];
+    if tag != 10 {
+        return Err(SchemError::Nbt("root is not a compound"));
+    }
+    let name = reader.string()?;
+    Ok((name, reader.payload(tag, 0)?))
+}
+
+/// Decode unsigned LEB128 varints that Sponge uses for block data
+fn decode_varints(bytes: &[u8]) -> Result<Vec<u32>, SchemError> {
+    let mut values = Vec::with_capacity(bytes.len());
+    let mut value = 0u32;
+    let mut shift = 0;
+    for b in bytes {
+        if shift >= 32 {
+            return Err(SchemError::InvalidBlockData("varint is too long"));
+        }
+        value |= ((b & 0x7F) as u32) << shift;
+        if b & 0x80 == 0 {
+            values.push(value);
+            value = 0;
+            shift = 0;
+        } else {
+            shift += 7;
+        }
+    }
+    if shift != 0 {
+        return Err(SchemError::InvalidBlockData("last varint is truncated"));
+    }
+    Ok(values)
+}
+
+/// Blocks of a schematic
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Schematic {
+    /// Size along X
+    pub width: usize,
+    /// Size along Y
+    pub height: usize,
+    /// Size along Z
+    pub length: usize,
+    /// Position of the schematic origin relative to the place it was copied from
+    pub offset: [i32; 3],
+    /// Block states by palette id, e.g. `minecraft:oak_stairs[facing=east]`
+    pub palette: Vec<String>,
+    /// Palette ids of blocks with X being the fastest axis, then Z and Y
+    pub blocks: Vec<u32>,
+}
+
+impl Schematic {
+    /// Read schematic from decompressed NBT
+    pub fn from_nbt(bytes: &[u8]) -> Result<Self, SchemError> {
+        let (_, root) = parse_nbt(bytes)?;
+        // Version 3 wraps everything into `Schematic` compound and moves blocks into
+        // `Blocks` compound
+        let schem = root.get("Schematic").unwrap_or(&root);
+        let container = schem.get("Blocks").unwrap_or(schem);
+        let size = |name| {
+            schem
+                .get(name)
+                .and_then(NbtTag::as_i64)
+                .map(|v| v as u16 as usize)
+                .ok_or(SchemError::MissingField(name))
+        };
+        let (width, height, length) = (size("Width")?, size("Height")?, size("Length")?);
+        let offset = match schem.get("Offset") {
+            Some(NbtTag::IntArray(v)) if v.len() == 3 => [v[0], v[1], v[2]],
+            _ => [0; 3],
+        };
+
+        let mut palette = vec![];
+        // Ids of a sparse palette can have gaps that blocks must not refer to
+        let mut filled = vec![];
+        match container.get("Palette") {
+            Some(NbtTag::Compound(entries)) => {
+                for (name, id) in entries {
+                    let id = id
+                        .as_i64()
+                        .and_then(|id| usize::try_from(id).ok())
+                        .filter(|id| *id < 1 << 20)
+                        .ok_or(SchemError::MissingField("Palette"))?;
+                    if palette.len() <= id {
+                        palette.resize(id + 1, String::new());
+                        filled.resize(id + 1, false);
+                    }
+                    palette[id] = name.clone();
+                    filled[id] = true;
+                }
+            }
+            _ => return Err(SchemError::MissingField("Palette")),
+        }
+        let data = match container.get("Data").or_else(|| container.get("BlockData")) {
+            Some(NbtTag::ByteArray(data)) => data,
+            _ => return Err(SchemError::MissingField("BlockData")),
+        };
+        let blocks = decode_varints(data)?;
+        if blocks.len() != width * height * length {
+            return Err(SchemError::InvalidBlockData(
+                "amount of blocks doesn't match dimensions",
+            ));
+        }
+        if blocks
+            .iter()
+            .any(|b| !filled.get(*b as usize).copied().unwrap_or(false))
+        {
+            return Err(SchemError::InvalidBlockData("block id is not in palette"));
+        }
+        Ok(Schematic {
+            width,
+            height,
+            length,
+            offset,
+            palette,
+            blocks,
+        })
+    }
+
+    /// Read schematic from gzip compressed NBT as stored in `.schem` files
+    #[cfg(feature = "schem")]
+    pub fn from_reader<R: io::Read>(reader: R) -> Result<Self, SchemError> {
+        let mut bytes = vec![];
+        io::Read::read_to_end(&mut flate2::read::GzDecoder::new(reader), &mut bytes)?;
+        Self::from_nbt(&bytes)
+    }
+
+    /// Read `.schem` file
+    #[cfg(feature = "schem")]
+    pub fn open<P: AsRef<std::path::Path>>(path: P) -> Result<Self, SchemError> {
+        Self::from_reader(io::BufReader::new(std::fs::File::open(path)?))
+    }
+
+    /// Block state at given position
+    pub fn block(&self, x: usize, y: usize, z: usize) -> &str {
+        let id = self.blocks[x + (z + y * self.length) * self.width];
+        &self.palette[id as usize]
+    }
+
+    /// Colors of palette entries
+    fn palette_colors(&self, colors: &BlockColors) -> Vec<RgbVoxel> {
+        self.palette.iter().map(|b| colors.color(b)).collect()
+    }
+
+    /// Make volume of the whole schematic. Schematics that are 1024 blocks or more along
+    /// any axis fail with `SchemError::TooLarge`, split them with `to_chunks`.
+    pub fn to_rle_volume(&self, colors: &BlockColors) -> Result<RleVolume, SchemError> {
+        let size = [self.width, self.height, self.length];
+        if size.iter().any(|s| *s >= 1024) {
+            return Err(SchemError::TooLarge { size });
+        }
+        let palette = self.palette_colors(colors);
+        let voxels = Array3::from_shape_fn((self.width, self.height, self.length), |(x, y, z)| {
+            palette[self.blocks[x + (z + y * self.length) * self.width] as usize]
+        });
+        Ok(voxels.into())
+    }
+
+    /// Split the schematic into chunks of given size for large builds. Chunk with
+    /// coordinates `c` starts at block `c * chunk_size`, chunks without drawn voxels are
+    /// skipped. Chunk size must be from 1 to 1023.
+    pub fn to_chunks(
+        &self,
+        colors: &BlockColors,
+        chunk_size: usize,
+    ) -> Result<Vec<([i32; 3], RleVolume)>, SchemError> {
+        if chunk_size == 0 || chunk_size >= 1024 {
+            return Err(SchemError::InvalidChunkSize(chunk_size));
+        }
+        let palette = self.palette_colors(colors);
+        let counts = [self.width, self.height, self.length].map(|s| s.div_ceil(chunk_size));
+        let mut chunks = vec![];
+        for cz in 0..counts[2] {
+            for cy in 0..counts[1] {
+                for cx in 0..counts[0] {
+                    let origin = [cx, cy, cz].map(|c| c * chunk_size);
+                    let end = [
+                        self.width.min(origin[0] + chunk_size),
+                        self.height.min(origin[1] + chunk_size),
+                        self.length.min(origin[2] + chunk_size),
+                    ];
+                    // Look for drawn blocks first, so empty chunks aren't allocated
+                    let drawn = (origin[1]..end[1]).any(|y| {
+                        (origin[2]..end[2]).any(|z| {
+                            let row = (z + y * self.length) * self.width;
+                            self.blocks[row + origin[0]..row + end[0]]
+                                .iter()
+                                .any(|b| !palette[*b as usize].is_empty())
+                        })
+                    });
+                    if !drawn {
+                        continue;
+                    }
+                    let voxels =
+                        Array3::from_shape_fn((chunk_size, chunk_size, chunk_size), |(x, y, z)| {
+                            let (x, y, z) = (origin[0] + x, origin[1] + y, origin[2] + z);
+                            if x >= end[0] || y >= end[1] || z >= end[2] {
+                                return RgbVoxel::empty();
+                            }
+                            palette[self.blocks[x + (z + y * self.length) * self.width] as usize]
+                        });
+                    chunks.push(([cx as i32, cy as i32, cz as i32], voxels.into()));
+                }
+            }
+        }
+        Ok(chunks)
+    }
+}
+
+/// Block that is never drawn
+fn is_air(id: &str) -> bool {
+    matches!(
+        id,
+        "minecraft:air" | "minecraft:cave_air" | "minecraft:void_air" | "minecraft:structure_void"
+    )
+}
+
+/// Colors of blocks by their ids without block state, e.g. `minecraft:stone`
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockColors {
+    pub colors: HashMap<String, RgbVoxel>,
+    /// Color of blocks that are not in the table, `None` skips them
+    pub fallback: Option<RgbVoxel>,
+}
+
+impl BlockColors {
+    /// Table without colors where all blocks are skipped
+    pub fn empty() -> Self {
+        BlockColors {
+            colors: HashMap::new(),
+            fallback: None,
+        }
+    }
+
+    /// Set color of block given as 8-bit sRGB
+    pub fn insert(&mut self, id: &str, rgb: [u8; 3]) {
+        self.colors.insert(
+            id.to_owned(),
+            RgbVoxel::from_rgba8([rgb[0], rgb[1], rgb[2], 255]),
+        );
+    }
+
+    /// Color of block state, block states and missing `minecraft:` namespace are ignored
+    pub fn color(&self, block: &str) -> RgbVoxel {
+        let id = block.split('[').next().unwrap_or(block);
+        let id = if id.contains(':') {
+            id.to_owned()
+        } else {
+            format!("minecraft:{}", id)
+        };
+        if is_air(&id) {
+            return RgbVoxel::empty();
+        }
+        self.colors
+            .get(&id)
+            .copied()
+            .or(self.fallback)
+            .unwrap_or_else(RgbVoxel::empty)
+    }
+}
+
+impl Default for BlockColors {
+    /// Approximate colors of common natural and building blocks, other blocks are gray
+    fn default() -> Self {
+        let mut table = BlockColors {
+            colors: HashMap::new(),
+            fallback: Some(RgbVoxel::from_rgba8([128, 128, 128, 255])),
+        };
+        let colors: [(&str, [u8; 3]); 40] = [
+            ("stone", [125, 125, 125]),
+            ("cobblestone", [122, 122, 122]),
+            ("granite", [149, 103, 85]),
+            ("diorite", [188, 188, 188]),
+            ("andesite", [136, 136, 136]),
+            ("deepslate", [80, 80, 82]),
+            ("bedrock", [85, 85, 85]),
+            ("grass_block", [95, 159, 53]),
+            ("dirt", [134, 96, 67]),
+            ("coarse_dirt", [119, 85, 59]),
+            ("podzol", [91, 63, 24]),
+            ("mycelium", [111, 98, 101]),
+            ("sand", [219, 207, 163]),
+            ("red_sand", [190, 102, 33]),
+            ("sandstone", [216, 203, 155]),
+            ("gravel", [131, 127, 126]),
+            ("clay", [160, 166, 179]),
+            ("snow", [249, 254, 254]),
+            ("snow_block", [249, 254, 254]),
+            ("ice", [145, 183, 253]),
+            ("packed_ice", [141, 180, 250]),
+            ("water", [63, 118, 228]),
+            ("lava", [207, 92, 20]),
+            ("oak_log", [109, 85, 50]),
+            ("spruce_log", [58, 37, 16]),
+            ("birch_log", [216, 215, 210]),
+            ("oak_planks", [162, 130, 78]),
+            ("spruce_planks", [114, 84, 48]),
+            ("birch_planks", [192, 175, 121]),
+            ("oak_leaves", [60, 120, 30]),
+            ("spruce_leaves", [60, 90, 60]),
+            ("birch_leaves", [80, 130, 50]),
+            ("glass", [200, 220, 230]),
+            ("bricks", [150, 97, 83]),
+            ("stone_bricks", [122, 121, 122]),
+            ("obsidian", [15, 10, 24]),
+            ("netherrack", [97, 38, 38]),
+            ("white_wool", [233, 236, 236]),
+            ("iron_block", [220, 220, 220]),
+            ("gold_block", [246, 208, 61]),
+        ];
+        for (id, rgb) in colors {
+            table.insert(&format!("minecraft:{}", id), rgb);
+        }
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(out: &mut Vec<u8>, tag: u8, name: &str) {
+        out.push(tag);
+        out.extend_from_slice(&(name.len() as u16).to_be_bytes());
+        out.extend_from_slice(name.as_bytes());
+    }
+
+    fn short(out: &mut Vec<u8>, field: &str, v: i16) {
+        name(out, 2, field);
+        out.extend_from_slice(&v.to_be_bytes());
+    }
+
+    /// Schematic of 3x2x2 blocks: stone floor, one glass block and unknown block on top
+    fn schematic_nbt(version: i32) -> Vec<u8> {
+        let mut blocks = vec![];
+        for y in 0..2 {
+            for _z in 0..2 {
+                for x in 0..3 {
+                    blocks.push(match (y, x) {
+                        (0, _) => 1u8,
+                        (1, 0) => 2,
+                        // Id 200 takes two varint bytes
+                        (1, 2) => 0xC8,
+                        _ => 0,
+                    });
+                    if blocks.last() == Some(&0xC8) {
+                        blocks.push(0x01);
+                    }
+                }
+            }
+        }
+        let mut palette = vec![];
+        for (block, id) in [
+            ("minecraft:air", 0i32),
+            ("minecraft:stone", 1),
+            ("minecraft:glass", 2),
+            ("mymod:crystal[lit=true]", 200),
+        ] {
+            name(&mut palette, 3, block);
+            palette.extend_from_slice(&id.to_be_bytes());
+        }
+        palette.push(0);
+
+        let mut body = vec![];
+        name(&mut body, 3, "Version");
+        body.extend_from_slice(&version.to_be_bytes());
+        short(&mut body, "Width", 3);
+        short(&mut body, "Height", 2);
+        short(&mut body, "Length", 2);
+        name(&mut body, 11, "Offset");
+        body.extend_from_slice(&3i32.to_be_bytes());
+        for v in [1i32, -2, 3] {
+            body.extend_from_slice(&v.to_be_bytes());
+        }
+        let mut block_fields = vec![];
+        name(&mut block_fields, 10, "Palette");
+        block_fields.extend_from_slice(&palette);
+        name(
+            &mut block_fields,
+            7,
+            if version == 3 { "Data" } else { "BlockData" },
+        );
+        block_fields.extend_from_slice(&(blocks.len() as i32).to_be_bytes());
+        block_fields.extend_from_slice(&blocks);
+        if version == 3 {
+            name(&mut body, 10, "Blocks");
+            body.extend_from_slice(&block_fields);
+            body.push(0);
+        } else {
+            body.extend_from_slice(&block_fields);
+        }
+        body.push(0);
+
+        let mut out = vec![];
+        if version == 3 {
+            name(&mut out, 10, "");
+            name(&mut out, 10, "Schematic");
+            out.extend_from_slice(&body);
+            out.push(0);
+        } else {
+            name(&mut out, 10, "Schematic");
+            out.extend_from_slice(&body);
+        }
+        out
+    }
+
+    #[test]
+    fn parse_test() {
+        for version in [2, 3] {
+            let schem = Schematic::from_nbt(&schematic_nbt(version)).unwrap();
+            assert_eq!((schem.width, schem.height, schem.length), (3, 2, 2));
+            assert_eq!(schem.offset, [1, -2, 3]);
+            assert_eq!(schem.block(1, 0, 1), "minecraft:stone");
+            assert_eq!(schem.block(0, 1, 1), "minecraft:glass");
+            assert_eq!(schem.block(2, 1, 0), "mymod:crystal[lit=true]");
+
+            let colors = BlockColors::default();
+            let volume = schem.to_rle_volume(&colors).unwrap();
+            assert_eq!(volume.dimensions(), [3, 2, 2]);
+            assert_eq!(volume.get(1, 0, 1), colors.color("minecraft:stone"));
+            assert_eq!(volume.get(0, 1, 0), colors.color("glass"));
+            assert_eq!(volume.get(2, 1, 0), colors.fallback.unwrap());
+            assert!(volume.get(1, 1, 0).is_empty(), "Air is empty");
+
+            let mut only_stone = BlockColors::empty();
+            only_stone.insert("minecraft:stone", [255, 0, 0]);
+            let volume = schem.to_rle_volume(&only_stone).unwrap();
+            assert_eq!(volume.get(0, 0, 0), RgbVoxel::only_red(31));
+            assert!(volume.get(2, 1, 0).is_empty(), "Unknown blocks are skipped");
+        }
+    }
+
+    #[test]
+    fn chunks_test() {
+        let schem = Schematic::from_nbt(&schematic_nbt(2)).unwrap();
+        let colors = BlockColors::default();
+        let chunks = schem.to_chunks(&colors, 2).unwrap();
+        let coords: Vec<_> = chunks.iter().map(|(c, _)| *c).collect();
+        assert_eq!(coords, vec![[0, 0, 0], [1, 0, 0]]);
+        assert_eq!(chunks[1].1.get(0, 1, 0), colors.fallback.unwrap());
+        assert!(chunks[1].1.get(1, 0, 0).is_empty(), "Chunk is padded");
+
+        let mut only_glass = BlockColors::empty();
+        only_glass.insert("minecraft:glass", [200, 200, 255]);
+        let chunks = schem.to_chunks(&only_glass, 1).unwrap();
+        assert!(
+            chunks.iter().all(|(_, c)| !c.get(0, 0, 0).is_empty()),
+            "Chunks without drawn blocks are skipped"
+        );
+        assert!(!chunks.is_empty() && chunks.len() < 12);
+
+        for size in [0, 1024] {
+            assert!(matches!(
+                schem.to_chunks(&colors, size),
+                Err(SchemError::InvalidChunkSize(s)) if s == size
+            ));
+        }
+        let large = Schematic {
+            width: 1024,
+            height: 1,
+            length: 1,
+            offset: [0; 3],
+            palette: vec!["minecraft:stone".to_owned()],
+            blocks: vec![0; 1024],
+        };
+        assert!(matches!(
+            large.to_rle_volume(&colors),
+            Err(SchemError::TooLarge { size: [1024, 1, 1] })
+        ));
+        assert_eq!(large.to_chunks(&colors, 64).unwrap().len(), 16);
+    }
+
+    #[test]
+    fn invalid_test() {
+        let bytes = schematic_nbt(2);
+        assert!(matches!(
+            Schematic::from_nbt(&bytes[..bytes.len() - 5]),
+            Err(SchemError::Nbt(_))
+        ));
+        // Block of id 150 that lies in the gap of the palette before 200
+        let mut gap = bytes.clone();
+        let at = gap.windows(2).position(|w| w == [0xC8, 0x01]).unwrap();
+        gap[at] = 0x96;
+        assert!(matches!(
+            Schematic::from_nbt(&gap),
+            Err(SchemError::InvalidBlockData(_))
+        ));
+        assert_eq!(decode_varints(&[0x80, 0x01, 0x05]).unwrap(), vec![128, 5]);
+        assert!(decode_varints(&[0x80]).is_err());
+    }
+
+    #[cfg(feature = "schem")]
+    #[test]
+    fn gzip_test() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(&schematic_nbt(3)).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let schem = Schematic::from_reader(compressed.as_slice()).unwrap();
+        assert_eq!(schem.palette.len(), 201);
+    }
+}
@@ -0,0 +1,293 @@
+use super::types::volume::RleVolume;
+use ndarray::{Array3, Axis};
+
+/// Squared distance that stands for "no target voxel on this line"
+const FAR: f32 = 1e20;
+
+/// Squared distances along the column to the nearest voxel of the given spans. Spans are
+/// half open `[start, end)` intervals sorted by height and may lie outside of the column.
+fn column_distances(spans: &[(i64, i64)], out: &mut [f32]) {
+    let mut next = 0;
+    for (y, d) in out.iter_mut().enumerate() {
+        let y = y as i64;
+        while next < spans.len() && spans[next].1 <= y {
+            next += 1;
+        }
+        let below = if next > 0 {
+            Some(y - (spans[next - 1].1 - 1))
+        } else {
+            None
+        };
+        let above = spans.get(next).map(|s| (s.0 - y).max(0));
+        *d = match (below, above) {
+            (None, None) => FAR,
+            (Some(a), None) | (None, Some(a)) => (a * a) as f32,
+            (Some(a), Some(b)) => (a.min(b) * a.min(b)) as f32,
+        };
+    }
+}
+
+/// One dimensional squared distance transform of sampled function by Felzenszwalb and
+/// Huttenlocher. `f` contains squared distances that are already known for each sample.
+fn distance_transform_1d(f: &[f32]) -> Vec<f32> {
+    let n = f.len();
+    let mut d = vec![FAR; n];
+    if n == 0 {
+        return d;
+    }
+    let mut v = vec![0usize; n];
+    let mut z = vec![0f32; n + 1];
+    let mut k = 0;
+    z[0] = f32::NEG_INFINITY;
+    z[1] = f32::INFINITY;
+    let intersect = |q: usize, p: usize| {
+        ((f[q] + (q * q) as f32) - (f[p] + (p * p) as f32)) / (2.0 * q as f32 - 2.0 * p as f32)
+    };
+    for q in 1..n {
+        let mut s = intersect(q, v[k]);
+        while s <= z[k] {
+            k -= 1;
+            s = intersect(q, v[k]);
+        }
+        k += 1;
+        v[k] = q;
+        z[k] = s;
+        z[k + 1] = f32::INFINITY;
+    }
+    k = 0;
+    for (q, dq) in d.iter_mut().enumerate() {
+        while z[k + 1] < q as f32 {
+            k += 1;
+        }
+        let diff = q as f32 - v[k] as f32;
+        *dq = (diff * diff + f[v[k]]).min(FAR);
+    }
+    d
+}
+
+/// Run transform along the given axis of the grid. When `border_is_target` is set, space
+/// right outside of the grid is considered to consist of target voxels.
+fn transform_axis(grid: &mut Array3<f32>, axis: usize, border_is_target: bool) {
+    let pad = if border_is_target { 1 } else { 0 };
+    for mut lane in grid.lanes_mut(Axis(axis)) {
+        let mut f = Vec::with_capacity(lane.len() + 2 * pad);
+        if border_is_target {
+            f.push(0.0);
+        }
+        f.extend(lane.iter().copied());
+        if border_is_target {
+            f.push(0.0);
+        }
+        let d = distance_transform_1d(&f);
+        for (v, dv) in lane.iter_mut().zip(d[pad..].iter()) {
+            *v = *dv;
+        }
+    }
+}
+
+/// Squared euclidean distances from each voxel center to the nearest filled (or empty when
+/// `to_filled` is false) voxel center. The first pass along Y is taken from RLE ranges.
+fn squared_distances(volume: &RleVolume, to_filled: bool) -> Array3<f32> {
+    let (xsize, ysize, zsize) = (
+        volume.xsize as usize,
+        volume.ysize as usize,
+        volume.zsize as usize,
+    );
+    let mut grid = Array3::from_elem((xsize, ysize, zsize), FAR);
+    let mut column_buf = vec![FAR; ysize];
+    for i in 0..volume.columns_count() {
+        let x = i % xsize;
+        let z = i / xsize;
+        let column = volume.column_at(i);
+        let mut filled = vec![];
+        let mut empty = vec![(-1, 0)];
+        let mut y = 0i64;
+        for range in column.ranges.iter() {
+            let skipped = range.skipped() as i64;
+            let drawn = range.drawn() as i64;
+            if skipped > 0 {
+                empty.push((y, y + skipped));
+            }
+            y += skipped;
+            if drawn > 0 {
+                filled.push((y, y + drawn));
+            }
+            y += drawn;
+        }
+        empty.push((y.min(ysize as i64), ysize as i64 + 1));
+        column_distances(if to_filled { &filled } else { &empty }, &mut column_buf);
+        for (y, d) in column_buf.iter().enumerate() {
+            grid[(x, y, z)] = *d;
+        }
+    }
+    transform_axis(&mut grid, 0, !to_filled);
+    transform_axis(&mut grid, 2, !to_filled);
+    grid
+}
+
+/// Calculate exact euclidean signed distance field of the volume with the same resolution.
+/// Distances are measured in voxels to the surface of voxel cubes: positive outside, negative
+/// inside and ±0.5 in voxels that touch the surface. Space outside of the volume is empty.
+/// Empty volume has `f32::INFINITY` everywhere.
+pub fn distance_field(volume: &RleVolume) -> Array3<f32> {
+    let outside = squared_distances(volume, true);
+    let inside = squared_distances(volume, false);
+    let mut field = outside;
+    field.zip_mut_with(&inside, |out, ins| {
+        *out = if *out == 0.0 {
+            0.5 - ins.sqrt()
+        } else if *out >= FAR {
+            f32::INFINITY
+        } else {
+            out.sqrt() - 0.5
+        };
+    });
+    // Distances are computed in layout of the volume, bring them to model coordinates
+    field
+        .permuted_axes(volume.axis.permutation())
+        .as_standard_layout()
+        .into_owned()
+}
+
+/// Calculate low resolution signed distance field where each cell covers `scale`^3 voxels.
+/// Each cell keeps the smallest distance of its voxels, so the field never overestimates
+/// distance to the surface and is safe for sphere tracing. Distances are in voxels of the
+/// original volume.
+pub fn signed_distance_field(volume: &RleVolume, scale: usize) -> Array3<f32> {
+    assert!(scale > 0, "Scale of distance field must be positive");
+    let field = distance_field(volume);
+    let (xsize, ysize, zsize) = field.dim();
+    let mut low = Array3::from_elem(
+        (
+            xsize.div_ceil(scale),
+            ysize.div_ceil(scale),
+            zsize.div_ceil(scale),
+        ),
+        f32::INFINITY,
+    );
+    for ((x, y, z), d) in field.indexed_iter() {
+        let cell = &mut low[(x / scale, y / scale, z / scale)];
+        *cell = cell.min(*d);
+    }
+    low
+}
+
+/// Quantize distance field into bytes. Distances in range `-max_distance .. max_distance`
+/// are mapped linearly into `0 .. 255` and clamped outside of it, so the surface is at 128.
+pub fn quantize_sdf(field: &Array3<f32>, max_distance: f32) -> Array3<u8> {
+    field.map(|d| {
+        let t = (d / max_distance).clamp(-1.0, 1.0);
+        (128.0 + t * 127.0).round() as u8
+    })
+}
+
+/// Restore distance from quantized form made by `quantize_sdf`
+pub fn dequantize_sdf(value: u8, max_distance: f32) -> f32 {
+    (value as f32 - 128.0) / 127.0 * max_distance
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::voxel::RgbVoxel;
+
+    /// Brute force signed distance for comparison
+    fn brute_force(voxels: &Array3<RgbVoxel>) -> Array3<f32> {
+        let (xsize, ysize, zsize) = voxels.dim();
+        let nearest = |x: usize, y: usize, z: usize, filled: bool| {
+            let mut best = f32::INFINITY;
+            for ((i, j, k), v) in voxels.indexed_iter() {
+                if v.is_empty() != filled {
+                    let d = ((i as f32 - x as f32).powi(2)
+                        + (j as f32 - y as f32).powi(2)
+                        + (k as f32 - z as f32).powi(2))
+                    .sqrt();
+                    best = best.min(d);
+                }
+            }
+            if !filled {
+                // Border of the volume is empty
+                let dims = [xsize, ysize, zsize];
+                for (a, p) in [x, y, z].iter().enumerate() {
+                    best = best.min((*p + 1) as f32).min((dims[a] - p) as f32);
+                }
+            }
+            best
+        };
+        Array3::from_shape_fn((xsize, ysize, zsize), |(x, y, z)| {
+            if voxels[(x, y, z)].is_empty() {
+                nearest(x, y, z, true) - 0.5
+            } else {
+                0.5 - nearest(x, y, z, false)
+            }
+        })
+    }
+
+    fn sphere(size: usize, radius: f32) -> Array3<RgbVoxel> {
+        let c = size as f32 / 2.0;
+        Array3::from_shape_fn((size, size + 2, size), |(x, y, z)| {
+            let d = (x as f32 + 0.5 - c).powi(2)
+                + (y as f32 + 0.5 - c).powi(2)
+                + (z as f32 + 0.5 - c).powi(2);
+            if d.sqrt() < radius {
+                RgbVoxel::only_red(1)
+            } else {
+                RgbVoxel::empty()
+            }
+        })
+    }
+
+    #[test]
+    fn distance_field_test() {
+        let voxels = sphere(10, 3.5);
+        let volume: RleVolume = voxels.clone().into();
+        let field = distance_field(&volume);
+        let expected = brute_force(&voxels);
+        for (idx, d) in field.indexed_iter() {
+            assert!(
+                (d - expected[idx]).abs() < 1e-4,
+                "Distance at {:?} is {}, expected {}",
+                idx,
+                d,
+                expected[idx]
+            );
+        }
+    }
+
+    #[test]
+    fn empty_field_test() {
+        let field = distance_field(&RleVolume::empty(2, 3, 4));
+        assert!(
+            field.iter().all(|d| d.is_infinite()),
+            "Empty volume is infinitely far"
+        );
+    }
+
+    #[test]
+    fn low_res_field_test() {
+        let voxels = sphere(16, 3.5);
+        let volume: RleVolume = voxels.into();
+        let field = distance_field(&volume);
+        let low = signed_distance_field(&volume, 4);
+        assert_eq!(low.dim(), (4, 5, 4), "Low resolution dimensions");
+        for ((x, y, z), d) in field.indexed_iter() {
+            assert!(
+                low[(x / 4, y / 4, z / 4)] <= *d,
+                "Low resolution field overestimates distance"
+            );
+        }
+        assert!(low[(2, 2, 2)] < 0.0, "Center is inside");
+        assert!(low[(0, 0, 0)] > 0.0, "Corner is outside");
+    }
+
+    #[test]
+    fn quantize_test() {
+        let field = Array3::from_shape_vec((1, 1, 4), vec![-10.0, 0.0, 2.0, 5.0]).unwrap();
+        let q = quantize_sdf(&field, 4.0);
+        assert_eq!(q.into_raw_vec(), vec![1, 128, 192, 255]);
+        assert!(
+            (dequantize_sdf(192, 4.0) - 2.0).abs() < 4.0 / 127.0,
+            "Dequantized distance is within one step"
+        );
+    }
+}
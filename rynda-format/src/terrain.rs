@@ -0,0 +1,427 @@
+use super::types::{column::RleColumn, source::VoxelSource, volume::RleVolume, voxel::RgbVoxel};
+
+/// Parameters of generated terrain. The same settings always give the same terrain.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TerrainSettings {
+    /// Seed of all noise functions
+    pub seed: u64,
+    /// Size of generated chunks in voxels
+    pub chunk_size: [u32; 3],
+    /// Height of the plains in voxels
+    pub base_height: f32,
+    /// Amplitude of hills in voxels
+    pub height_scale: f32,
+    /// Frequency of the largest hills, waves per voxel
+    pub frequency: f32,
+    /// Amount of noise octaves
+    pub octaves: u32,
+    /// Frequency multiplier between octaves
+    pub lacunarity: f32,
+    /// Amplitude multiplier between octaves
+    pub gain: f32,
+    /// Part of height that comes from ridged noise, which makes sharp mountain ridges
+    pub ridges: f32,
+    /// Frequency of cave tunnels, waves per voxel
+    pub cave_frequency: f32,
+    /// Width of cave tunnels, zero disables caves
+    pub cave_width: f32,
+    /// Voxels below this height that are not filled by ground are water
+    pub sea_level: i32,
+}
+
+impl Default for TerrainSettings {
+    fn default() -> Self {
+        TerrainSettings {
+            seed: 0,
+            chunk_size: [64, 64, 64],
+            base_height: 24.0,
+            height_scale: 32.0,
+            frequency: 1.0 / 128.0,
+            octaves: 4,
+            lacunarity: 2.0,
+            gain: 0.5,
+            ridges: 0.3,
+            cave_frequency: 1.0 / 32.0,
+            cave_width: 0.08,
+            sea_level: 16,
+        }
+    }
+}
+
+/// Climate zone of terrain that defines colors of its surface
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Biome {
+    Desert,
+    Plains,
+    Forest,
+    Tundra,
+}
+
+impl Biome {
+    /// Color of the top voxel
+    fn surface(&self) -> RgbVoxel {
+        match self {
+            Biome::Desert => RgbVoxel::rgb(27, 50, 16),
+            Biome::Plains => RgbVoxel::rgb(11, 44, 6),
+            Biome::Forest => RgbVoxel::rgb(5, 30, 4),
+            Biome::Tundra => RgbVoxel::rgb(29, 61, 30),
+        }
+    }
+
+    /// Color of few voxels under the surface
+    fn subsoil(&self) -> RgbVoxel {
+        match self {
+            Biome::Desert => RgbVoxel::rgb(24, 42, 12),
+            _ => RgbVoxel::rgb(14, 22, 7),
+        }
+    }
+}
+
+const STONE: [u8; 3] = [15, 30, 15];
+const WATER: [u8; 3] = [4, 20, 24];
+
+/// Depth of subsoil layer under the surface in voxels
+const SUBSOIL_DEPTH: i32 = 4;
+
+/// SplitMix64 step, used to derive permutations and per voxel variation from the seed
+fn splitmix(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Hash of integer coordinates
+fn hash3(seed: u64, x: i32, y: i32, z: i32) -> u64 {
+    let mut state = seed
+        ^ (x as u32 as u64).wrapping_mul(0x8DA6_B343)
+        ^ ((y as u32 as u64).wrapping_mul(0xD816_3841) << 16)
+        ^ ((z as u32 as u64).wrapping_mul(0xCB1A_B31F) << 32);
+    splitmix(&mut state)
+}
+
+/// Classic 3D gradient noise with permutation table shuffled by seed
+struct Perlin {
+    perm: [u8; 512],
+}
+
+/// Directions to the edges of a cube
+const GRADIENTS: [[f32; 3]; 12] = [
+    [1.0, 1.0, 0.0],
+    [-1.0, 1.0, 0.0],
+    [1.0, -1.0, 0.0],
+    [-1.0, -1.0, 0.0],
+    [1.0, 0.0, 1.0],
+    [-1.0, 0.0, 1.0],
+    [1.0, 0.0, -1.0],
+    [-1.0, 0.0, -1.0],
+    [0.0, 1.0, 1.0],
+    [0.0, -1.0, 1.0],
+    [0.0, 1.0, -1.0],
+    [0.0, -1.0, -1.0],
+];
+
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+impl Perlin {
+    fn new(seed: u64) -> Self {
+        let mut state = seed;
+        let mut table: [u8; 256] = std::array::from_fn(|i| i as u8);
+        for i in (1..256).rev() {
+            let j = (splitmix(&mut state) % (i as u64 + 1)) as usize;
+            table.swap(i, j);
+        }
+        Perlin {
+            perm: std::array::from_fn(|i| table[i % 256]),
+        }
+    }
+
+    fn gradient(&self, x: i32, y: i32, z: i32, d: [f32; 3]) -> f32 {
+        let p = &self.perm;
+        let h = p
+            [p[p[(x & 255) as usize] as usize + (y & 255) as usize] as usize + (z & 255) as usize]
+            as usize;
+        let g = GRADIENTS[h % 12];
+        g[0] * d[0] + g[1] * d[1] + g[2] * d[2]
+    }
+
+    /// Noise value in range about -1.0 .. 1.0
+    fn noise(&self, x: f32, y: f32, z: f32) -> f32 {
+        let (fx, fy, fz) = (x.floor(), y.floor(), z.floor());
+        let (ix, iy, iz) = (fx as i32, fy as i32, fz as i32);
+        let (dx, dy, dz) = (x - fx, y - fy, z - fz);
+        let (u, v, w) = (fade(dx), fade(dy), fade(dz));
+        let corner = |cx: i32, cy: i32, cz: i32| {
+            self.gradient(
+                ix + cx,
+                iy + cy,
+                iz + cz,
+                [dx - cx as f32, dy - cy as f32, dz - cz as f32],
+            )
+        };
+        lerp(
+            lerp(
+                lerp(corner(0, 0, 0), corner(1, 0, 0), u),
+                lerp(corner(0, 1, 0), corner(1, 1, 0), u),
+                v,
+            ),
+            lerp(
+                lerp(corner(0, 0, 1), corner(1, 0, 1), u),
+                lerp(corner(0, 1, 1), corner(1, 1, 1), u),
+                v,
+            ),
+            w,
+        )
+    }
+}
+
+/// Deterministic noise terrain that can generate chunk at any offset, so chunks can be
+/// dropped and regenerated on demand
+pub struct TerrainGenerator {
+    /// Parameters the generator was made with
+    pub settings: TerrainSettings,
+    height: Perlin,
+    climate: Perlin,
+    caves: Perlin,
+}
+
+impl TerrainGenerator {
+    /// Make generator, all noise functions are seeded from `settings.seed`
+    pub fn new(settings: TerrainSettings) -> Self {
+        let mut state = settings.seed;
+        TerrainGenerator {
+            settings,
+            height: Perlin::new(splitmix(&mut state)),
+            climate: Perlin::new(splitmix(&mut state)),
+            caves: Perlin::new(splitmix(&mut state)),
+        }
+    }
+
+    /// Fractal Brownian motion and ridged noise of the height map in range about 0.0 .. 1.0
+    fn height_noise(&self, x: f32, z: f32) -> (f32, f32) {
+        let s = &self.settings;
+        let (mut fbm, mut ridged) = (0.0, 0.0);
+        let (mut amplitude, mut frequency, mut total) = (1.0, s.frequency, 0.0);
+        for octave in 0..s.octaves {
+            // Octaves are sampled from different planes to decorrelate them
+            let n = self
+                .height
+                .noise(x * frequency, octave as f32 * 17.3, z * frequency);
+            fbm += n * amplitude;
+            ridged += (1.0 - n.abs()).powi(2) * amplitude;
+            total += amplitude;
+            amplitude *= s.gain;
+            frequency *= s.lacunarity;
+        }
+        if total == 0.0 {
+            return (0.5, 0.0);
+        }
+        (fbm / total * 0.5 + 0.5, ridged / total)
+    }
+
+    /// Height of the ground surface at given world coordinates
+    pub fn height(&self, x: i32, z: i32) -> i32 {
+        let s = &self.settings;
+        let (fbm, ridged) = self.height_noise(x as f32, z as f32);
+        let h = fbm * (1.0 - s.ridges) + ridged * s.ridges;
+        (s.base_height + (h - 0.5) * 2.0 * s.height_scale).round() as i32
+    }
+
+    /// Climate zone at given world coordinates. Temperature drops with height.
+    pub fn biome(&self, x: i32, z: i32, height: i32) -> Biome {
+        let f = self.settings.frequency * 0.5;
+        let temperature = self.climate.noise(x as f32 * f, 0.5, z as f32 * f)
+            - (height - self.settings.sea_level).max(0) as f32 / (2.0 * self.settings.height_scale);
+        let moisture = self.climate.noise(x as f32 * f, 40.5, z as f32 * f);
+        if temperature < -0.3 {
+            Biome::Tundra
+        } else if temperature > 0.25 && moisture < 0.0 {
+            Biome::Desert
+        } else if moisture > 0.1 {
+            Biome::Forest
+        } else {
+            Biome::Plains
+        }
+    }
+
+    /// Check that the voxel is inside a cave tunnel. Tunnels are where two 3D noises are
+    /// close to zero at once.
+    fn is_cave(&self, x: i32, y: i32, z: i32) -> bool {
+        let s = &self.settings;
+        if s.cave_width <= 0.0 {
+            return false;
+        }
+        let (fx, fy, fz) = (
+            x as f32 * s.cave_frequency,
+            y as f32 * s.cave_frequency * 1.5,
+            z as f32 * s.cave_frequency,
+        );
+        let a = self.caves.noise(fx, fy, fz);
+        let b = self.caves.noise(fx + 31.7, fy + 11.3, fz - 7.1);
+        a * a + b * b < s.cave_width * s.cave_width
+    }
+
+    /// Slightly vary color of voxel, so flat areas don't look plain
+    fn vary(&self, color: [u8; 3], x: i32, y: i32, z: i32) -> RgbVoxel {
+        let h = hash3(self.settings.seed, x, y, z);
+        let shift = (h % 3) as u8;
+        RgbVoxel::rgb(color[0] + shift, color[1] + shift * 2, color[2] + shift)
+    }
+
+    /// Fill voxels of the column at world X, Z from world height `y0` upward
+    fn column(&self, x: i32, y0: i32, z: i32, column: &mut [RgbVoxel]) {
+        let height = self.height(x, z);
+        let biome = self.biome(x, z, height);
+        for (i, voxel) in column.iter_mut().enumerate() {
+            let y = y0 + i as i32;
+            *voxel = if y > height {
+                if y <= self.settings.sea_level {
+                    self.vary(WATER, x, y, z)
+                } else {
+                    RgbVoxel::empty()
+                }
+            } else if y < height - 1 && self.is_cave(x, y, z) {
+                RgbVoxel::empty()
+            } else if y == height && height >= self.settings.sea_level {
+                biome.surface()
+            } else if y > height - SUBSOIL_DEPTH {
+                biome.subsoil()
+            } else {
+                self.vary(STONE, x, y, z)
+            };
+        }
+    }
+
+    /// Generate chunk with given chunk coordinates, its corner is at `offset * chunk_size`.
+    /// Columns are compressed one by one without dense array of the whole chunk.
+    pub fn generate_chunk(&self, offset: [i32; 3]) -> RleVolume {
+        let size = self.settings.chunk_size;
+        let origin = [
+            offset[0] * size[0] as i32,
+            offset[1] * size[1] as i32,
+            offset[2] * size[2] as i32,
+        ];
+        self.generate_box(origin, size)
+    }
+
+    /// Generate volume of given size with its corner at world coordinates, e.g. a chunk of
+    /// other size than `TerrainSettings::chunk_size`
+    pub fn generate_box(&self, origin: [i32; 3], size: [u32; 3]) -> RleVolume {
+        let [xsize, ysize, zsize] = size;
+        let mut buffer = vec![RgbVoxel::empty(); ysize as usize];
+        let columns = (0..zsize)
+            .flat_map(|z| (0..xsize).map(move |x| (x, z)))
+            .map(|(x, z)| {
+                self.column(
+                    origin[0] + x as i32,
+                    origin[1],
+                    origin[2] + z as i32,
+                    &mut buffer,
+                );
+                RleColumn::compress(&buffer)
+            });
+        RleVolume::from_columns(xsize as usize, ysize as usize, zsize as usize, columns)
+    }
+}
+
+impl VoxelSource for TerrainGenerator {
+    /// Sample terrain at world coordinates without generating a chunk
+    fn voxel(&self, x: i32, y: i32, z: i32) -> RgbVoxel {
+        let mut voxel = [RgbVoxel::empty()];
+        self.column(x, y, z, &mut voxel);
+        voxel[0]
+    }
+
+    fn voxel_column(&self, x: i32, y0: i32, z: i32, column: &mut [RgbVoxel]) {
+        self.column(x, y0, z, column);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(seed: u64) -> TerrainSettings {
+        TerrainSettings {
+            seed,
+            chunk_size: [24, 48, 24],
+            ..TerrainSettings::default()
+        }
+    }
+
+    #[test]
+    fn deterministic_test() {
+        let a = TerrainGenerator::new(settings(7)).generate_chunk([1, 0, -2]);
+        let b = TerrainGenerator::new(settings(7)).generate_chunk([1, 0, -2]);
+        assert_eq!(a.checksum(), b.checksum(), "Same seed gives same chunk");
+        let c = TerrainGenerator::new(settings(8)).generate_chunk([1, 0, -2]);
+        assert_ne!(a.checksum(), c.checksum(), "Other seed gives other chunk");
+    }
+
+    #[test]
+    fn chunk_matches_sampling_test() {
+        let generator = TerrainGenerator::new(settings(3));
+        let offset = [-1, 0, 2];
+        let chunk = generator.generate_chunk(offset);
+        assert_eq!(chunk.dimensions(), [24, 48, 24]);
+        let mut filled = 0;
+        for z in 0..24 {
+            for y in 0..48 {
+                for x in 0..24 {
+                    let world = generator.voxel(x - 24, y, z + 48);
+                    assert_eq!(chunk.voxel(x, y, z), world, "Voxel {} {} {}", x, y, z);
+                    filled += !world.is_empty() as usize;
+                }
+            }
+        }
+        assert!(
+            filled > 0 && filled < 24 * 48 * 24,
+            "Chunk has ground and air"
+        );
+
+        let shifted = generator.generate_box([-20, 3, 50], [10, 40, 6]);
+        assert_eq!(shifted.dimensions(), [10, 40, 6]);
+        assert_eq!(shifted.voxel(0, 0, 0), chunk.voxel(4, 3, 2));
+        assert_eq!(shifted.voxel(9, 39, 5), chunk.voxel(13, 42, 7));
+
+        // Neighbour chunks continue each other
+        let right = generator.generate_chunk([0, 0, 2]);
+        for y in 0..48 {
+            assert_eq!(right.voxel(0, y, 5), generator.voxel(0, y, 53));
+        }
+    }
+
+    #[test]
+    fn terrain_features_test() {
+        let generator = TerrainGenerator::new(TerrainSettings {
+            cave_width: 0.0,
+            ..settings(11)
+        });
+        let height = generator.height(5, 9);
+        assert!(
+            !generator.voxel(5, height, 9).is_empty(),
+            "Surface is solid"
+        );
+        assert!(
+            generator
+                .voxel(5, height.max(generator.settings.sea_level) + 1, 9)
+                .is_empty(),
+            "Air above surface and water"
+        );
+        assert!(!generator.voxel(5, height - 10, 9).is_empty(), "No caves");
+
+        let caves = TerrainGenerator::new(settings(11));
+        let empty_underground = (0..64)
+            .flat_map(|x| (0..64).map(move |z| (x, z)))
+            .filter(|(x, z)| caves.voxel(*x, 0, *z).is_empty())
+            .count();
+        assert!(empty_underground > 0, "Caves cut the ground");
+    }
+}
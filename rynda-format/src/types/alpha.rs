@@ -0,0 +1,313 @@
+use super::{
+    attribute::{attribute_word, AttributeStream},
+    pointermap::GpuVolume,
+    volume::{DrawnVoxel, RleVolume},
+    voxel::RgbVoxel,
+};
+use crate::color::srgb_to_linear;
+use ndarray::Array3;
+
+/// Amount of bytes `VoxelAlpha` takes in memory
+pub const VOXEL_ALPHA_SIZE: usize = 2;
+
+/// Class of voxel material that tells renderer how light passes through it
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum MaterialClass {
+    /// Light doesn't pass through the voxel
+    #[default]
+    Opaque = 0,
+    /// Solid translucent material like glass or ice
+    Glass = 1,
+    /// Translucent liquid like water
+    Liquid = 2,
+}
+
+/// Opacity and material class of a drawn voxel. Empty voxels are not stored in columns at
+/// all, so a voxel with zero alpha is still present in the volume (e.g. for collisions) and
+/// only invisible. In memory it is 16-bit value with alpha in the low byte and material
+/// class in the high byte. `planecast.comp` reads the stream packed by
+/// `AttributeStream::to_words` and blends columns as `gpu_composite_column` does.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct VoxelAlpha {
+    /// Opacity from 0 (fully transparent) to 255 (opaque)
+    pub alpha: u8,
+    /// Material class of the voxel
+    pub class: MaterialClass,
+}
+
+impl Default for VoxelAlpha {
+    /// Opaque voxel
+    fn default() -> Self {
+        VoxelAlpha::opaque()
+    }
+}
+
+impl VoxelAlpha {
+    /// Shortcut for making alpha of given material
+    pub fn new(alpha: u8, class: MaterialClass) -> Self {
+        VoxelAlpha { alpha, class }
+    }
+
+    /// Voxel that blocks light completely
+    pub fn opaque() -> Self {
+        VoxelAlpha::new(255, MaterialClass::Opaque)
+    }
+
+    /// Check that the voxel blocks light completely
+    pub fn is_opaque(&self) -> bool {
+        self.alpha == 255
+    }
+
+    /// Opacity in range 0.0 .. 1.0
+    pub fn opacity(&self) -> f32 {
+        self.alpha as f32 / 255.0
+    }
+}
+
+/// Make alpha stream from array of alpha values in model layout. Values of empty voxels
+/// are ignored.
+pub fn alpha_from_array(
+    volume: &RleVolume,
+    alphas: &Array3<VoxelAlpha>,
+) -> AttributeStream<VoxelAlpha> {
+    let [xsize, ysize, zsize] = volume.dimensions();
+    assert_eq!(
+        alphas.dim(),
+        (xsize as usize, ysize as usize, zsize as usize),
+        "Alpha array has the size of the volume"
+    );
+    AttributeStream::from_fn(volume, |x, y, z, _| {
+        alphas[(x as usize, y as usize, z as usize)]
+    })
+}
+
+/// Make volume and its alpha stream from array of 8-bit sRGB colors with alpha in model
+/// layout. Voxels with zero alpha are empty, translucent voxels get given material class.
+pub fn volume_from_rgba(
+    colors: &Array3<[u8; 4]>,
+    translucent: MaterialClass,
+) -> (RleVolume, AttributeStream<VoxelAlpha>) {
+    let volume: RleVolume = colors.map(|c| RgbVoxel::from_rgba8(*c)).into();
+    let alphas = AttributeStream::from_fn(&volume, |x, y, z, _| {
+        let alpha = colors[(x as usize, y as usize, z as usize)][3];
+        if alpha == 255 {
+            VoxelAlpha::opaque()
+        } else {
+            VoxelAlpha::new(alpha, translucent)
+        }
+    });
+    (volume, alphas)
+}
+
+/// Order in which voxels of a column are visited
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColumnOrder {
+    /// From the bottom of the column up, i.e. for a ray that looks up
+    Ascending,
+    /// From the top of the column down, i.e. for a ray that looks down
+    Descending,
+}
+
+/// Accumulated color of translucent voxels blended front-to-back. Color is premultiplied
+/// by alpha and kept in linear light.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Composite {
+    /// Premultiplied color in linear light
+    pub color: [f32; 3],
+    /// Accumulated opacity in range 0.0 .. 1.0
+    pub alpha: f32,
+}
+
+impl Composite {
+    /// Opacity after which voxels behind don't affect the result
+    pub const SATURATION: f32 = 0.999;
+
+    /// Blend voxel that lies behind all voxels blended before
+    pub fn add(&mut self, color: RgbVoxel, alpha: VoxelAlpha) {
+        let rgba = color.to_rgba8();
+        let weight = (1.0 - self.alpha) * alpha.opacity();
+        for (c, s) in self.color.iter_mut().zip(rgba) {
+            *c += weight * srgb_to_linear(s);
+        }
+        self.alpha += weight;
+    }
+
+    /// Check that nothing behind can change the result, so traversal can stop
+    pub fn is_saturated(&self) -> bool {
+        self.alpha >= Self::SATURATION
+    }
+}
+
+impl RleVolume {
+    /// Blend drawn voxels of the column front-to-back in given order. Traversal stops at
+    /// the first voxel that saturates the result, e.g. an opaque one.
+    pub fn composite_column(
+        &self,
+        index: usize,
+        alphas: &AttributeStream<VoxelAlpha>,
+        order: ColumnOrder,
+    ) -> Composite {
+        let voxels = self.drawn_voxels(index);
+        let mut composite = Composite::default();
+        let mut blend = |voxel: &DrawnVoxel| {
+            let alpha = alphas.get(voxel.slot).copied().unwrap_or_default();
+            composite.add(voxel.color, alpha);
+            composite.is_saturated()
+        };
+        match order {
+            ColumnOrder::Ascending => voxels.iter().any(&mut blend),
+            ColumnOrder::Descending => voxels.iter().rev().any(&mut blend),
+        };
+        composite
+    }
+}
+
+/// Mirrors `composite_column` of `planecast.comp` that blends drawn voxels of the model
+/// column at X, Z front-to-back from the top. Alphas are packed by
+/// `AttributeStream::to_words`, voxels are not shaded with normals.
+pub fn gpu_composite_column(volume: &GpuVolume, alphas: &[u32], x: u32, z: u32) -> Composite {
+    let srgb_to_linear = |c: f32| {
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    let mut composite = Composite::default();
+    for y in (0..volume.model_size()[1]).rev() {
+        if composite.is_saturated() {
+            break;
+        }
+        if let Some(offset) = volume.fetch_voxel([x, y, z]) {
+            let color = volume.column_u16(offset);
+            let rgb = [
+                (color & 0x1F) as f32 / 31.0,
+                ((color >> 5) & 0x3F) as f32 / 63.0,
+                ((color >> 11) & 0x1F) as f32 / 31.0,
+            ];
+            let opacity = (attribute_word(alphas, offset) & 0xFF) as f32 / 255.0;
+            let weight = (1.0 - composite.alpha) * opacity;
+            for (c, s) in composite.color.iter_mut().zip(rgb) {
+                *c += weight * srgb_to_linear(s);
+            }
+            composite.alpha += weight;
+        }
+    }
+    composite
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alpha_layout_test() {
+        assert_eq!(std::mem::size_of::<VoxelAlpha>(), VOXEL_ALPHA_SIZE);
+        let alpha = VoxelAlpha::new(100, MaterialClass::Liquid);
+        let bytes: [u8; 2] = unsafe { std::mem::transmute(alpha) };
+        assert_eq!(bytes, [100, 2], "Alpha is in the low byte");
+    }
+
+    #[test]
+    fn composite_test() {
+        // Column of opaque red bottom, glass in the middle and air on the top
+        let colors = Array3::from_shape_fn((1, 4, 1), |(_, y, _)| match y {
+            0 => [255, 0, 0, 255],
+            1 | 2 => [0, 0, 255, 128],
+            _ => [0, 0, 0, 0],
+        });
+        let (volume, alphas) = volume_from_rgba(&colors, MaterialClass::Glass);
+        assert!(alphas.matches(&volume));
+        assert!(volume.get(0, 3, 0).is_empty(), "Zero alpha is empty voxel");
+        let glass = volume.drawn_voxels(0)[1];
+        assert_eq!(
+            alphas.values[glass.slot],
+            VoxelAlpha::new(128, MaterialClass::Glass),
+            "Translucent voxel is present"
+        );
+        let same = alpha_from_array(
+            &volume,
+            &colors.map(|c| match c[3] {
+                255 => VoxelAlpha::opaque(),
+                a => VoxelAlpha::new(a, MaterialClass::Glass),
+            }),
+        );
+        assert_eq!(same, alphas);
+
+        let from_top = volume.composite_column(0, &alphas, ColumnOrder::Descending);
+        assert!(from_top.is_saturated(), "Opaque bottom stops the ray");
+        assert!(
+            from_top.color[2] > from_top.color[0],
+            "Glass in front tints"
+        );
+        assert!(from_top.color[0] > 0.0, "Red shows through glass");
+
+        let from_bottom = volume.composite_column(0, &alphas, ColumnOrder::Ascending);
+        assert_eq!(
+            from_bottom.color,
+            [1.0, 0.0, 0.0],
+            "Opaque voxel hides glass"
+        );
+
+        let opaque = AttributeStream::new(&volume);
+        let composite = volume.composite_column(0, &opaque, ColumnOrder::Descending);
+        assert_eq!(composite.alpha, 1.0);
+        assert_eq!(composite.color, [0.0, 0.0, 1.0], "First opaque voxel wins");
+    }
+
+    #[test]
+    fn gpu_composite_test() {
+        use crate::types::{
+            axis::RleAxis,
+            pointermap::{column_words, GpuPointerColumn},
+        };
+
+        // Glass and water layers of different opacity over opaque ground with holes
+        let colors = Array3::from_shape_fn((4, 9, 3), |(x, y, z)| match y {
+            0 if (x + z) % 3 != 0 => [200, 120, 40, 255],
+            2 | 3 => [40, 80, 255, (60 + 40 * x) as u8],
+            5 if z != 1 => [20, 200, 90, 128],
+            7 => [255, 255, 255, (20 * z) as u8],
+            _ => [0, 0, 0, 0],
+        });
+        let (volume, alphas) = volume_from_rgba(&colors, MaterialClass::Glass);
+        let array_alphas = colors.map(|c| match c[3] {
+            255 => VoxelAlpha::opaque(),
+            a => VoxelAlpha::new(a, MaterialClass::Glass),
+        });
+        let voxels = colors.map(|c| RgbVoxel::from_rgba8(*c));
+        for axis in [RleAxis::X, RleAxis::Y, RleAxis::Z] {
+            let axis_volume = RleVolume::from_array_with_axis(&voxels, axis);
+            let axis_alphas = alpha_from_array(&axis_volume, &array_alphas);
+            let view = axis_volume.view();
+            let columns = column_words(view.columns);
+            let alpha_words = axis_alphas.to_words();
+            let gpu = GpuVolume {
+                size: [view.xsize, view.ysize, view.zsize],
+                axis: axis.code() as u32,
+                pointers: GpuPointerColumn::from_pointers(view.pointers),
+                columns: &columns,
+            };
+            for (x, z) in (0..4).flat_map(|x| (0..3).map(move |z| (x, z))) {
+                let index = volume.column_index(x, z);
+                let expected = volume.composite_column(index, &alphas, ColumnOrder::Descending);
+                let composite = gpu_composite_column(&gpu, &alpha_words, x, z);
+                assert!(
+                    (composite.alpha - expected.alpha).abs() < 1e-5
+                        && composite
+                            .color
+                            .iter()
+                            .zip(expected.color)
+                            .all(|(a, b)| (a - b).abs() < 2e-3),
+                    "Column {:?} of {:?} volume: {:?} vs {:?}",
+                    (x, z),
+                    axis,
+                    composite,
+                    expected
+                );
+            }
+        }
+    }
+}
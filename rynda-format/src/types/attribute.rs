@@ -0,0 +1,137 @@
+use super::{pointermap::column_words, volume::RleVolume, voxel::RGB_VOXEL_SIZE};
+use alloc::{vec, vec::Vec};
+use core::mem::size_of;
+
+/// Per voxel data that is stored parallel to the color data of `RleVolume::columns`. The
+/// stream has one element per voxel sized slot of the columns buffer, so a voxel color at
+/// byte offset `o` of the columns buffer has its attribute at index `o / RGB_VOXEL_SIZE`.
+/// Slots that are occupied by RLE ranges are left with default values.
+///
+/// That layout allows a shader to address the attribute with the same pointer it uses to
+/// read colors from the columns buffer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AttributeStream<T> {
+    /// Values of the attribute indexed by color slot
+    pub values: Vec<T>,
+}
+
+impl<T: Clone + Default> AttributeStream<T> {
+    /// Make stream for the volume filled with default values
+    pub fn new(volume: &RleVolume) -> Self {
+        AttributeStream {
+            values: vec![T::default(); volume.color_slots()],
+        }
+    }
+
+    /// Make stream by calculating attribute for each drawn voxel of the volume. The function
+    /// receives model X, Y, Z coordinates and the color slot of the voxel.
+    pub fn from_fn<F>(volume: &RleVolume, mut f: F) -> Self
+    where
+        F: FnMut(u32, u32, u32, usize) -> T,
+    {
+        let mut stream = Self::new(volume);
+        for i in 0..volume.columns_count() {
+            let x = (i % volume.xsize as usize) as u32;
+            let z = (i / volume.xsize as usize) as u32;
+            for voxel in volume.drawn_voxels(i) {
+                let [x, y, z] = volume.axis.swap([x, voxel.y, z]);
+                stream.values[voxel.slot] = f(x, y, z, voxel.slot);
+            }
+        }
+        stream
+    }
+}
+
+impl<T> AttributeStream<T> {
+    /// Get attribute of voxel by its color slot
+    pub fn get(&self, slot: usize) -> Option<&T> {
+        self.values.get(slot)
+    }
+
+    /// Check that the stream fits the color data of the volume
+    pub fn matches(&self, volume: &RleVolume) -> bool {
+        self.values.len() == volume.color_slots()
+    }
+}
+
+impl<T: Copy> AttributeStream<T> {
+    /// Pack 16-bit attributes into little endian words the way `planecast.comp` reads them
+    /// from storage buffers, two slots per word with the even slot in the low half. The tail
+    /// is padded with zeros.
+    pub fn to_words(&self) -> Vec<u32> {
+        assert_eq!(
+            size_of::<T>(),
+            2,
+            "Only 16-bit attributes are packed into words"
+        );
+        // Attributes that go to GPU are plain 16-bit values without padding
+        let bytes = unsafe {
+            core::slice::from_raw_parts(
+                self.values.as_ptr() as *const u8,
+                self.values.len() * size_of::<T>(),
+            )
+        };
+        column_words(bytes)
+    }
+}
+
+/// Read 16-bit attribute of a voxel from words made by `AttributeStream::to_words` by byte
+/// offset of the voxel color in the columns buffer. Mirrors the attribute readers of
+/// `planecast.comp`.
+pub fn attribute_word(words: &[u32], color_offset: u32) -> u32 {
+    let slot = color_offset / RGB_VOXEL_SIZE as u32;
+    (words[slot as usize / 2] >> ((slot % 2) * 16)) & 0xFFFF
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{column::RleColumn, voxel::RgbVoxel};
+
+    #[test]
+    fn stream_slots_test() {
+        let columns = (0..2 * 3).map(|i| {
+            let (x, z) = (i % 2, i / 2);
+            let voxels: Vec<RgbVoxel> = (0..6)
+                .map(|y| {
+                    if y % 2 == 0 {
+                        RgbVoxel::rgb(x as u8 + 1, y as u8, z as u8)
+                    } else {
+                        RgbVoxel::empty()
+                    }
+                })
+                .collect();
+            RleColumn::compress(&voxels)
+        });
+        let volume = RleVolume::from_columns(2, 6, 3, columns);
+        let stream = AttributeStream::from_fn(&volume, |x, y, z, _| (x, y, z));
+        assert!(stream.matches(&volume), "Stream is parallel to columns");
+
+        let mut visited = 0;
+        for i in 0..volume.columns_count() {
+            for voxel in volume.drawn_voxels(i) {
+                let (x, y, z) = stream.values[voxel.slot];
+                assert_eq!(
+                    RgbVoxel::rgb(x as u8 + 1, y as u8, z as u8),
+                    voxel.color,
+                    "Attribute slot points to the voxel color"
+                );
+                visited += 1;
+            }
+        }
+        assert_eq!(visited, 2 * 3 * 3, "All drawn voxels are visited");
+    }
+
+    #[test]
+    fn stream_words_test() {
+        let stream = AttributeStream {
+            values: vec![0x1234u16, 0xABCD, 0x0F0F],
+        };
+        let words = stream.to_words();
+        assert_eq!(words, [0xABCD1234, 0x0F0F], "Tail is padded");
+        for (slot, value) in stream.values.iter().enumerate() {
+            let offset = (slot * RGB_VOXEL_SIZE) as u32;
+            assert_eq!(attribute_word(&words, offset), *value as u32);
+        }
+    }
+}
@@ -0,0 +1,69 @@
+/// Axis of the model along which RLE columns of a volume run. Volume stores voxels in its
+/// own layout where columns always run along Y, and the model axis is swapped with Y to
+/// get there. Sizes of the volume (`xsize`, `ysize`, `zsize`) are given in that layout.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RleAxis {
+    /// Columns run along model X, model X and Y are swapped
+    X,
+    /// Columns run along model Y, layout of the volume matches the model
+    #[default]
+    Y,
+    /// Columns run along model Z, model Z and Y are swapped
+    Z,
+}
+
+/// All possible RLE axes
+pub const RLE_AXES: [RleAxis; 3] = [RleAxis::X, RleAxis::Y, RleAxis::Z];
+
+impl RleAxis {
+    /// Convert coordinates between model and volume layout. The swap is its own inverse, so
+    /// the same function works in both directions.
+    pub fn swap<T: Copy>(self, p: [T; 3]) -> [T; 3] {
+        match self {
+            RleAxis::X => [p[1], p[0], p[2]],
+            RleAxis::Y => p,
+            RleAxis::Z => [p[0], p[2], p[1]],
+        }
+    }
+
+    /// Order of model array axes that gives the volume layout, suitable for
+    /// `ndarray::ArrayBase::permuted_axes`. As the swap, it is its own inverse.
+    pub fn permutation(self) -> [usize; 3] {
+        self.swap([0, 1, 2])
+    }
+
+    /// Stable numeric code of the axis that is used in binary formats and shaders
+    pub fn code(self) -> u8 {
+        match self {
+            RleAxis::X => 0,
+            RleAxis::Y => 1,
+            RleAxis::Z => 2,
+        }
+    }
+
+    /// Decode axis from code given by `code`
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(RleAxis::X),
+            1 => Some(RleAxis::Y),
+            2 => Some(RleAxis::Z),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn swap_is_involution() {
+        for axis in RLE_AXES {
+            let p = [1, 2, 3];
+            assert_eq!(axis.swap(axis.swap(p)), p, "Double swap along {:?}", axis);
+            assert_eq!(axis.swap(p)[1], p[axis.code() as usize], "Axis becomes Y");
+            assert_eq!(RleAxis::from_code(axis.code()), Some(axis));
+        }
+    }
+}
@@ -2,9 +2,11 @@ use super::{
     range::{RleRange, RLE_DRAWN_MAX, RLE_RANGE_SIZE, RLE_SKIPPED_MAX},
     voxel::{RgbVoxel, RGB_VOXEL_SIZE},
 };
+use alloc::{vec, vec::Vec};
 
 /// Describes unpacked run length encoded column that is stored inside buffer in the `RleVolume`.
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RleColumn {
     /// Ranges of skipped-drawn voxels. May or may not contain first range depending of usage of the column.
     pub ranges: Vec<RleRange>,
@@ -545,7 +547,7 @@ mod tests {
             size = column.pack_into(buffer.as_mut_ptr());
         }
         assert_eq!(size, buffer.len(), "Packed size is not equal buffer size");
-        assert_eq!(buffer, vec![], "Packing column with no ranges");
+        assert_eq!(buffer, Vec::<u8>::new(), "Packing column with no ranges");
     }
 
     #[test]
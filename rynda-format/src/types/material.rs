@@ -0,0 +1,254 @@
+use super::{
+    alpha::{MaterialClass, VoxelAlpha},
+    attribute::AttributeStream,
+};
+use std::collections::HashMap;
+
+/// Amount of palette entries, voxels refer to them with 8-bit indices
+pub const PALETTE_SIZE: usize = 256;
+
+/// How a material interacts with light, mirrors `_type` property of MagicaVoxel MATL chunk
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MaterialKind {
+    /// Plain diffuse surface
+    #[default]
+    Diffuse,
+    /// Metallic surface
+    Metal,
+    /// Translucent glass
+    Glass,
+    /// Light source
+    Emissive,
+    /// Mix of metal, glass and emission
+    Blend,
+    /// Participating media like cloud or fog
+    Media,
+}
+
+impl MaterialKind {
+    fn from_vox(value: &str) -> Self {
+        match value {
+            "_metal" => MaterialKind::Metal,
+            "_glass" => MaterialKind::Glass,
+            "_emit" => MaterialKind::Emissive,
+            "_blend" => MaterialKind::Blend,
+            "_media" => MaterialKind::Media,
+            _ => MaterialKind::Diffuse,
+        }
+    }
+}
+
+/// Physical properties of a palette entry. Values are kept as MagicaVoxel defines them,
+/// all of them except IOR and flux are in range 0.0 .. 1.0.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Material {
+    /// How the material interacts with light
+    pub kind: MaterialKind,
+    /// Strength of the material effect, e.g. how much light passes through glass
+    pub weight: f32,
+    /// Surface roughness
+    pub roughness: f32,
+    /// Metalness
+    pub metalness: f32,
+    /// Specular reflection strength
+    pub specular: f32,
+    /// Index of refraction
+    pub ior: f32,
+    /// Part of light that passes through the material
+    pub transparency: f32,
+    /// Emission strength
+    pub emission: f32,
+    /// Power of emission, light intensity is `emission * 10^flux`
+    pub flux: f32,
+}
+
+impl Default for Material {
+    /// Plain diffuse material of MagicaVoxel
+    fn default() -> Self {
+        Material {
+            kind: MaterialKind::Diffuse,
+            weight: 1.0,
+            roughness: 0.1,
+            metalness: 0.0,
+            specular: 0.5,
+            ior: 1.3,
+            transparency: 0.0,
+            emission: 0.0,
+            flux: 0.0,
+        }
+    }
+}
+
+impl Material {
+    /// Parse properties of MagicaVoxel MATL chunk, missing or malformed values are replaced
+    /// with defaults.
+    pub fn from_vox_properties(properties: &HashMap<String, String>) -> Self {
+        let mut material = Material::default();
+        let float = |key: &str| {
+            properties
+                .get(key)
+                .and_then(|v| v.trim().parse::<f32>().ok())
+        };
+        if let Some(kind) = properties.get("_type") {
+            material.kind = MaterialKind::from_vox(kind);
+        }
+        material.weight = float("_weight").unwrap_or(material.weight);
+        material.roughness = float("_rough").unwrap_or(material.roughness);
+        material.metalness = float("_metal").unwrap_or(material.metalness);
+        material.specular = float("_spec").unwrap_or(material.specular);
+        // Newer files store IOR without the leading one, older ones store full value in `_ri`
+        material.ior = match (float("_ri"), float("_ior")) {
+            (Some(ri), _) => ri,
+            (None, Some(ior)) if ior < 1.0 => ior + 1.0,
+            (None, Some(ior)) => ior,
+            (None, None) => material.ior,
+        };
+        material.transparency = float("_trans")
+            .or_else(|| float("_alpha"))
+            .unwrap_or(material.transparency);
+        material.emission = float("_emit").unwrap_or(material.emission);
+        material.flux = float("_flux").unwrap_or(material.flux);
+        if material.kind == MaterialKind::Glass && properties.get("_trans").is_none() {
+            // Old files keep amount of glass in the weight
+            material.transparency = material.weight;
+        }
+        material
+    }
+
+    /// Check that the material emits light
+    pub fn is_emissive(&self) -> bool {
+        matches!(self.kind, MaterialKind::Emissive | MaterialKind::Blend) && self.emission > 0.0
+    }
+
+    /// Check that light passes through the material
+    pub fn is_translucent(&self) -> bool {
+        matches!(self.kind, MaterialKind::Glass | MaterialKind::Blend) && self.transparency > 0.0
+    }
+
+    /// Light intensity of emissive material
+    pub fn intensity(&self) -> f32 {
+        if self.is_emissive() {
+            self.emission * 10f32.powf(self.flux)
+        } else {
+            0.0
+        }
+    }
+
+    /// Alpha of voxels made of the material
+    pub fn alpha(&self) -> VoxelAlpha {
+        if self.is_translucent() {
+            let alpha = ((1.0 - self.transparency.clamp(0.0, 1.0)) * 255.0).round() as u8;
+            VoxelAlpha::new(alpha, MaterialClass::Glass)
+        } else {
+            VoxelAlpha::opaque()
+        }
+    }
+}
+
+/// Materials of a volume keyed by palette index. Voxels refer to the table with palette
+/// index stream that is parallel to the columns buffer, see `AttributeStream`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MaterialTable {
+    /// Material of each palette entry
+    pub materials: Vec<Material>,
+}
+
+impl Default for MaterialTable {
+    /// Table with all entries made of diffuse material
+    fn default() -> Self {
+        MaterialTable {
+            materials: vec![Material::default(); PALETTE_SIZE],
+        }
+    }
+}
+
+impl MaterialTable {
+    /// Get material of palette entry
+    pub fn get(&self, index: u8) -> &Material {
+        &self.materials[index as usize]
+    }
+
+    /// Replace material of palette entry
+    pub fn set(&mut self, index: u8, material: Material) {
+        self.materials[index as usize] = material;
+    }
+
+    /// Palette indices of entries that emit light
+    pub fn emissive(&self) -> impl Iterator<Item = u8> + '_ {
+        self.materials
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| m.is_emissive())
+            .map(|(i, _)| i as u8)
+    }
+
+    /// Make alpha stream for voxels with given palette indices. Glass voxels of the stream
+    /// are blended by `RleVolume::composite_column` and by `planecast.comp` on GPU.
+    pub fn alpha_stream(&self, indices: &AttributeStream<u8>) -> AttributeStream<VoxelAlpha> {
+        AttributeStream {
+            values: indices
+                .values
+                .iter()
+                .map(|i| self.get(*i).alpha())
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn properties(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn vox_properties_test() {
+        let plain =
+            Material::from_vox_properties(&properties(&[("_rough", "0.1"), ("_ior", "0.3")]));
+        assert_eq!(plain.kind, MaterialKind::Diffuse);
+        assert!((plain.ior - 1.3).abs() < 1e-6, "IOR is stored without one");
+        assert_eq!(plain.alpha(), VoxelAlpha::opaque());
+
+        let lamp = Material::from_vox_properties(&properties(&[
+            ("_type", "_emit"),
+            ("_emit", "0.5"),
+            ("_flux", "2"),
+        ]));
+        assert!(lamp.is_emissive());
+        assert!((lamp.intensity() - 50.0).abs() < 1e-3);
+
+        let glass = Material::from_vox_properties(&properties(&[
+            ("_type", "_glass"),
+            ("_trans", "0.75"),
+            ("_ri", "1.5"),
+            ("_metal", "garbage"),
+        ]));
+        assert!(glass.is_translucent() && !glass.is_emissive());
+        assert_eq!(glass.ior, 1.5);
+        assert_eq!(
+            glass.metalness, 0.0,
+            "Malformed values fall back to defaults"
+        );
+        assert_eq!(glass.alpha(), VoxelAlpha::new(64, MaterialClass::Glass));
+
+        let mut table = MaterialTable::default();
+        table.set(3, lamp);
+        table.set(7, glass);
+        assert_eq!(table.emissive().collect::<Vec<_>>(), vec![3]);
+        let indices = AttributeStream {
+            values: vec![0, 7, 3],
+        };
+        assert_eq!(
+            table.alpha_stream(&indices).values,
+            vec![VoxelAlpha::opaque(), glass.alpha(), VoxelAlpha::opaque()]
+        );
+    }
+}
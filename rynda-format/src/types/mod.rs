@@ -1,5 +1,17 @@
+#[cfg(feature = "std")]
+pub mod alpha;
+pub mod attribute;
+pub mod axis;
 pub mod column;
+#[cfg(feature = "std")]
+pub mod material;
+#[cfg(feature = "std")]
+pub mod normal;
 pub mod pointermap;
 pub mod range;
+#[cfg(feature = "serde")]
+mod serialize;
+pub mod source;
+pub mod view;
 pub mod volume;
 pub mod voxel;
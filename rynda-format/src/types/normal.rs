@@ -0,0 +1,238 @@
+use super::{attribute::AttributeStream, volume::RleVolume};
+use modular_bitfield::{specifiers::*, *};
+use std::fmt;
+
+/// Amount of bytes `PackedNormal` takes in memory
+pub const PACKED_NORMAL_SIZE: usize = 2;
+
+/// Unit normal vector quantized with octahedral mapping into 8 bits per component. The
+/// sphere is folded onto XZ plane, Y is up direction. `planecast.comp` shades voxels with
+/// normals from the stream packed by `AttributeStream::to_words`, its `decode_normal` is
+/// mirrored by `decode_normal` of this module.
+#[repr(C, packed(2))]
+#[derive(Clone, Copy)]
+#[bitfield]
+pub struct PackedNormal {
+    /// Quantized X coordinate on the octahedron projection
+    pub u: B8,
+    /// Quantized Z coordinate on the octahedron projection
+    pub v: B8,
+}
+
+impl PartialEq for PackedNormal {
+    fn eq(&self, other: &Self) -> bool {
+        self.u() == other.u() && self.v() == other.v()
+    }
+}
+
+impl Eq for PackedNormal {}
+
+impl fmt::Debug for PackedNormal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PackedNormal")
+            .field("u", &self.u())
+            .field("v", &self.v())
+            .finish()
+    }
+}
+
+impl Default for PackedNormal {
+    /// Normal that looks up
+    fn default() -> Self {
+        PackedNormal::encode([0.0, 1.0, 0.0])
+    }
+}
+
+/// Map -1.0 .. 1.0 to 0 .. 255
+fn quantize(v: f32) -> u8 {
+    ((v.clamp(-1.0, 1.0) * 0.5 + 0.5) * 255.0).round() as u8
+}
+
+/// Map 0 .. 255 to -1.0 .. 1.0
+fn dequantize(v: u8) -> f32 {
+    v as f32 / 255.0 * 2.0 - 1.0
+}
+
+fn normalize(n: [f32; 3]) -> [f32; 3] {
+    let len = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+    if len == 0.0 {
+        [0.0, 1.0, 0.0]
+    } else {
+        [n[0] / len, n[1] / len, n[2] / len]
+    }
+}
+
+impl PackedNormal {
+    /// Quantize vector, it doesn't need to be normalized. Zero vector is encoded as up direction.
+    pub fn encode(normal: [f32; 3]) -> Self {
+        let [x, y, z] = normalize(normal);
+        let l1 = x.abs() + y.abs() + z.abs();
+        let (mut px, mut pz) = (x / l1, z / l1);
+        if y < 0.0 {
+            let fx = (1.0 - pz.abs()) * px.signum();
+            let fz = (1.0 - px.abs()) * pz.signum();
+            px = fx;
+            pz = fz;
+        }
+        PackedNormal::new()
+            .with_u(quantize(px))
+            .with_v(quantize(pz))
+    }
+
+    /// Restore unit vector from quantized form
+    pub fn decode(&self) -> [f32; 3] {
+        let fx = dequantize(self.u());
+        let fz = dequantize(self.v());
+        let mut n = [fx, 1.0 - fx.abs() - fz.abs(), fz];
+        let t = (-n[1]).max(0.0);
+        n[0] += if n[0] >= 0.0 { -t } else { t };
+        n[2] += if n[2] >= 0.0 { -t } else { t };
+        normalize(n)
+    }
+}
+
+/// Mirrors `decode_normal` of `planecast.comp` that takes the normal as read by
+/// `attribute_word`
+pub fn decode_normal(packed: u32) -> [f32; 3] {
+    let f = [
+        (packed & 0xFF) as f32 / 255.0 * 2.0 - 1.0,
+        ((packed >> 8) & 0xFF) as f32 / 255.0 * 2.0 - 1.0,
+    ];
+    let mut n = [f[0], 1.0 - f[0].abs() - f[1].abs(), f[1]];
+    let t = (-n[1]).max(0.0);
+    n[0] += if n[0] >= 0.0 { -t } else { t };
+    n[2] += if n[2] >= 0.0 { -t } else { t };
+    normalize(n)
+}
+
+/// Estimate normals of all drawn voxels from occupancy of their 3x3x3 neighbourhood. The
+/// normal points from filled neighbours to empty ones, space outside of the volume is
+/// considered empty. Voxels that are surrounded from all sides get up normal.
+pub fn estimate_normals(volume: &RleVolume) -> AttributeStream<PackedNormal> {
+    let voxels = volume.to_array();
+    let (xsize, ysize, zsize) = voxels.dim();
+    let filled = |x: i64, y: i64, z: i64| {
+        x >= 0
+            && y >= 0
+            && z >= 0
+            && (x as usize) < xsize
+            && (y as usize) < ysize
+            && (z as usize) < zsize
+            && !voxels[(x as usize, y as usize, z as usize)].is_empty()
+    };
+
+    AttributeStream::from_fn(volume, |x, y, z, _| {
+        let mut gradient = [0.0f32; 3];
+        for dz in -1..=1 {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    if filled(x as i64 + dx, y as i64 + dy, z as i64 + dz) {
+                        gradient[0] -= dx as f32;
+                        gradient[1] -= dy as f32;
+                        gradient[2] -= dz as f32;
+                    }
+                }
+            }
+        }
+        PackedNormal::encode(gradient)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{
+        attribute::attribute_word,
+        voxel::{RgbVoxel, RGB_VOXEL_SIZE},
+    };
+    use ndarray::Array3;
+
+    fn assert_close(a: [f32; 3], b: [f32; 3], descr: &str) {
+        let dot = a[0] * b[0] + a[1] * b[1] + a[2] * b[2];
+        assert!(dot > 0.99, "{}: {:?} is not close to {:?}", descr, a, b);
+    }
+
+    #[test]
+    fn encode_decode_test() {
+        let normals = [
+            [0.0, 1.0, 0.0],
+            [0.0, -1.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [-1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0],
+            [0.0, 0.0, -1.0],
+            [1.0, 1.0, 1.0],
+            [-1.0, -2.0, 0.5],
+            [0.3, -0.1, -0.9],
+        ];
+        for n in normals {
+            assert_close(
+                PackedNormal::encode(n).decode(),
+                normalize(n),
+                "Encoding-decoding normal",
+            );
+        }
+        assert_eq!(
+            PackedNormal::encode([0.0, 1.0, 0.0]).into_bytes(),
+            [128, 128],
+            "Up normal is in the center of the projection"
+        );
+    }
+
+    #[test]
+    fn gpu_decode_test() {
+        let normals = AttributeStream {
+            values: (0..=u16::MAX)
+                .map(|p| PackedNormal::from_bytes(p.to_le_bytes()))
+                .collect::<Vec<_>>(),
+        };
+        let words = normals.to_words();
+        for (slot, normal) in normals.values.iter().enumerate() {
+            let packed = attribute_word(&words, (slot * RGB_VOXEL_SIZE) as u32);
+            assert_eq!(packed, slot as u32, "Shader reads the normal bits");
+            assert_eq!(
+                decode_normal(packed),
+                normal.decode(),
+                "Shader decodes {:?}",
+                normal
+            );
+        }
+    }
+
+    #[test]
+    fn estimate_normals_test() {
+        // Slab of voxels at the bottom of the volume
+        let voxels = Array3::from_shape_fn((5, 4, 5), |(_, y, _)| {
+            if y < 2 {
+                RgbVoxel::only_red(1)
+            } else {
+                RgbVoxel::empty()
+            }
+        });
+        let volume: RleVolume = voxels.into();
+        let normals = estimate_normals(&volume);
+        assert!(normals.matches(&volume), "Normals are parallel to colors");
+
+        let index = volume.column_index(2, 2);
+        let top = volume.drawn_voxels(index)[1];
+        assert_eq!(top.y, 1, "Top voxel of the slab");
+        assert_close(
+            normals.values[top.slot].decode(),
+            [0.0, 1.0, 0.0],
+            "Top surface normal",
+        );
+        let bottom = volume.drawn_voxels(index)[0];
+        assert_close(
+            normals.values[bottom.slot].decode(),
+            [0.0, -1.0, 0.0],
+            "Bottom surface normal",
+        );
+
+        let corner = volume.drawn_voxels(volume.column_index(0, 2))[1];
+        assert_close(
+            normals.values[corner.slot].decode(),
+            [-1.0, 1.0, 0.0],
+            "Edge surface normal",
+        );
+    }
+}
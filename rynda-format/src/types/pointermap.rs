@@ -1,8 +1,21 @@
-use super::range::RleRange;
+use super::{
+    range::{RleRange, RLE_RANGE_SIZE},
+    voxel::{RgbVoxel, RGB_VOXEL_SIZE},
+};
+use alloc::vec::Vec;
+use core::mem::{align_of, offset_of, size_of};
+
+/// Size of `PointerColumn` in bytes, the pointers map is uploaded to GPU as is
+pub const POINTER_COLUMN_SIZE: usize = 8;
 
 /// Describes head of Y column of voxels in pointers map.
-#[repr(packed(8))]
+///
+/// The layout is part of the GPU ABI: `pointer` at byte 0, `rle_count` at byte 4 and
+/// `first_range` at byte 6, all little endian. See `GpuPointerColumn` and `PointerTexel`
+/// for the way shaders read it.
+#[repr(C)]
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PointerColumn {
     /// Offset in columns array in bytes
     pub pointer: u32,
@@ -13,3 +26,293 @@ pub struct PointerColumn {
     /// particularly for large outdoor environments and landscape-like scenes with hills and mountains.
     pub first_range: RleRange,
 }
+
+/// Pointer column as `planecast.comp` reads it from the storage buffer: `pointer` and
+/// `fields` words, where `fields` holds `rle_count` in the low half and the first range in
+/// the high half.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GpuPointerColumn {
+    pub pointer: u32,
+    pub fields: u32,
+}
+
+impl GpuPointerColumn {
+    /// Mirrors `rle_count` of the shader
+    pub fn rle_count(&self) -> u32 {
+        self.fields & 0xFFFF
+    }
+
+    /// Mirrors `skipped` of the shader
+    pub fn skipped(&self) -> u32 {
+        (self.fields >> 16) & 0x3FF
+    }
+
+    /// Mirrors `drawn` of the shader
+    pub fn drawn(&self) -> u32 {
+        (self.fields >> 26) & 0x3F
+    }
+
+    /// Reinterpret pointers map the way the storage buffer upload does
+    pub fn from_pointers(pointers: &[PointerColumn]) -> &[GpuPointerColumn] {
+        // Size and alignment match, see the assertions below
+        unsafe {
+            core::slice::from_raw_parts(
+                pointers.as_ptr() as *const GpuPointerColumn,
+                pointers.len(),
+            )
+        }
+    }
+}
+
+/// Pack columns buffer into little endian words the way `planecast.comp` reads it from the
+/// storage buffer. The tail is padded with zeros, so the shader never reads past the end.
+pub fn column_words(columns: &[u8]) -> Vec<u32> {
+    columns
+        .chunks(4)
+        .map(|chunk| {
+            let mut word = [0; 4];
+            word[..chunk.len()].copy_from_slice(chunk);
+            u32::from_le_bytes(word)
+        })
+        .collect()
+}
+
+/// Volume as `planecast.comp` sees it in the storage buffers and uniforms
+#[derive(Clone, Copy, Debug)]
+pub struct GpuVolume<'a> {
+    /// Sizes of the volume in its layout, the `volume_size` uniform
+    pub size: [u32; 3],
+    /// Code of the axis that columns run along, the `volume_axis` uniform
+    pub axis: u32,
+    /// Pointers map in the `InputData` buffer
+    pub pointers: &'a [GpuPointerColumn],
+    /// Columns buffer in the `ColumnData` buffer, see `column_words`
+    pub columns: &'a [u32],
+}
+
+impl<'a> GpuVolume<'a> {
+    /// Mirrors `to_volume_layout` of the shader
+    pub fn to_volume_layout(&self, p: [u32; 3]) -> [u32; 3] {
+        match self.axis {
+            0 => [p[1], p[0], p[2]],
+            2 => [p[0], p[2], p[1]],
+            _ => p,
+        }
+    }
+
+    /// Mirrors `model_size` of the shader
+    pub fn model_size(&self) -> [u32; 3] {
+        self.to_volume_layout(self.size)
+    }
+
+    /// Mirrors `column_u16` of the shader
+    pub fn column_u16(&self, offset: u32) -> u32 {
+        let byte = |offset: u32| (self.columns[offset as usize / 4] >> ((offset % 4) * 8)) & 0xFF;
+        byte(offset) | (byte(offset + 1) << 8)
+    }
+
+    /// Mirrors `fetch_voxel` of the shader: byte offset of the voxel color in the columns
+    /// buffer or `None` for empty voxel
+    pub fn fetch_voxel(&self, model: [u32; 3]) -> Option<u32> {
+        let [x, y, z] = self.to_volume_layout(model);
+        let head = self.pointers[(x + z * self.size[0]) as usize];
+        let count = head.rle_count();
+        let (mut skipped, mut drawn) = (head.skipped(), head.drawn());
+        let (mut start, mut color_i) = (0, 0);
+        let mut range_i = 0;
+        loop {
+            if y < start + skipped {
+                return None;
+            }
+            if y < start + skipped + drawn {
+                let color_i = color_i + y - start - skipped;
+                return Some(
+                    head.pointer + count * RLE_RANGE_SIZE as u32 + color_i * RGB_VOXEL_SIZE as u32,
+                );
+            }
+            start += skipped + drawn;
+            color_i += drawn;
+            if range_i >= count {
+                return None;
+            }
+            let range = self.column_u16(head.pointer + range_i * RLE_RANGE_SIZE as u32);
+            skipped = range & 0x3FF;
+            drawn = (range >> 10) & 0x3F;
+            range_i += 1;
+        }
+    }
+}
+
+/// Pointer column as `pointermap.frag` reads it from the RGBA16UI texture, one texel per
+/// column
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PointerTexel {
+    pub r: u16,
+    pub g: u16,
+    pub b: u16,
+    pub a: u16,
+}
+
+impl PointerTexel {
+    /// Mirrors `pointer` of the shader
+    pub fn pointer(&self) -> u32 {
+        self.r as u32 + ((self.g as u32) << 16)
+    }
+
+    /// Mirrors `rle_count` of the shader
+    pub fn rle_count(&self) -> u32 {
+        self.b as u32 & 0xFFFF
+    }
+
+    /// Mirrors `skipped` of the shader
+    pub fn skipped(&self) -> u32 {
+        self.a as u32 & 0x3FF
+    }
+
+    /// Mirrors `drawn` of the shader
+    pub fn drawn(&self) -> u32 {
+        (self.a as u32 >> 10) & 0x3F
+    }
+
+    /// Reinterpret pointers map the way the texture upload does
+    pub fn from_pointers(pointers: &[PointerColumn]) -> &[PointerTexel] {
+        // Size matches and alignment is smaller, see the assertions below
+        unsafe {
+            core::slice::from_raw_parts(pointers.as_ptr() as *const PointerTexel, pointers.len())
+        }
+    }
+}
+
+// Layout drift of the types that go to GPU as is breaks the shaders, so it fails the build
+const _: () = {
+    assert!(size_of::<RgbVoxel>() == RGB_VOXEL_SIZE);
+    assert!(align_of::<RgbVoxel>() == 1);
+    assert!(size_of::<RleRange>() == RLE_RANGE_SIZE);
+    assert!(align_of::<RleRange>() == 1);
+
+    assert!(size_of::<PointerColumn>() == POINTER_COLUMN_SIZE);
+    assert!(align_of::<PointerColumn>() == 4);
+    assert!(offset_of!(PointerColumn, pointer) == 0);
+    assert!(offset_of!(PointerColumn, rle_count) == 4);
+    assert!(offset_of!(PointerColumn, first_range) == 6);
+
+    assert!(size_of::<GpuPointerColumn>() == POINTER_COLUMN_SIZE);
+    assert!(align_of::<GpuPointerColumn>() == align_of::<PointerColumn>());
+    assert!(offset_of!(GpuPointerColumn, pointer) == 0);
+    assert!(offset_of!(GpuPointerColumn, fields) == 4);
+
+    assert!(size_of::<PointerTexel>() == POINTER_COLUMN_SIZE);
+    assert!(align_of::<PointerTexel>() <= align_of::<PointerColumn>());
+    assert!(offset_of!(PointerTexel, b) == 4);
+    assert!(offset_of!(PointerTexel, a) == 6);
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{column::RleColumn, volume::RleVolume};
+    use alloc::vec::Vec;
+
+    #[test]
+    fn gpu_decoding_test() {
+        // Columns with long skips, long draws and many ranges to fill all bits
+        let columns = (0..40 * 40).map(|i| {
+            let (x, z) = (i % 40, i / 40);
+            let voxels: Vec<RgbVoxel> = (0..300)
+                .map(|y| {
+                    let filled = match x % 7 {
+                        0 => false,
+                        1 => y >= 290,
+                        2 => (200..280).contains(&y),
+                        3 => (y + z) % 3 == 0,
+                        m => y > m * 40 + z,
+                    };
+                    if filled {
+                        RgbVoxel::rgb(1 + (x % 30) as u8, (y % 64) as u8, (z % 32) as u8)
+                    } else {
+                        RgbVoxel::empty()
+                    }
+                })
+                .collect();
+            RleColumn::compress(&voxels)
+        });
+        let volume = RleVolume::from_columns(40, 300, 40, columns);
+        let view = volume.view();
+        let words = GpuPointerColumn::from_pointers(view.pointers);
+        let texels = PointerTexel::from_pointers(view.pointers);
+        assert_eq!(words.len(), volume.columns_count());
+        for (i, (word, texel)) in words.iter().zip(texels).enumerate() {
+            let pcol = volume.pointer_column(i);
+            let range = pcol.first_range;
+            let expected = (
+                pcol.pointer,
+                pcol.rle_count as u32,
+                range.skipped() as u32,
+                range.drawn() as u32,
+            );
+            assert_eq!(
+                (word.pointer, word.rle_count(), word.skipped(), word.drawn()),
+                expected,
+                "Storage buffer column {}",
+                i
+            );
+            assert_eq!(
+                (
+                    texel.pointer(),
+                    texel.rle_count(),
+                    texel.skipped(),
+                    texel.drawn()
+                ),
+                expected,
+                "Texture column {}",
+                i
+            );
+        }
+        assert!(
+            words.iter().any(|w| w.pointer > 0xFFFF),
+            "High half of pointer is covered"
+        );
+        assert!(words.iter().any(|w| w.skipped() > 255 && w.drawn() > 0));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn gpu_fetch_test() {
+        use crate::types::axis::RleAxis;
+        use ndarray::Array3;
+
+        let voxels = Array3::from_shape_fn((5, 70, 3), |(x, y, z)| {
+            if (x + y / 7 + z) % 3 == 0 {
+                RgbVoxel::rgb(x as u8 + 1, (y % 64) as u8, z as u8)
+            } else {
+                RgbVoxel::empty()
+            }
+        });
+        for axis in [RleAxis::X, RleAxis::Y, RleAxis::Z] {
+            let volume = RleVolume::from_array_with_axis(&voxels, axis);
+            let view = volume.view();
+            let words = column_words(view.columns);
+            assert_eq!(words.len(), view.columns.len().div_ceil(4));
+            let gpu = GpuVolume {
+                size: [view.xsize, view.ysize, view.zsize],
+                axis: axis.code() as u32,
+                pointers: GpuPointerColumn::from_pointers(view.pointers),
+                columns: &words,
+            };
+            assert_eq!(gpu.model_size(), [5, 70, 3]);
+            for ((x, y, z), voxel) in voxels.indexed_iter() {
+                let fetched = gpu.fetch_voxel([x as u32, y as u32, z as u32]);
+                let expected = u16::from_le_bytes(voxel.into_bytes()) as u32;
+                assert_eq!(
+                    fetched.map(|offset| gpu.column_u16(offset)),
+                    (!voxel.is_empty()).then_some(expected),
+                    "Voxel {:?} of {:?} volume",
+                    (x, y, z),
+                    axis
+                );
+            }
+        }
+    }
+}
@@ -1,5 +1,5 @@
+use core::fmt;
 use modular_bitfield::{specifiers::*, *};
-use std::fmt;
 
 /// Amount of bytes `RleRange` takes in packed form
 pub const RLE_RANGE_SIZE: usize = 2;
@@ -9,7 +9,7 @@ pub const RLE_SKIPPED_MAX: usize = 1024;
 pub const RLE_DRAWN_MAX: usize = 64;
 
 /// Single run length encoded range of voxels. First, skip "empty" voxels and then draw N voxels from the buffer.
-#[repr(packed(2))]
+#[repr(C, packed(2))]
 #[derive(Clone, Copy)]
 #[bitfield]
 pub struct RleRange {
@@ -0,0 +1,217 @@
+//! Serde support for the format types. Bitfield types are serialised by value as `u16`
+//! and volume as its dimensions, RLE axis and compact byte blob made by
+//! `RleVolume::to_blob`.
+use super::{axis::RleAxis, range::RleRange, volume::RleVolume, voxel::RgbVoxel};
+use alloc::vec::Vec;
+use core::fmt;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+impl Serialize for RgbVoxel {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        u16::from_le_bytes(self.into_bytes()).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for RgbVoxel {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(RgbVoxel::from_bytes(
+            u16::deserialize(deserializer)?.to_le_bytes(),
+        ))
+    }
+}
+
+impl Serialize for RleRange {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        u16::from_le_bytes(self.into_bytes()).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for RleRange {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(RleRange::from_bytes(
+            u16::deserialize(deserializer)?.to_le_bytes(),
+        ))
+    }
+}
+
+/// Byte buffer that is serialised as bytes rather than sequence of numbers
+struct Blob(Vec<u8>);
+
+impl Serialize for Blob {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.0)
+    }
+}
+
+struct BlobVisitor;
+
+impl<'de> de::Visitor<'de> for BlobVisitor {
+    type Value = Blob;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "byte array")
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Blob, E> {
+        Ok(Blob(v.to_vec()))
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Blob, E> {
+        Ok(Blob(v))
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Blob, A::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(b) = seq.next_element()? {
+            bytes.push(b);
+        }
+        Ok(Blob(bytes))
+    }
+}
+
+impl<'de> Deserialize<'de> for Blob {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_bytes(BlobVisitor)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "RleVolume")]
+struct RleVolumeRepr {
+    xsize: u32,
+    ysize: u32,
+    zsize: u32,
+    #[serde(default)]
+    axis: RleAxis,
+    data: Blob,
+}
+
+impl Serialize for RleVolume {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        RleVolumeRepr {
+            xsize: self.xsize,
+            ysize: self.ysize,
+            zsize: self.zsize,
+            axis: self.axis,
+            data: Blob(self.to_blob()),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for RleVolume {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = RleVolumeRepr::deserialize(deserializer)?;
+        let mut volume = RleVolume::from_blob(repr.xsize, repr.ysize, repr.zsize, &repr.data.0)
+            .map_err(de::Error::custom)?;
+        volume.axis = repr.axis;
+        Ok(volume)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::super::{column::RleColumn, pointermap::PointerColumn, volume::BLOB_POINTER_SIZE};
+    use super::*;
+    use ndarray::Array3;
+
+    fn test_volume() -> RleVolume {
+        let voxels = Array3::from_shape_fn((3, 70, 2), |(x, y, z)| {
+            if (x + y + z) % 3 == 0 || y > 60 {
+                RgbVoxel::rgb(x as u8 * 10, y as u8 % 64, z as u8 * 10)
+            } else {
+                RgbVoxel::empty()
+            }
+        });
+        voxels.into()
+    }
+
+    fn check_volume(volume: &RleVolume, restored: &RleVolume, format: &str) {
+        assert_eq!(
+            (restored.xsize, restored.ysize, restored.zsize),
+            (volume.xsize, volume.ysize, volume.zsize),
+            "Dimensions survive {}",
+            format
+        );
+        assert_eq!(
+            restored.to_array(),
+            volume.to_array(),
+            "Voxels survive {}",
+            format
+        );
+        assert_eq!(restored.checksum(), volume.checksum());
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let voxel = RgbVoxel::rgb(1, 2, 3);
+        let json = serde_json::to_string(&voxel).unwrap();
+        assert_eq!(serde_json::from_str::<RgbVoxel>(&json).unwrap(), voxel);
+
+        let column = RleColumn {
+            ranges: vec![RleRange::range(3, 1), RleRange::range(0, 2)],
+            colors: vec![RgbVoxel::only_red(1); 3],
+        };
+        let json = serde_json::to_string(&column).unwrap();
+        assert_eq!(serde_json::from_str::<RleColumn>(&json).unwrap(), column);
+
+        let volume = test_volume();
+        let json = serde_json::to_string(&volume).unwrap();
+        check_volume(&volume, &serde_json::from_str(&json).unwrap(), "JSON");
+    }
+
+    #[test]
+    fn ron_round_trip() {
+        let range = RleRange::range(1000, 42);
+        let ron = ron::to_string(&range).unwrap();
+        assert_eq!(ron::from_str::<RleRange>(&ron).unwrap(), range);
+
+        let pointer = PointerColumn {
+            pointer: 12,
+            rle_count: 3,
+            first_range: range,
+        };
+        let restored: PointerColumn = ron::from_str(&ron::to_string(&pointer).unwrap()).unwrap();
+        assert_eq!(restored.pointer, 12);
+        assert_eq!(restored.rle_count, 3);
+        assert_eq!(restored.first_range, range);
+
+        let volume = test_volume();
+        let ron = ron::to_string(&volume).unwrap();
+        check_volume(&volume, &ron::from_str(&ron).unwrap(), "RON");
+    }
+
+    #[test]
+    fn bincode_round_trip() {
+        let volume = test_volume();
+        let bytes = bincode::serialize(&volume).unwrap();
+        assert!(
+            bytes.len() < 3 * 4 + 8 + 6 * BLOB_POINTER_SIZE + volume.columns_size as usize + 8,
+            "Volume is serialised compactly"
+        );
+        check_volume(&volume, &bincode::deserialize(&bytes).unwrap(), "bincode");
+
+        let empty = RleVolume::empty(0, 0, 0);
+        let bytes = bincode::serialize(&empty).unwrap();
+        check_volume(&empty, &bincode::deserialize(&bytes).unwrap(), "bincode");
+    }
+
+    #[test]
+    fn damaged_blob() {
+        let volume = test_volume();
+        let mut bytes = bincode::serialize(&volume).unwrap();
+        bytes.pop();
+        // Blob length prefix is larger than the data now
+        assert!(bincode::deserialize::<RleVolume>(&bytes).is_err());
+
+        let repr = RleVolumeRepr {
+            xsize: 2,
+            ysize: 2,
+            zsize: 2,
+            axis: RleAxis::Y,
+            data: Blob(vec![0; 3]),
+        };
+        let bytes = bincode::serialize(&repr).unwrap();
+        assert!(bincode::deserialize::<RleVolume>(&bytes).is_err());
+    }
+}
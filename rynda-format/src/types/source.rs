@@ -0,0 +1,78 @@
+use super::{axis::RleAxis, volume::RleVolume, voxel::RgbVoxel};
+
+/// Anything that can be sampled voxel by voxel in signed coordinates. Coordinates
+/// outside of the stored data are expected to return empty voxels, that allows
+/// algorithms to look over the borders of a volume or a chunk.
+pub trait VoxelSource {
+    /// Get voxel at given coordinates, `y` is up direction
+    fn voxel(&self, x: i32, y: i32, z: i32) -> RgbVoxel;
+
+    /// Fill `column` with voxels at X, Z from height `y0` upward. Sampling voxel by voxel
+    /// is the default, sources that store columns should decode each of them once.
+    fn voxel_column(&self, x: i32, y0: i32, z: i32, column: &mut [RgbVoxel]) {
+        for (i, voxel) in column.iter_mut().enumerate() {
+            *voxel = self.voxel(x, y0 + i as i32, z);
+        }
+    }
+}
+
+impl VoxelSource for RleVolume {
+    fn voxel(&self, x: i32, y: i32, z: i32) -> RgbVoxel {
+        let [xsize, ysize, zsize] = self.dimensions();
+        if x < 0 || y < 0 || z < 0 || x as u32 >= xsize || y as u32 >= ysize || z as u32 >= zsize {
+            RgbVoxel::empty()
+        } else {
+            self.get(x as u32, y as u32, z as u32)
+        }
+    }
+
+    fn voxel_column(&self, x: i32, y0: i32, z: i32, column: &mut [RgbVoxel]) {
+        let [xsize, _, zsize] = self.dimensions();
+        if self.axis != RleAxis::Y {
+            for (i, voxel) in column.iter_mut().enumerate() {
+                *voxel = self.voxel(x, y0 + i as i32, z);
+            }
+            return;
+        }
+        column.fill(RgbVoxel::empty());
+        if x < 0 || z < 0 || x as u32 >= xsize || z as u32 >= zsize {
+            return;
+        }
+        let voxels = self.column(x as u32, z as u32).decompress();
+        for (i, voxel) in column.iter_mut().enumerate() {
+            let y = y0 + i as i32;
+            if y >= 0 && (y as usize) < voxels.len() {
+                *voxel = voxels[y as usize];
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use ndarray::Array3;
+
+    #[test]
+    fn voxel_column_test() {
+        let voxels = Array3::from_shape_fn((3, 5, 4), |(x, y, z)| {
+            if (x + y + z) % 2 == 0 {
+                RgbVoxel::rgb(x as u8 + 1, y as u8, z as u8)
+            } else {
+                RgbVoxel::empty()
+            }
+        });
+        for axis in [RleAxis::X, RleAxis::Y, RleAxis::Z] {
+            let volume = RleVolume::from_array_with_axis(&voxels, axis);
+            let mut column = [RgbVoxel::only_red(1); 9];
+            for x in -1..4 {
+                for z in -1..5 {
+                    volume.voxel_column(x, -2, z, &mut column);
+                    for (i, voxel) in column.iter().enumerate() {
+                        assert_eq!(*voxel, volume.voxel(x, i as i32 - 2, z), "{:?}", axis);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,319 @@
+use super::{
+    axis::RleAxis,
+    column::RleColumn,
+    pointermap::PointerColumn,
+    range::{RleRange, RLE_RANGE_SIZE},
+    volume::{PackedColumn, RleVolume},
+    voxel::{RgbVoxel, RGB_VOXEL_SIZE},
+};
+use core::fmt;
+use core::mem;
+
+/// Reasons why bytes cannot be viewed as a volume
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ViewError {
+    /// Pointers map is not aligned for `PointerColumn`
+    Misaligned,
+    /// Pointers map has other size than the dimensions require
+    SizeMismatch,
+    /// Volume is larger than the format allows
+    TooLarge,
+    /// In-memory layout is little endian and can't be viewed on big endian targets
+    BigEndian,
+    /// Column with the index points outside of the columns buffer
+    ColumnOutOfBounds(usize),
+    /// Column with the index is higher than the volume
+    ColumnTooHigh(usize),
+}
+
+impl fmt::Display for ViewError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ViewError::Misaligned => write!(f, "Pointers map is not aligned"),
+            ViewError::SizeMismatch => write!(f, "Pointers map doesn't match dimensions"),
+            ViewError::TooLarge => write!(f, "Volume is too large"),
+            ViewError::BigEndian => write!(f, "Volume can't be viewed on big endian target"),
+            ViewError::ColumnOutOfBounds(i) => write!(f, "Column {} is out of buffer", i),
+            ViewError::ColumnTooHigh(i) => write!(f, "Column {} is higher than volume", i),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ViewError {}
+
+/// Read-only volume that borrows its pointers map and columns buffer, e.g. from memory
+/// mapped file. Layout of the data is the same as in `RleVolume`, so the view can be
+/// uploaded to GPU without copying.
+#[derive(Clone, Copy, Debug)]
+pub struct RleVolumeView<'a> {
+    /// Size of volume by X axis in its layout
+    pub xsize: u32,
+    /// Size of volume by Y axis in its layout, i.e. maximum height of columns
+    pub ysize: u32,
+    /// Size of volume by Z axis in its layout
+    pub zsize: u32,
+    /// Axis of the model that columns run along
+    pub axis: RleAxis,
+    /// Contains xsize*zsize heads of columns
+    pub pointers: &'a [PointerColumn],
+    /// Packed ranges and colors of columns
+    pub columns: &'a [u8],
+}
+
+impl<'a> RleVolumeView<'a> {
+    /// View bytes as volume. Pointers map must be aligned for `PointerColumn` and all columns
+    /// are checked to lie inside the columns buffer and to fit the height, so the bytes can
+    /// come from untrusted file.
+    pub fn from_raw_parts(
+        size: [u32; 3],
+        axis: RleAxis,
+        pointers: &'a [u8],
+        columns: &'a [u8],
+    ) -> Result<Self, ViewError> {
+        if cfg!(target_endian = "big") {
+            return Err(ViewError::BigEndian);
+        }
+        let [xsize, ysize, zsize] = size;
+        if xsize >= 1024 || ysize >= 1024 || zsize >= 1024 || columns.len() > u32::MAX as usize {
+            return Err(ViewError::TooLarge);
+        }
+        let count = (xsize * zsize) as usize;
+        if pointers.len() != count * mem::size_of::<PointerColumn>() {
+            return Err(ViewError::SizeMismatch);
+        }
+        if !(pointers.as_ptr() as usize).is_multiple_of(mem::align_of::<PointerColumn>()) {
+            return Err(ViewError::Misaligned);
+        }
+        // Alignment and size are checked above and any bit pattern is valid `PointerColumn`
+        let pointers = unsafe {
+            core::slice::from_raw_parts(pointers.as_ptr() as *const PointerColumn, count)
+        };
+        let view = RleVolumeView {
+            xsize,
+            ysize,
+            zsize,
+            axis,
+            pointers,
+            columns,
+        };
+        for i in 0..count {
+            view.validate_column(i)?;
+        }
+        Ok(view)
+    }
+
+    fn validate_column(&self, index: usize) -> Result<(), ViewError> {
+        let pcol = &self.pointers[index];
+        let start = pcol.pointer as usize;
+        let ranges_end = start + pcol.rle_count as usize * RLE_RANGE_SIZE;
+        if ranges_end > self.columns.len() {
+            return Err(ViewError::ColumnOutOfBounds(index));
+        }
+        let first = pcol.first_range;
+        let mut height = first.skipped() as usize + first.drawn() as usize;
+        let mut drawn = first.drawn() as usize;
+        for range in self.columns[start..ranges_end].chunks_exact(RLE_RANGE_SIZE) {
+            let range = RleRange::from_bytes([range[0], range[1]]);
+            height += range.skipped() as usize + range.drawn() as usize;
+            drawn += range.drawn() as usize;
+        }
+        if ranges_end + drawn * RGB_VOXEL_SIZE > self.columns.len() {
+            return Err(ViewError::ColumnOutOfBounds(index));
+        }
+        if height > self.ysize as usize {
+            return Err(ViewError::ColumnTooHigh(index));
+        }
+        Ok(())
+    }
+
+    /// Amount of columns in the pointers map
+    pub fn columns_count(&self) -> usize {
+        self.pointers.len()
+    }
+
+    /// Sizes of the volume along model X, Y and Z axes
+    pub fn dimensions(&self) -> [u32; 3] {
+        self.axis.swap([self.xsize, self.ysize, self.zsize])
+    }
+
+    /// Pointers map as raw bytes in the layout the GPU expects
+    pub fn pointers_bytes(&self) -> &'a [u8] {
+        unsafe {
+            core::slice::from_raw_parts(
+                self.pointers.as_ptr() as *const u8,
+                mem::size_of_val(self.pointers),
+            )
+        }
+    }
+
+    /// Get slice of the columns buffer that holds the column
+    pub fn column_bytes(&self, index: usize) -> &'a [u8] {
+        let pcol = &self.pointers[index];
+        let start = pcol.pointer as usize;
+        let ranges_end = start + pcol.rle_count as usize * RLE_RANGE_SIZE;
+        let drawn: usize = pcol.first_range.drawn() as usize
+            + self.columns[start..ranges_end]
+                .chunks_exact(RLE_RANGE_SIZE)
+                .map(|r| RleRange::from_bytes([r[0], r[1]]).drawn() as usize)
+                .sum::<usize>();
+        &self.columns[start..ranges_end + drawn * RGB_VOXEL_SIZE]
+    }
+
+    /// Copy the column in its packed form
+    pub fn packed_column(&self, index: usize) -> PackedColumn {
+        let pcol = &self.pointers[index];
+        PackedColumn {
+            rle_count: pcol.rle_count,
+            first_range: pcol.first_range,
+            bytes: self.column_bytes(index).to_vec(),
+        }
+    }
+
+    /// Unpack the whole column (including first range) by its index in the pointers map
+    pub fn column_at(&self, index: usize) -> RleColumn {
+        self.packed_column(index).unpack()
+    }
+
+    /// Get voxel at given model coordinates
+    pub fn get(&self, x: u32, y: u32, z: u32) -> RgbVoxel {
+        let [x, y, z] = self.axis.swap([x, y, z]);
+        assert!(x < self.xsize && z < self.zsize, "Voxel is out of volume");
+        let index = (x + z * self.xsize) as usize;
+        self.column_at(index)
+            .decompress()
+            .get(y as usize)
+            .copied()
+            .unwrap_or_else(RgbVoxel::empty)
+    }
+
+    /// Copy the view into owned volume
+    pub fn to_volume(&self) -> RleVolume {
+        let mut volume = RleVolume::from_columns(
+            self.xsize as usize,
+            self.ysize as usize,
+            self.zsize as usize,
+            (0..self.columns_count()).map(|i| self.column_at(i)),
+        );
+        volume.axis = self.axis;
+        volume
+    }
+}
+
+impl RleVolume {
+    /// Borrow the volume as a view, e.g. to use the same upload code for owned and mapped
+    /// volumes.
+    pub fn view(&self) -> RleVolumeView<'_> {
+        let count = self.columns_count();
+        unsafe {
+            RleVolumeView {
+                xsize: self.xsize,
+                ysize: self.ysize,
+                zsize: self.zsize,
+                axis: self.axis,
+                pointers: if count == 0 {
+                    &[]
+                } else {
+                    core::slice::from_raw_parts(self.pointers, count)
+                },
+                columns: if self.columns_size == 0 {
+                    &[]
+                } else {
+                    core::slice::from_raw_parts(self.columns, self.columns_size as usize)
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::column::RleColumn;
+    use alloc::{vec, vec::Vec};
+
+    fn test_volume() -> RleVolume {
+        let columns = (0..5 * 4).map(|i| {
+            let (x, z) = (i % 5, i / 5);
+            let voxels: Vec<RgbVoxel> = (0..9)
+                .map(|y| {
+                    if (x * 3 + y + z * 5) % 4 == 0 {
+                        RgbVoxel::rgb(x as u8, y as u8, z as u8 + 1)
+                    } else {
+                        RgbVoxel::empty()
+                    }
+                })
+                .collect();
+            RleColumn::compress(&voxels)
+        });
+        RleVolume::from_columns(5, 9, 4, columns)
+    }
+
+    /// Copy bytes into buffer aligned for `PointerColumn`
+    fn aligned(bytes: &[u8]) -> Vec<u64> {
+        let mut buffer = vec![0u64; bytes.len().div_ceil(8)];
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                bytes.as_ptr(),
+                buffer.as_mut_ptr() as *mut u8,
+                bytes.len(),
+            );
+        }
+        buffer
+    }
+
+    fn as_bytes(buffer: &[u64], len: usize) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(buffer.as_ptr() as *const u8, len) }
+    }
+
+    #[test]
+    fn view_test() {
+        let volume = test_volume();
+        let view = volume.view();
+        let pointers = aligned(view.pointers_bytes());
+        let pointers = as_bytes(&pointers, view.pointers_bytes().len());
+        let mapped = RleVolumeView::from_raw_parts(
+            [view.xsize, view.ysize, view.zsize],
+            view.axis,
+            pointers,
+            view.columns,
+        )
+        .unwrap();
+        let restored = mapped.to_volume();
+        assert_eq!(restored.dimensions(), volume.dimensions());
+        assert_eq!(restored.to_blob(), volume.to_blob());
+        assert_eq!(mapped.get(1, 2, 3), volume.get(1, 2, 3));
+        assert_eq!(mapped.column_bytes(7), volume.column_bytes(7));
+    }
+
+    #[test]
+    fn invalid_view_test() {
+        let volume = test_volume();
+        let view = volume.view();
+        let len = view.pointers_bytes().len();
+        let buffer = aligned(&[&[0u8; 8][..], view.pointers_bytes()].concat());
+        let shifted = &as_bytes(&buffer, len + 8)[1..len + 1];
+        let size = [view.xsize, view.ysize, view.zsize];
+        assert_eq!(
+            RleVolumeView::from_raw_parts(size, view.axis, shifted, view.columns).unwrap_err(),
+            ViewError::Misaligned
+        );
+
+        let pointers = aligned(view.pointers_bytes());
+        let pointers = as_bytes(&pointers, len);
+        assert_eq!(
+            RleVolumeView::from_raw_parts(size, view.axis, &pointers[8..], view.columns)
+                .unwrap_err(),
+            ViewError::SizeMismatch
+        );
+        let truncated = &view.columns[..view.columns.len() - 1];
+        assert!(matches!(
+            RleVolumeView::from_raw_parts(size, view.axis, pointers, truncated),
+            Err(ViewError::ColumnOutOfBounds(_))
+        ));
+        assert!(matches!(
+            RleVolumeView::from_raw_parts([size[0], 2, size[2]], view.axis, pointers, view.columns),
+            Err(ViewError::ColumnTooHigh(_))
+        ));
+    }
+}
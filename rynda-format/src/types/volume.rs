@@ -1,7 +1,18 @@
-use super::{column::RleColumn, pointermap::PointerColumn, range::RleRange, voxel::RgbVoxel};
+use super::{
+    axis::RleAxis,
+    column::RleColumn,
+    pointermap::PointerColumn,
+    range::{RleRange, RLE_RANGE_SIZE},
+    voxel::{RgbVoxel, RGB_VOXEL_SIZE},
+};
+use alloc::alloc::{alloc, dealloc, Layout};
+use alloc::{vec, vec::Vec};
+use core::ptr;
+#[cfg(feature = "std")]
 use ndarray::{s, Array3, Axis};
-use std::alloc::{alloc, dealloc, Layout};
-use std::ptr;
+
+/// Size of single pointer column in the blob made by `RleVolume::to_blob`
+pub const BLOB_POINTER_SIZE: usize = 4;
 
 /// Run length encoded volume of voxels that consists of two parts. Flat pointers map
 /// and columns buffer itself.
@@ -13,6 +24,9 @@ pub struct RleVolume {
     pub ysize: u32,
     /// Size of volume by Z axis. Number of columns in Z axis of pointers buffer.
     pub zsize: u32,
+    /// Axis of the model that columns run along. Sizes and columns of the volume are given
+    /// in its own layout, where the axis is swapped with Y.
+    pub axis: RleAxis,
     /// Contains xsize*zsize elements that defines begining of RLE columns of voxel.
     pub pointers: *mut PointerColumn,
     /// Size of columns buffer in bytes, used for fast copying the volume.
@@ -24,18 +38,55 @@ pub struct RleVolume {
     pub columns: *mut u8,
 }
 
+// The volume exclusively owns memory behind its pointers and never mutates it through
+// shared references, so it can be sent to and shared between threads.
+unsafe impl Send for RleVolume {}
+unsafe impl Sync for RleVolume {}
+
+impl Clone for RleVolume {
+    fn clone(&self) -> Self {
+        let num_pointers = self.columns_count();
+        let mut pointers = ptr::null::<PointerColumn>() as *mut PointerColumn;
+        let mut columns = ptr::null::<u8>() as *mut u8;
+        unsafe {
+            if num_pointers > 0 {
+                pointers = alloc(Layout::array::<PointerColumn>(num_pointers).unwrap())
+                    as *mut PointerColumn;
+                pointers.copy_from_nonoverlapping(self.pointers, num_pointers);
+            }
+            if self.columns_size > 0 {
+                columns = alloc(Layout::array::<u8>(self.columns_size as usize).unwrap());
+                columns.copy_from_nonoverlapping(self.columns, self.columns_size as usize);
+            }
+        }
+        RleVolume {
+            xsize: self.xsize,
+            ysize: self.ysize,
+            zsize: self.zsize,
+            axis: self.axis,
+            pointers,
+            columns_size: self.columns_size,
+            columns,
+        }
+    }
+}
+
 impl Drop for RleVolume {
     fn drop(&mut self) {
         let num_pointers = (self.xsize * self.zsize) as usize;
         unsafe {
-            dealloc(
-                self.pointers as *mut u8,
-                Layout::array::<PointerColumn>(num_pointers).unwrap(),
-            );
-            dealloc(
-                self.columns,
-                Layout::array::<u8>(self.columns_size as usize).unwrap(),
-            );
+            if num_pointers > 0 {
+                dealloc(
+                    self.pointers as *mut u8,
+                    Layout::array::<PointerColumn>(num_pointers).unwrap(),
+                );
+            }
+            if self.columns_size > 0 {
+                dealloc(
+                    self.columns,
+                    Layout::array::<u8>(self.columns_size as usize).unwrap(),
+                );
+            }
         }
     }
 }
@@ -57,36 +108,24 @@ impl RleVolume {
             "zsize of RleVolume is bigger than or equal to 1024!"
         );
 
-        let pointers;
-        unsafe {
-            let num_pointers = xsize * zsize;
-            pointers =
-                alloc(Layout::array::<PointerColumn>(num_pointers).unwrap()) as *mut PointerColumn;
-            for i in 0..num_pointers {
-                let point = pointers.add(i);
-                *point = PointerColumn {
-                    pointer: 0,
-                    rle_count: 0,
-                    first_range: RleRange::new().with_skipped(ysize as u16).with_drawn(0),
-                };
-            }
-        }
-
-        RleVolume {
-            xsize: xsize as u32,
-            ysize: ysize as u32,
-            zsize: zsize as u32,
-            pointers,
-            columns_size: 0,
-            columns: ptr::null::<u8>() as *mut u8,
-        }
+        let column = RleColumn {
+            ranges: vec![RleRange::range(ysize as u16, 0)],
+            colors: vec![],
+        };
+        RleVolume::from_columns(
+            xsize,
+            ysize,
+            zsize,
+            core::iter::repeat_n(column, xsize * zsize),
+        )
     }
-}
-
-impl From<Array3<RgbVoxel>> for RleVolume {
-    fn from(array: Array3<RgbVoxel>) -> Self {
-        let (xsize, ysize, zsize) = array.dim();
 
+    /// Construct volume from full RLE columns (including first range) that are given in
+    /// order of pointers map, i.e. X changes first and Z changes last.
+    pub fn from_columns<I>(xsize: usize, ysize: usize, zsize: usize, columns: I) -> Self
+    where
+        I: IntoIterator<Item = RleColumn>,
+    {
         assert!(
             xsize < 1024,
             "xsize of RleVolume is bigger than or equal to 1024!"
@@ -100,47 +139,56 @@ impl From<Array3<RgbVoxel>> for RleVolume {
             "zsize of RleVolume is bigger than or equal to 1024!"
         );
 
-        let pointers;
-        let mut columns: Vec<RleColumn> = vec![];
+        let num_pointers = xsize * zsize;
+        let mut heads = Vec::with_capacity(num_pointers);
+        let mut rests = Vec::with_capacity(num_pointers);
         let mut columns_offset: usize = 0;
-        unsafe {
-            let num_pointers = xsize * zsize;
-            pointers =
-                alloc(Layout::array::<PointerColumn>(num_pointers).unwrap()) as *mut PointerColumn;
-            for i in 0..num_pointers {
-                let x = i % xsize;
-                let z = i / zsize;
-                let column = array
-                    .slice(s![x..x + 1, .., z..z + 1])
-                    .remove_axis(Axis(2))
-                    .remove_axis(Axis(0));
-                let rle_col = RleColumn::compress(&column.to_vec());
-                let (first_range, rest_column) = rle_col.split_head().unwrap();
-                let point = pointers.add(i);
-                let rle_count = rest_column.intervals_count();
-                assert!(
-                    rle_count < 65536,
-                    "RLE intervals overflow in single column, expected less than {:?}, got {:?}",
-                    65536,
-                    rle_count
-                );
-                *point = PointerColumn {
-                    pointer: columns_offset as u32,
-                    rle_count: rle_count as u16,
-                    first_range,
-                };
-                columns.push(rest_column.clone());
-                columns_offset += rest_column.memory_size();
-            }
+        for rle_col in columns.into_iter().take(num_pointers) {
+            let (first_range, rest_column) = rle_col
+                .split_head()
+                .unwrap_or_else(|| (RleRange::range(0, 0), RleColumn::compress(&[])));
+            let rle_count = rest_column.intervals_count();
+            assert!(
+                rle_count < 65536,
+                "RLE intervals overflow in single column, expected less than {:?}, got {:?}",
+                65536,
+                rle_count
+            );
+            heads.push(PointerColumn {
+                pointer: columns_offset as u32,
+                rle_count: rle_count as u16,
+                first_range,
+            });
+            columns_offset += rest_column.memory_size();
+            rests.push(rest_column);
         }
+        assert_eq!(
+            heads.len(),
+            num_pointers,
+            "Expected {} columns for volume {}x{}x{}",
+            num_pointers,
+            xsize,
+            ysize,
+            zsize
+        );
 
-        let columns_array;
+        let mut pointers = ptr::null::<PointerColumn>() as *mut PointerColumn;
+        let mut columns_array = ptr::null::<u8>() as *mut u8;
         let mut offset: usize = 0;
         unsafe {
-            columns_array = alloc(Layout::array::<u8>(columns_offset).unwrap());
-            for c in columns {
-                let start = columns_array.add(offset);
-                offset += c.pack_into(start);
+            if num_pointers > 0 {
+                pointers = alloc(Layout::array::<PointerColumn>(num_pointers).unwrap())
+                    as *mut PointerColumn;
+                for (i, head) in heads.into_iter().enumerate() {
+                    pointers.add(i).write(head);
+                }
+            }
+            if columns_offset > 0 {
+                columns_array = alloc(Layout::array::<u8>(columns_offset).unwrap());
+                for c in rests {
+                    let start = columns_array.add(offset);
+                    offset += c.pack_into(start);
+                }
             }
         }
         assert_eq!(columns_offset, offset, "Memory sizes should be equal");
@@ -149,47 +197,498 @@ impl From<Array3<RgbVoxel>> for RleVolume {
             xsize: xsize as u32,
             ysize: ysize as u32,
             zsize: zsize as u32,
+            axis: RleAxis::Y,
             pointers,
             columns_size: columns_offset as u32,
             columns: columns_array,
         }
     }
-}
 
-impl From<RleVolume> for Array3<RgbVoxel> {
-    fn from(volume: RleVolume) -> Array3<RgbVoxel> {
+    /// Encode dense array of voxels with RLE columns along the given model axis
+    #[cfg(feature = "std")]
+    pub fn from_array_with_axis(array: &Array3<RgbVoxel>, axis: RleAxis) -> Self {
+        let p = axis.permutation();
+        let layout = array
+            .view()
+            .permuted_axes(p)
+            .as_standard_layout()
+            .into_owned();
+        let mut volume: RleVolume = layout.into();
+        volume.axis = axis;
+        volume
+    }
+
+    /// Memory the array takes when encoded along each of the axes in bytes
+    #[cfg(feature = "std")]
+    pub fn axis_memory_usage(array: &Array3<RgbVoxel>) -> [(RleAxis, usize); 3] {
+        super::axis::RLE_AXES.map(|axis| {
+            (
+                axis,
+                RleVolume::from_array_with_axis(array, axis).memory_size(),
+            )
+        })
+    }
+
+    /// Encode array along the axis that gives the smallest volume. Y wins ties.
+    #[cfg(feature = "std")]
+    pub fn from_array_smallest(array: &Array3<RgbVoxel>) -> Self {
+        let mut best = RleVolume::from_array_with_axis(array, RleAxis::Y);
+        for axis in [RleAxis::X, RleAxis::Z] {
+            let volume = RleVolume::from_array_with_axis(array, axis);
+            if volume.memory_size() < best.memory_size() {
+                best = volume;
+            }
+        }
+        best
+    }
+
+    /// Sizes of the volume along model X, Y and Z axes
+    pub fn dimensions(&self) -> [u32; 3] {
+        self.axis.swap([self.xsize, self.ysize, self.zsize])
+    }
+
+    /// Memory taken by pointers map and columns buffer in bytes
+    pub fn memory_size(&self) -> usize {
+        self.columns_count() * core::mem::size_of::<PointerColumn>() + self.columns_size as usize
+    }
+
+    /// Amount of columns in the pointers map
+    pub fn columns_count(&self) -> usize {
+        (self.xsize * self.zsize) as usize
+    }
+
+    /// Index of XZ column in the pointers map
+    pub fn column_index(&self, x: u32, z: u32) -> usize {
+        assert!(
+            x < self.xsize && z < self.zsize,
+            "Column {}x{} is out of volume {}x{}",
+            x,
+            z,
+            self.xsize,
+            self.zsize
+        );
+        (x + z * self.xsize) as usize
+    }
+
+    /// Get head of the column by its index in the pointers map
+    pub fn pointer_column(&self, index: usize) -> &PointerColumn {
+        assert!(index < self.columns_count(), "Column index out of range");
+        unsafe { &*self.pointers.add(index) }
+    }
+
+    /// Unpack the whole column (including first range) by its index in the pointers map
+    pub fn column_at(&self, index: usize) -> RleColumn {
+        let pcol = self.pointer_column(index);
+        unsafe {
+            RleColumn::unpack_from(
+                self.columns.add(pcol.pointer as usize),
+                pcol.rle_count as usize,
+                Some(pcol.first_range),
+            )
+        }
+    }
+
+    /// Unpack the whole column (including first range) at given XZ coordinates
+    pub fn column(&self, x: u32, z: u32) -> RleColumn {
+        self.column_at(self.column_index(x, z))
+    }
+
+    /// Get voxel at given model coordinates without unpacking the column. Voxels above
+    /// the stored column height are empty.
+    pub fn get(&self, x: u32, y: u32, z: u32) -> RgbVoxel {
+        let [x, y, z] = self.axis.swap([x, y, z]);
+        let pcol = self.pointer_column(self.column_index(x, z));
+        let mut range = pcol.first_range;
+        let mut range_i = 0;
+        let mut color_i = 0;
+        let mut start = 0;
+        loop {
+            let skipped = range.skipped() as u32;
+            let drawn = range.drawn() as u32;
+            if y < start + skipped {
+                return RgbVoxel::empty();
+            }
+            if y < start + skipped + drawn {
+                color_i += (y - start - skipped) as usize;
+                break;
+            }
+            start += skipped + drawn;
+            color_i += drawn as usize;
+            if range_i >= pcol.rle_count as usize {
+                return RgbVoxel::empty();
+            }
+            let mut range_bytes = [0; RLE_RANGE_SIZE];
+            unsafe {
+                let ptr = self
+                    .columns
+                    .add(pcol.pointer as usize + range_i * RLE_RANGE_SIZE);
+                range_bytes
+                    .as_mut_ptr()
+                    .copy_from_nonoverlapping(ptr, RLE_RANGE_SIZE);
+            }
+            range = RleRange::from_bytes(range_bytes);
+            range_i += 1;
+        }
+
+        let mut color_bytes = [0; RGB_VOXEL_SIZE];
+        unsafe {
+            let ptr = self.columns.add(
+                pcol.pointer as usize
+                    + (pcol.rle_count as usize) * RLE_RANGE_SIZE
+                    + color_i * RGB_VOXEL_SIZE,
+            );
+            color_bytes
+                .as_mut_ptr()
+                .copy_from_nonoverlapping(ptr, RGB_VOXEL_SIZE);
+        }
+        RgbVoxel::from_bytes(color_bytes)
+    }
+
+    /// Set voxel at given model coordinates. The column is repacked, so slots of attribute
+    /// streams become invalid. Use `replace_columns` to edit many voxels at once.
+    pub fn set(&mut self, x: u32, y: u32, z: u32, voxel: RgbVoxel) {
+        let [x, y, z] = self.axis.swap([x, y, z]);
+        assert!(y < self.ysize, "Voxel {} is above the volume", y);
+        let index = self.column_index(x, z);
+        let mut voxels = self.column_at(index).decompress();
+        let y = y as usize;
+        if voxels.len() <= y {
+            if voxel.is_empty() {
+                return;
+            }
+            voxels.resize(y + 1, RgbVoxel::empty());
+        }
+        voxels[y] = voxel;
+        self.replace_columns([(index, RleColumn::compress(&voxels))]);
+    }
+
+    /// Decompress the volume into dense array of voxels in model coordinates
+    #[cfg(feature = "std")]
+    pub fn to_array(&self) -> Array3<RgbVoxel> {
+        let arr = self.to_layout_array();
+        if self.axis == RleAxis::Y {
+            arr
+        } else {
+            let p = self.axis.permutation();
+            arr.permuted_axes(p).as_standard_layout().into_owned()
+        }
+    }
+
+    /// Decompress the volume into dense array of voxels in layout of the volume, where
+    /// columns run along Y.
+    #[cfg(feature = "std")]
+    pub fn to_layout_array(&self) -> Array3<RgbVoxel> {
         let mut arr = Array3::zeros((
-            volume.xsize as usize,
-            volume.ysize as usize,
-            volume.zsize as usize,
+            self.xsize as usize,
+            self.ysize as usize,
+            self.zsize as usize,
         ));
 
-        unsafe {
-            let num_pointers = (volume.xsize * volume.zsize) as usize;
-            for i in 0..num_pointers {
-                let x = i % (volume.xsize as usize);
-                let z = i / (volume.zsize as usize);
-                let pcol = &*volume.pointers.add(i);
-                let col = RleColumn::unpack_from(
-                    volume.columns.offset(pcol.pointer as isize),
-                    pcol.rle_count as usize,
-                    Some(pcol.first_range),
-                );
+        for i in 0..self.columns_count() {
+            let x = i % (self.xsize as usize);
+            let z = i / (self.xsize as usize);
+            for (y, color) in self.column_at(i).decompress().iter().enumerate() {
+                arr[(x, y, z)] = *color;
+            }
+        }
+
+        arr
+    }
+
+    /// Amount of voxel sized slots in the columns buffer. Attribute streams that are
+    /// parallel to the color data have this length.
+    pub fn color_slots(&self) -> usize {
+        self.columns_size as usize / RGB_VOXEL_SIZE
+    }
 
-                for (y, color) in col.decompress().iter().enumerate() {
-                    arr[(x, y, z)] = *color;
+    /// Slot of the first color of the column in the columns buffer. Colors of a column are
+    /// stored sequentially, so k-th drawn voxel of the column is at `first_color_slot + k`.
+    pub fn first_color_slot(&self, index: usize) -> usize {
+        let pcol = self.pointer_column(index);
+        (pcol.pointer as usize + (pcol.rle_count as usize) * RLE_RANGE_SIZE) / RGB_VOXEL_SIZE
+    }
+
+    /// Return drawn voxels of the column with their heights and color slots
+    pub fn drawn_voxels(&self, index: usize) -> Vec<DrawnVoxel> {
+        let column = self.column_at(index);
+        let mut slot = self.first_color_slot(index);
+        let mut voxels = Vec::with_capacity(column.colors.len());
+        let mut y = 0;
+        let mut colors = column.colors.iter();
+        for range in column.ranges.iter() {
+            y += range.skipped() as u32;
+            for _ in 0..range.drawn() {
+                voxels.push(DrawnVoxel {
+                    y,
+                    slot,
+                    color: *colors.next().unwrap(),
+                });
+                y += 1;
+                slot += 1;
+            }
+        }
+        voxels
+    }
+
+    /// Amount of bytes the column (without first range) takes in the columns buffer
+    pub fn column_size(&self, index: usize) -> usize {
+        let pcol = self.pointer_column(index);
+        let mut drawn = pcol.first_range.drawn() as usize;
+        for i in 0..pcol.rle_count as usize {
+            let mut range_bytes = [0; RLE_RANGE_SIZE];
+            unsafe {
+                let ptr = self.columns.add(pcol.pointer as usize + i * RLE_RANGE_SIZE);
+                range_bytes
+                    .as_mut_ptr()
+                    .copy_from_nonoverlapping(ptr, RLE_RANGE_SIZE);
+            }
+            drawn += RleRange::from_bytes(range_bytes).drawn() as usize;
+        }
+        (pcol.rle_count as usize) * RLE_RANGE_SIZE + drawn * RGB_VOXEL_SIZE
+    }
+
+    /// Get slice of the columns buffer that holds the column
+    pub fn column_bytes(&self, index: usize) -> &[u8] {
+        let size = self.column_size(index);
+        if size == 0 {
+            return &[];
+        }
+        let pcol = self.pointer_column(index);
+        unsafe { core::slice::from_raw_parts(self.columns.add(pcol.pointer as usize), size) }
+    }
+
+    /// Copy the column in its packed form
+    pub fn packed_column(&self, index: usize) -> PackedColumn {
+        let pcol = self.pointer_column(index);
+        PackedColumn {
+            rle_count: pcol.rle_count,
+            first_range: pcol.first_range,
+            bytes: self.column_bytes(index).to_vec(),
+        }
+    }
+
+    /// Replace some columns of the volume given by their indices in the pointers map. The
+    /// columns buffer is repacked, so slots of attribute streams become invalid.
+    pub fn replace_columns<I>(&mut self, columns: I)
+    where
+        I: IntoIterator<Item = (usize, RleColumn)>,
+    {
+        let mut replaced: Vec<Option<RleColumn>> = vec![None; self.columns_count()];
+        let mut any = false;
+        for (index, column) in columns {
+            assert!(index < replaced.len(), "Column index out of range");
+            replaced[index] = Some(column);
+            any = true;
+        }
+        if !any {
+            return;
+        }
+        let mut volume = RleVolume::from_columns(
+            self.xsize as usize,
+            self.ysize as usize,
+            self.zsize as usize,
+            replaced
+                .into_iter()
+                .enumerate()
+                .map(|(i, c)| c.unwrap_or_else(|| self.column_at(i))),
+        );
+        volume.axis = self.axis;
+        *self = volume;
+    }
+
+    /// Pack the volume into compact byte blob that doesn't depend on memory layout:
+    ///
+    /// - `rle_count: u16` and `first_range: u16` of each pointer column in little endian
+    /// - columns buffer as is
+    ///
+    /// Offsets of columns are restored from their ranges, so the blob has no pointers in it.
+    /// Dimensions and RLE axis are not included.
+    pub fn to_blob(&self) -> Vec<u8> {
+        let columns = self.columns_count();
+        let mut data = Vec::with_capacity(columns * BLOB_POINTER_SIZE + self.columns_size as usize);
+        for i in 0..columns {
+            let pcol = self.pointer_column(i);
+            data.extend_from_slice(&{ pcol.rle_count }.to_le_bytes());
+            data.extend_from_slice(&{ pcol.first_range }.into_bytes());
+        }
+        for i in 0..columns {
+            data.extend_from_slice(self.column_bytes(i));
+        }
+        data
+    }
+
+    /// Restore volume from blob made by `to_blob`. The blob is validated, so it can come
+    /// from untrusted source. RLE axis of the result is Y.
+    pub fn from_blob(
+        xsize: u32,
+        ysize: u32,
+        zsize: u32,
+        data: &[u8],
+    ) -> Result<Self, &'static str> {
+        if xsize >= 1024 || ysize >= 1024 || zsize >= 1024 {
+            return Err("volume is too large");
+        }
+        let columns = (xsize * zsize) as usize;
+        if columns * BLOB_POINTER_SIZE > data.len() {
+            return Err("volume blob is too short for pointers map");
+        }
+        let (heads, mut rest) = data.split_at(columns * BLOB_POINTER_SIZE);
+
+        let mut packed = Vec::with_capacity(columns);
+        for head in heads.chunks_exact(BLOB_POINTER_SIZE) {
+            let rle_count = u16::from_le_bytes([head[0], head[1]]);
+            let first_range = RleRange::from_bytes([head[2], head[3]]);
+            let ranges_size = rle_count as usize * RLE_RANGE_SIZE;
+            if rest.len() < ranges_size {
+                return Err("volume blob is truncated");
+            }
+            let drawn: usize = first_range.drawn() as usize
+                + rest[..ranges_size]
+                    .chunks_exact(RLE_RANGE_SIZE)
+                    .map(|r| RleRange::from_bytes([r[0], r[1]]).drawn() as usize)
+                    .sum::<usize>();
+            let size = ranges_size + drawn * RGB_VOXEL_SIZE;
+            if rest.len() < size {
+                return Err("volume blob is truncated");
+            }
+            let column = PackedColumn {
+                rle_count,
+                first_range,
+                bytes: rest[..size].to_vec(),
+            };
+            if !column.is_valid(ysize) {
+                return Err("volume column is higher than the volume");
+            }
+            packed.push(column);
+            rest = &rest[size..];
+        }
+        if !rest.is_empty() {
+            return Err("volume blob has trailing bytes");
+        }
+        Ok(RleVolume::from_columns(
+            xsize as usize,
+            ysize as usize,
+            zsize as usize,
+            packed.iter().map(|c| c.unpack()),
+        ))
+    }
+
+    /// Make copy of the volume with the same layout, where color of each drawn voxel is
+    /// replaced by the function. The function receives color slot and current color.
+    pub fn map_colors<F>(&self, mut f: F) -> RleVolume
+    where
+        F: FnMut(usize, RgbVoxel) -> RgbVoxel,
+    {
+        let volume = self.clone();
+        for i in 0..volume.columns_count() {
+            for voxel in volume.drawn_voxels(i) {
+                let bytes = f(voxel.slot, voxel.color).into_bytes();
+                unsafe {
+                    let ptr = volume.columns.add(voxel.slot * RGB_VOXEL_SIZE);
+                    ptr.copy_from_nonoverlapping(bytes.as_ptr(), RGB_VOXEL_SIZE);
                 }
             }
         }
+        volume
+    }
+}
 
-        arr
+/// Column in the packed form as it is stored inside the volume: head from the pointers map
+/// and the bytes from the columns buffer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PackedColumn {
+    /// Count of RLE intervals stored in bytes
+    pub rle_count: u16,
+    /// First range that is stored in the pointers map
+    pub first_range: RleRange,
+    /// Ranges and colors of the column
+    pub bytes: Vec<u8>,
+}
+
+impl PackedColumn {
+    /// Check that bytes have the size the ranges require and the column fits the height.
+    /// Columns that come from untrusted sources must be checked before `unpack`.
+    pub fn is_valid(&self, ysize: u32) -> bool {
+        let ranges_size = self.rle_count as usize * RLE_RANGE_SIZE;
+        if self.bytes.len() < ranges_size {
+            return false;
+        }
+        let mut height = self.first_range.skipped() as usize + self.first_range.drawn() as usize;
+        let mut drawn = self.first_range.drawn() as usize;
+        for chunk in self.bytes[..ranges_size].chunks_exact(RLE_RANGE_SIZE) {
+            let range = RleRange::from_bytes([chunk[0], chunk[1]]);
+            height += range.skipped() as usize + range.drawn() as usize;
+            drawn += range.drawn() as usize;
+        }
+        height <= ysize as usize && self.bytes.len() == ranges_size + drawn * RGB_VOXEL_SIZE
+    }
+
+    /// Unpack the whole column including the first range
+    pub fn unpack(&self) -> RleColumn {
+        let mut drawn = self.first_range.drawn() as usize;
+        for i in 0..self.rle_count as usize {
+            let offset = i * RLE_RANGE_SIZE;
+            assert!(
+                offset + RLE_RANGE_SIZE <= self.bytes.len(),
+                "Packed column is truncated"
+            );
+            let range = RleRange::from_bytes([self.bytes[offset], self.bytes[offset + 1]]);
+            drawn += range.drawn() as usize;
+        }
+        assert_eq!(
+            self.bytes.len(),
+            (self.rle_count as usize) * RLE_RANGE_SIZE + drawn * RGB_VOXEL_SIZE,
+            "Packed column has wrong size"
+        );
+        unsafe {
+            RleColumn::unpack_from(
+                self.bytes.as_ptr(),
+                self.rle_count as usize,
+                Some(self.first_range),
+            )
+        }
+    }
+}
+
+/// Non empty voxel of a column with its location in the columns buffer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DrawnVoxel {
+    /// Height of the voxel in the column
+    pub y: u32,
+    /// Index of the voxel color in the columns buffer measured in voxels
+    pub slot: usize,
+    /// Color of the voxel
+    pub color: RgbVoxel,
+}
+
+#[cfg(feature = "std")]
+impl From<Array3<RgbVoxel>> for RleVolume {
+    fn from(array: Array3<RgbVoxel>) -> Self {
+        let (xsize, ysize, zsize) = array.dim();
+
+        let columns = (0..xsize * zsize).map(|i| {
+            let x = i % xsize;
+            let z = i / xsize;
+            let column = array
+                .slice(s![x..x + 1, .., z..z + 1])
+                .remove_axis(Axis(2))
+                .remove_axis(Axis(0));
+            RleColumn::compress(&column.to_vec())
+        });
+        RleVolume::from_columns(xsize, ysize, zsize, columns)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<RleVolume> for Array3<RgbVoxel> {
+    fn from(volume: RleVolume) -> Array3<RgbVoxel> {
+        volume.to_array()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use ndarray::arr3;
 
     #[test]
     fn empty_volumes() {
@@ -225,6 +724,60 @@ mod tests {
         let _v = RleVolume::empty(1024, 512, 1024);
     }
 
+    /// Column of given height with drawn voxels in a pattern that depends on `seed`
+    fn pattern_column(ysize: usize, seed: usize) -> Vec<RgbVoxel> {
+        (0..ysize)
+            .map(|y| {
+                if y % 3 == seed % 3 || y > 60 + seed {
+                    RgbVoxel::rgb(seed as u8 + 1, (y % 64) as u8, 7)
+                } else {
+                    RgbVoxel::empty()
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn columns_get_set_test() {
+        let (xsize, ysize, zsize) = (3, 70, 2);
+        let voxels: Vec<Vec<RgbVoxel>> = (0..xsize * zsize)
+            .map(|i| pattern_column(ysize, i))
+            .collect();
+        let columns: Vec<RleColumn> = voxels.iter().map(|c| RleColumn::compress(c)).collect();
+        let mut volume = RleVolume::from_columns(xsize, ysize, zsize, columns.clone());
+        assert_eq!(volume.dimensions(), [3, 70, 2]);
+        for (i, column) in columns.iter().enumerate() {
+            assert_eq!(&volume.column_at(i), column, "Column {}", i);
+            let (x, z) = ((i % xsize) as u32, (i / xsize) as u32);
+            for (y, voxel) in voxels[i].iter().enumerate() {
+                assert_eq!(
+                    volume.get(x, y as u32, z),
+                    *voxel,
+                    "Voxel {}x{}x{}",
+                    x,
+                    y,
+                    z
+                );
+            }
+        }
+
+        volume.set(2, 69, 1, RgbVoxel::only_red(5));
+        volume.set(0, 0, 0, RgbVoxel::empty());
+        volume.set(1, 1, 0, RgbVoxel::only_blue(9));
+        assert_eq!(volume.get(2, 69, 1), RgbVoxel::only_red(5));
+        assert!(volume.get(0, 0, 0).is_empty());
+        assert_eq!(volume.get(1, 1, 0), RgbVoxel::only_blue(9));
+        assert_eq!(volume.get(2, 1, 1), voxels[5][1], "Other voxels are intact");
+        assert_eq!(volume.column_at(4), columns[4], "Other columns are intact");
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod array_tests {
+    use super::*;
+    use crate::types::axis::RLE_AXES;
+    use ndarray::arr3;
+
     fn encode_decode_array(voxels: Array3<RgbVoxel>, descr: &str) {
         let (x, y, z) = voxels.dim();
         let volume: RleVolume = voxels.clone().into();
@@ -280,4 +833,138 @@ mod tests {
         ]);
         encode_decode_array(voxels, "partially filled");
     }
+
+    #[test]
+    fn encode_array_non_cubic() {
+        let voxels = Array3::from_shape_fn((3, 2, 5), |(x, y, z)| {
+            if (x + y + z) % 2 == 0 {
+                RgbVoxel::rgb(x as u8 + 1, y as u8, z as u8)
+            } else {
+                RgbVoxel::empty()
+            }
+        });
+        encode_decode_array(voxels, "non cubic");
+    }
+
+    #[test]
+    fn get_voxel_test() {
+        let voxels = Array3::from_shape_fn((3, 70, 2), |(x, y, z)| {
+            if y % 3 == 0 || y < 66 {
+                RgbVoxel::rgb(x as u8 + 1, (y % 64) as u8, z as u8)
+            } else {
+                RgbVoxel::empty()
+            }
+        });
+        let volume: RleVolume = voxels.clone().into();
+        for ((x, y, z), voxel) in voxels.indexed_iter() {
+            assert_eq!(
+                volume.get(x as u32, y as u32, z as u32),
+                *voxel,
+                "Voxel at {}x{}x{}",
+                x,
+                y,
+                z
+            );
+            assert_eq!(
+                volume.column(x as u32, z as u32).decompress()[y],
+                *voxel,
+                "Column voxel at {}x{}x{}",
+                x,
+                y,
+                z
+            );
+        }
+    }
+
+    #[test]
+    fn set_voxel_test() {
+        let mut expected = Array3::from_elem((4, 9, 3), RgbVoxel::empty());
+        let mut volume = RleVolume::from_array_with_axis(&expected, RleAxis::Z);
+        for (i, (x, y, z)) in [(1, 8, 2), (1, 7, 2), (3, 0, 0), (1, 8, 2)]
+            .iter()
+            .enumerate()
+        {
+            let voxel = RgbVoxel::rgb(i as u8 + 1, *y as u8, 5);
+            volume.set(*x, *y, *z, voxel);
+            expected[(*x as usize, *y as usize, *z as usize)] = voxel;
+        }
+        volume.set(1, 7, 2, RgbVoxel::empty());
+        volume.set(0, 4, 1, RgbVoxel::empty());
+        expected[(1, 7, 2)] = RgbVoxel::empty();
+        assert_eq!(volume.axis, RleAxis::Z);
+        assert_eq!(volume.to_array(), expected);
+    }
+
+    #[test]
+    fn packed_column_test() {
+        let voxels = Array3::from_shape_fn((3, 8, 2), |(x, y, z)| {
+            if (y + x) % 3 != 0 {
+                RgbVoxel::rgb(x as u8 + 1, y as u8, z as u8)
+            } else {
+                RgbVoxel::empty()
+            }
+        });
+        let volume: RleVolume = voxels.into();
+        let mut total = 0;
+        for i in 0..volume.columns_count() {
+            let packed = volume.packed_column(i);
+            assert_eq!(packed.unpack(), volume.column_at(i), "Packed column {}", i);
+            total += packed.bytes.len();
+        }
+        assert_eq!(
+            total, volume.columns_size as usize,
+            "Columns cover the whole buffer"
+        );
+    }
+
+    #[test]
+    fn replace_columns_test() {
+        let voxels = Array3::from_shape_fn((2, 3, 2), |(x, y, z)| {
+            RgbVoxel::rgb(x as u8 + 1, y as u8, z as u8)
+        });
+        let mut volume: RleVolume = voxels.clone().into();
+        let column = RleColumn::compress(&[RgbVoxel::empty(), RgbVoxel::only_red(3)]);
+        volume.replace_columns(vec![(volume.column_index(1, 0), column)]);
+
+        let mut expected = voxels;
+        expected[(1, 0, 0)] = RgbVoxel::empty();
+        expected[(1, 1, 0)] = RgbVoxel::only_red(3);
+        expected[(1, 2, 0)] = RgbVoxel::empty();
+        assert_eq!(volume.to_array(), expected, "Only one column is replaced");
+    }
+
+    #[test]
+    fn axis_encoding_test() {
+        let voxels = Array3::from_shape_fn((40, 3, 2), |(x, y, z)| {
+            RgbVoxel::rgb(x as u8 % 32, (y + 1) as u8, z as u8)
+        });
+        for axis in RLE_AXES {
+            let volume = RleVolume::from_array_with_axis(&voxels, axis);
+            assert_eq!(volume.axis, axis);
+            assert_eq!(
+                volume.dimensions(),
+                [40, 3, 2],
+                "Model sizes along {:?}",
+                axis
+            );
+            assert_eq!(volume.to_array(), voxels, "Decoding along {:?}", axis);
+            assert_eq!(
+                volume.get(17, 2, 1),
+                voxels[(17, 2, 1)],
+                "Voxel along {:?}",
+                axis
+            );
+        }
+        assert_eq!(
+            RleVolume::from_array_with_axis(&voxels, RleAxis::X).xsize,
+            3,
+            "Layout sizes are swapped"
+        );
+
+        // Each voxel has its own color, so the volume with the fewest columns is the smallest
+        let usage = RleVolume::axis_memory_usage(&voxels);
+        let smallest = usage.iter().min_by_key(|(_, size)| *size).unwrap().0;
+        assert_eq!(smallest, RleAxis::X, "Long volume is compressed along X");
+        assert_eq!(RleVolume::from_array_smallest(&voxels).axis, RleAxis::X);
+    }
 }
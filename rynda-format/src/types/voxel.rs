@@ -1,14 +1,14 @@
+use core::fmt;
 use core::ops::Add;
 use modular_bitfield::{specifiers::*, *};
 use num_traits::identities::Zero;
-use std::fmt;
 
 /// Amount of bytes RgbVoxel takes in memory
 pub const RGB_VOXEL_SIZE: usize = 2;
 
 /// 16-bit RGB voxel with 5 bits for red and blue colors and 6 bits for green color. Human eye is considered
 /// more sensitive to green tones. Zero values in all components are considered as an empty voxel.
-#[repr(packed(2))]
+#[repr(C, packed(2))]
 #[derive(Clone, Copy)]
 #[bitfield]
 pub struct RgbVoxel {
@@ -39,11 +39,7 @@ impl Add for RgbVoxel {
     type Output = RgbVoxel;
 
     fn add(self, other: Self) -> Self {
-        RgbVoxel::rgb(
-            self.red() + other.red(),
-            self.green() + other.green(),
-            self.blue() + other.blue(),
-        )
+        self.saturating_add(other)
     }
 }
 
@@ -91,6 +87,15 @@ impl RgbVoxel {
     pub fn only_blue(blue: u8) -> Self {
         Self::empty().with_blue(blue)
     }
+
+    /// Add colors component-wise, clamping each channel at its maximum
+    pub fn saturating_add(self, other: Self) -> Self {
+        RgbVoxel::rgb(
+            (self.red() + other.red()).min(31),
+            (self.green() + other.green()).min(63),
+            (self.blue() + other.blue()).min(31),
+        )
+    }
 }
 
 #[cfg(test)]
@@ -115,5 +120,10 @@ mod tests {
             [0b00000000, 0b11111000],
             "Blue bits are not in expected place"
         );
+        assert_eq!(
+            RgbVoxel::rgb(20, 40, 1) + RgbVoxel::rgb(20, 40, 2),
+            RgbVoxel::rgb(31, 63, 3),
+            "Addition saturates"
+        );
     }
 }
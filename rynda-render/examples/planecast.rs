@@ -8,7 +8,7 @@ use std::str;
 
 use rynda_format::types::{volume::RleVolume, voxel::RgbVoxel};
 use rynda_render::render::{
-    buffer::texture::Texture,
+    buffer::{shader::ShaderBuffer, texture::Texture},
     camera::Camera,
     debug::enable_gl_debug,
     pipeline::{
@@ -60,6 +60,8 @@ fn main() {
     });
     let volume: RleVolume = voxels.into();
     let pointmap_texture = Texture::from_pointermap(gl::TEXTURE0, &volume);
+    let pointmap_buffer = ShaderBuffer::from_pointermap(&volume);
+    let columns_buffer = ShaderBuffer::from_columns(&volume);
 
     let quad_vertex = str::from_utf8(include_bytes!("../shaders/quad.vert")).unwrap();
     let vertex_shader = str::from_utf8(include_bytes!("../shaders/quad_transform.vert")).unwrap();
@@ -130,6 +132,11 @@ fn main() {
             "volume_size",
             &UVec3::new(volume.xsize, volume.ysize, volume.zsize),
         );
+        planecast_pipelinne
+            .program
+            .set_uniform("volume_axis", &(volume.axis.code() as u32));
+        pointmap_buffer.bind(1);
+        columns_buffer.bind(4);
         planecast_pipelinne.draw();
 
         quad_pipeline.bind();
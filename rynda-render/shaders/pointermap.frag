@@ -6,6 +6,7 @@ layout(location = 0) out vec4 color;
 uniform uvec3 volume_size;
 uniform int mode;
 
+/// Texel of `PointerColumn`, decoding mirrors `PointerTexel`
 uint pointer(uvec4 column) {
     return uint(column.r) + (uint(column.g) << 16);
 }
@@ -107,3 +107,18 @@ impl IndexBuffer<GLshort> {
         }
     }
 }
+
+impl IndexBuffer<GLuint> {
+    pub fn draw(&self) {
+        if self.length > 0 {
+            unsafe {
+                gl::DrawElements(
+                    primitive_type_id(self.primitive),
+                    self.length as GLint,
+                    gl::UNSIGNED_INT,
+                    ptr::null(),
+                );
+            }
+        }
+    }
+}
@@ -1,5 +1,12 @@
 use gl::types::*;
-use rynda_format::types::{pointermap::PointerColumn, volume::RleVolume};
+use rynda_format::types::{
+    alpha::VoxelAlpha,
+    attribute::AttributeStream,
+    normal::PackedNormal,
+    pointermap::{column_words, PointerColumn},
+    view::RleVolumeView,
+    volume::RleVolume,
+};
 use std::marker::PhantomData;
 use std::mem;
 use std::os::raw::c_void;
@@ -82,40 +89,52 @@ impl<T> ShaderBuffer<T> {
             gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, 0);
         }
     }
+
+    /// Bind buffer to the given slot
+    pub fn bind(&self, slot: u32) {
+        unsafe {
+            gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, slot, self.id);
+        }
+    }
 }
 
 impl ShaderBuffer<PointerColumn> {
-    /// Create SSBO for RLE volume pointermap
+    /// Create SSBO for RLE volume pointermap. The map is in layout order of the volume, so
+    /// shaders need `volume.axis` as well to find model coordinates.
     pub fn from_pointermap(volume: &RleVolume) -> Self {
-        let mut id = 0;
-        let elements = (volume.xsize * volume.zsize) as usize;
-        let buffer_size = mem::size_of::<PointerColumn>() * elements;
+        ShaderBuffer::from_pointermap_view(&volume.view())
+    }
 
-        unsafe {
-            gl::GenBuffers(1, &mut id);
-            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, id);
-            gl::BufferData(
-                gl::SHADER_STORAGE_BUFFER,
-                buffer_size as isize,
-                volume.pointers as *const c_void,
-                gl::DYNAMIC_COPY,
-            );
-            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, 0);
-        }
+    /// Create SSBO for pointermap of borrowed volume, e.g. memory mapped one. Data goes
+    /// to GPU right from the borrowed memory.
+    pub fn from_pointermap_view(volume: &RleVolumeView) -> Self {
+        ShaderBuffer::from(volume.pointers)
+    }
+}
 
-        ShaderBuffer {
-            phantom: PhantomData,
-            id,
-            buffer_size,
-            elements,
-        }
+impl ShaderBuffer<u8> {
+    /// Create SSBO for columns buffer of borrowed volume without intermediate copy. Shaders
+    /// that read it as 32-bit words need `ShaderBuffer::from_columns` with padded tail.
+    pub fn from_columns_view(volume: &RleVolumeView) -> Self {
+        ShaderBuffer::from(volume.columns)
+    }
+}
+
+impl ShaderBuffer<u32> {
+    /// Create SSBO for columns buffer of RLE volume packed into words, see `column_words`
+    pub fn from_columns(volume: &RleVolume) -> Self {
+        ShaderBuffer::from(&column_words(volume.view().columns))
     }
 
-    /// Bind buffer to the given slot
-    pub fn bind(&self, slot: u32) {
-        unsafe {
-            gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, slot, self.id);
-        }
+    /// Create SSBO for normals that are parallel to the columns buffer of RLE volume
+    pub fn from_normals(normals: &AttributeStream<PackedNormal>) -> Self {
+        ShaderBuffer::from(&normals.to_words())
+    }
+
+    /// Create SSBO for alpha and material classes that are parallel to the columns buffer
+    /// of RLE volume
+    pub fn from_alpha(alphas: &AttributeStream<VoxelAlpha>) -> Self {
+        ShaderBuffer::from(&alphas.to_words())
     }
 }
 
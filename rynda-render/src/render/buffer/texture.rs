@@ -1,5 +1,6 @@
 use gl::types::*;
-use rynda_format::types::volume::RleVolume;
+use rynda_format::types::{view::RleVolumeView, volume::RleVolume};
+use std::os::raw::c_void;
 use std::{mem, ptr};
 
 #[derive(Debug, PartialEq, Eq)]
@@ -81,14 +82,21 @@ impl<const FORMAT: TextureFormat> Texture<FORMAT> {
 }
 
 impl Texture<{ TextureFormat::RGBAUI16 }> {
-    /// Make texture from pointermap of RLE volume
+    /// Make texture from pointermap of RLE volume. Texels are in layout order of the
+    /// volume, shaders need `volume.axis` as well to find model coordinates.
     pub fn from_pointermap(unit: GLenum, volume: &RleVolume) -> Self {
+        Texture::from_pointermap_view(unit, &volume.view())
+    }
+
+    /// Make texture from pointermap of borrowed volume, e.g. memory mapped one. Data goes
+    /// to GPU right from the borrowed memory.
+    pub fn from_pointermap_view(unit: GLenum, volume: &RleVolumeView) -> Self {
         let mut tex_id = 0;
         unsafe {
             gl::GenTextures(1, &mut tex_id);
             gl::ActiveTexture(unit);
             gl::BindTexture(gl::TEXTURE_2D, tex_id);
-            let datum = mem::transmute(volume.pointers);
+            let datum = volume.pointers.as_ptr() as *const c_void;
             gl::TexImage2D(
                 gl::TEXTURE_2D,
                 0,
@@ -12,7 +12,10 @@ use crate::render::{
         program::ShaderProgram,
     },
 };
-use rynda_format::types::{pointermap::PointerColumn, volume::RleVolume};
+use rynda_format::types::{
+    alpha::VoxelAlpha, attribute::AttributeStream, normal::PackedNormal, pointermap::PointerColumn,
+    volume::RleVolume,
+};
 
 /// Pipeline that renders raycast to a texture
 pub struct RaycastPipeline<'a> {
@@ -20,25 +23,54 @@ pub struct RaycastPipeline<'a> {
     pub texture: Texture<{ TextureFormat::RGBA }>,
     pub image_dimensions: (u32, u32),
     pub pointmap_buffer: ShaderBuffer<PointerColumn>,
+    pub columns_buffer: ShaderBuffer<u32>,
+    /// Shade voxels with the normals if they are set
+    pub normals_buffer: Option<ShaderBuffer<u32>>,
+    /// Blend translucent voxels front-to-back if alpha is set
+    pub alpha_buffer: Option<ShaderBuffer<u32>>,
     pub volume: &'a RleVolume,
 }
 
 impl<'a> RaycastPipeline<'a> {
+    /// Output image covers model XZ plane of the volume, columns may run along any axis
     pub fn new(compute_shader: &str, volume: &'a RleVolume) -> Self {
         let cs = Shader::compile(ShaderType::Compute, compute_shader);
         let program = ShaderProgram::link(vec![cs]);
 
-        let texture = Texture::new(gl::TEXTURE1, volume.xsize, volume.zsize, None);
+        let [xsize, _, zsize] = volume.dimensions();
+        let texture = Texture::new(gl::TEXTURE1, xsize, zsize, None);
         let pointmap_buffer = ShaderBuffer::from_pointermap(volume);
+        let columns_buffer = ShaderBuffer::from_columns(volume);
 
         RaycastPipeline {
             program,
             texture,
-            image_dimensions: (volume.xsize, volume.zsize),
+            image_dimensions: (xsize, zsize),
             pointmap_buffer,
+            columns_buffer,
+            normals_buffer: None,
+            alpha_buffer: None,
             volume,
         }
     }
+
+    /// Shade voxels with normals that are parallel to the columns buffer of the volume
+    pub fn set_normals(&mut self, normals: &AttributeStream<PackedNormal>) {
+        assert!(
+            normals.matches(self.volume),
+            "Normals are not parallel to the volume"
+        );
+        self.normals_buffer = Some(ShaderBuffer::from_normals(normals));
+    }
+
+    /// Blend translucent voxels with alpha that is parallel to the columns buffer of the volume
+    pub fn set_alpha(&mut self, alphas: &AttributeStream<VoxelAlpha>) {
+        assert!(
+            alphas.matches(self.volume),
+            "Alpha is not parallel to the volume"
+        );
+        self.alpha_buffer = Some(ShaderBuffer::from_alpha(alphas));
+    }
 }
 
 impl<'a> Pipeline for RaycastPipeline<'a> {
@@ -59,9 +91,13 @@ impl<'a> Pipeline for RaycastPipeline<'a> {
                 self.volume.zsize,
             );
 
+            // Columns may run along other model axis than Y
+            let volume_axis_id = self.program.uniform_location("volume_axis");
+            gl::Uniform1ui(volume_axis_id, self.volume.axis.code() as GLuint);
+
             // Bind texture as mutable
             gl::BindImageTexture(
-                1,
+                0,
                 self.texture.id as GLuint,
                 0,
                 gl::FALSE,
@@ -69,7 +105,20 @@ impl<'a> Pipeline for RaycastPipeline<'a> {
                 gl::WRITE_ONLY,
                 gl::RGBA8,
             );
-            self.pointmap_buffer.bind(0);
+            self.pointmap_buffer.bind(1);
+            self.columns_buffer.bind(4);
+
+            let use_normals_id = self.program.uniform_location("use_normals");
+            gl::Uniform1i(use_normals_id, self.normals_buffer.is_some() as GLint);
+            if let Some(normals) = &self.normals_buffer {
+                normals.bind(2);
+            }
+
+            let use_alpha_id = self.program.uniform_location("use_alpha");
+            gl::Uniform1i(use_alpha_id, self.alpha_buffer.is_some() as GLint);
+            if let Some(alphas) = &self.alpha_buffer {
+                alphas.bind(3);
+            }
         }
     }
 
@@ -3,7 +3,15 @@ use crate::math::{
     transform::{HasTransform, Transform},
 };
 use glam::IVec3;
-use rynda_format::types::volume::RleVolume;
+use rynda_format::{
+    brush::{Brush, BrushOp},
+    history::VolumeStore,
+    mesh::{surface_nets, SurfaceMesh},
+    region::{RegionError, RegionStore},
+    schem::{BlockColors, Schematic},
+    terrain::TerrainGenerator,
+    types::{source::VoxelSource, volume::RleVolume, voxel::RgbVoxel},
+};
 use std::collections::HashMap;
 
 /// Size of chunk in voxels in each dimension
@@ -62,6 +70,214 @@ impl ChunkedModel {
     pub fn get_chunk(&self, coords: IVec3) -> Option<&RleVolume> {
         self.volumes.get(&coords)
     }
+
+    /// Extract smooth surface of the chunk. Voxels of neighbour chunks are taken into
+    /// account, so meshes of adjacent chunks connect without seams. Positions are in
+    /// voxel units relative to the chunk corner.
+    pub fn surface_mesh(&self, coords: IVec3) -> SurfaceMesh {
+        let origin = coords * (CHUNK_SIZE as i32);
+        surface_nets(self, origin.to_array(), [CHUNK_SIZE; 3])
+    }
+
+    /// Apply brush to all chunks it touches. Brush transform is given in model voxel
+    /// coordinates that span across all chunks. Missing chunks are created when the brush
+    /// adds voxels to them. Returns changed columns of each touched chunk.
+    pub fn apply_brush(&mut self, brush: &Brush, op: BrushOp) -> HashMap<IVec3, Vec<usize>> {
+        let size = CHUNK_SIZE as i32;
+        let (min, max) = brush.voxel_bounds();
+        let min_chunk = IVec3::from(min.map(|v| v.div_euclid(size)));
+        let max_chunk = IVec3::from(max.map(|v| v.div_euclid(size)));
+
+        let mut dirty = HashMap::new();
+        for z in min_chunk.z..=max_chunk.z {
+            for y in min_chunk.y..=max_chunk.y {
+                for x in min_chunk.x..=max_chunk.x {
+                    let coords = IVec3::new(x, y, z);
+                    let origin = (coords * size).as_vec3();
+                    let local = Brush {
+                        transform: brush.transform.translated((-origin).to_array()),
+                        ..*brush
+                    };
+                    let columns = match self.volumes.get_mut(&coords) {
+                        Some(chunk) => chunk.apply_brush(&local, op),
+                        None => {
+                            if !matches!(op, BrushOp::Add(_)) {
+                                continue;
+                            }
+                            let mut chunk = RleVolume::empty(CHUNK_SIZE, CHUNK_SIZE, CHUNK_SIZE);
+                            let columns = chunk.apply_brush(&local, op);
+                            if !columns.is_empty() {
+                                self.add_chunk(coords, chunk);
+                            }
+                            columns
+                        }
+                    };
+                    if !columns.is_empty() {
+                        dirty.insert(coords, columns);
+                    }
+                }
+            }
+        }
+        dirty
+    }
+
+    /// Columns of all chunks that the brush can touch, including chunks that don't exist
+    /// yet. Used to record brush strokes in edit journal.
+    pub fn brush_columns(&self, brush: &Brush) -> Vec<(IVec3, usize)> {
+        let size = CHUNK_SIZE as i32;
+        let (min, max) = brush.voxel_bounds();
+        let min_chunk = IVec3::from(min.map(|v| v.div_euclid(size)));
+        let max_chunk = IVec3::from(max.map(|v| v.div_euclid(size)));
+
+        let mut columns = vec![];
+        for cz in min_chunk.z..=max_chunk.z {
+            for cy in min_chunk.y..=max_chunk.y {
+                for cx in min_chunk.x..=max_chunk.x {
+                    let coords = IVec3::new(cx, cy, cz);
+                    let origin = coords * size;
+                    if let Some(chunk) = self.volumes.get(&coords) {
+                        // Existing chunk may be encoded along other axis
+                        let local = Brush {
+                            transform: brush.transform.translated((-origin.as_vec3()).to_array()),
+                            ..*brush
+                        };
+                        columns
+                            .extend(chunk.brush_columns(&local).into_iter().map(|i| (coords, i)));
+                        continue;
+                    }
+                    let low = IVec3::from(min).max(origin);
+                    let high = IVec3::from(max).min(origin + IVec3::splat(size - 1));
+                    for z in low.z..=high.z {
+                        for x in low.x..=high.x {
+                            if (low.y..=high.y).any(|y| brush.covers(x, y, z)) {
+                                let index = (x - origin.x) + (z - origin.z) * size;
+                                columns.push((coords, index as usize));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        columns
+    }
+
+    /// Load chunk from region store unless it is already loaded. Returns false when the
+    /// store has no such chunk.
+    pub fn load_chunk(
+        &mut self,
+        store: &mut RegionStore,
+        coords: IVec3,
+    ) -> Result<bool, RegionError> {
+        if self.volumes.contains_key(&coords) {
+            return Ok(true);
+        }
+        match store.load_chunk(coords.to_array())? {
+            Some(chunk) => {
+                self.add_chunk(coords, chunk);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Save given loaded chunks into region store, chunks of each region are saved
+    /// atomically
+    pub fn save_chunks(
+        &self,
+        store: &mut RegionStore,
+        coords: &[IVec3],
+    ) -> Result<(), RegionError> {
+        store.save_chunks(
+            coords
+                .iter()
+                .filter_map(|c| self.volumes.get(c).map(|v| (c.to_array(), v))),
+        )
+    }
+
+    /// Generate chunk unless it is already loaded. Terrain is deterministic, so chunks that
+    /// were never edited can be dropped and generated again. Chunks have the model size
+    /// whatever chunk size the generator settings have.
+    pub fn generate_chunk(&mut self, generator: &TerrainGenerator, coords: IVec3) {
+        if !self.volumes.contains_key(&coords) {
+            let origin = coords * (CHUNK_SIZE as i32);
+            let chunk = generator.generate_box(origin.to_array(), [CHUNK_SIZE as u32; 3]);
+            self.add_chunk(coords, chunk);
+        }
+    }
+
+    /// Make model of a schematic that is too large for a single volume, one block is one
+    /// voxel
+    pub fn from_schematic(schematic: &Schematic, colors: &BlockColors) -> Self {
+        let mut model = ChunkedModel::new();
+        let chunks = schematic
+            .to_chunks(colors, CHUNK_SIZE)
+            .expect("Model chunk size is valid for schematics");
+        for (coords, chunk) in chunks {
+            model.add_chunk(IVec3::from(coords), chunk);
+        }
+        model
+    }
+
+    /// Save chunk and drop it from memory
+    pub fn unload_chunk(
+        &mut self,
+        store: &mut RegionStore,
+        coords: IVec3,
+    ) -> Result<(), RegionError> {
+        if let Some(chunk) = self.volumes.get(&coords) {
+            store.save_chunk(coords.to_array(), chunk)?;
+            self.volumes.remove(&coords);
+        }
+        Ok(())
+    }
+}
+
+impl VolumeStore for ChunkedModel {
+    type Key = IVec3;
+
+    fn volume(&self, key: &IVec3) -> Option<&RleVolume> {
+        self.volumes.get(key)
+    }
+
+    fn volume_mut(&mut self, key: &IVec3) -> Option<&mut RleVolume> {
+        self.volumes.get_mut(key)
+    }
+}
+
+impl VoxelSource for ChunkedModel {
+    /// Sample voxel in model voxel coordinates that span across all chunks
+    fn voxel(&self, x: i32, y: i32, z: i32) -> RgbVoxel {
+        let size = CHUNK_SIZE as i32;
+        let coords = IVec3::new(x.div_euclid(size), y.div_euclid(size), z.div_euclid(size));
+        match self.volumes.get(&coords) {
+            None => RgbVoxel::empty(),
+            Some(chunk) => chunk.voxel(x.rem_euclid(size), y.rem_euclid(size), z.rem_euclid(size)),
+        }
+    }
+
+    /// Decode each chunk column once for the part of the column that falls into it
+    fn voxel_column(&self, x: i32, y0: i32, z: i32, column: &mut [RgbVoxel]) {
+        let size = CHUNK_SIZE as i32;
+        let mut filled = 0;
+        while filled < column.len() {
+            let y = y0 + filled as i32;
+            let coords = IVec3::new(x.div_euclid(size), y.div_euclid(size), z.div_euclid(size));
+            let end = column
+                .len()
+                .min(filled + (size - y.rem_euclid(size)) as usize);
+            let part = &mut column[filled..end];
+            match self.volumes.get(&coords) {
+                None => part.fill(RgbVoxel::empty()),
+                Some(chunk) => chunk.voxel_column(
+                    x.rem_euclid(size),
+                    y.rem_euclid(size),
+                    z.rem_euclid(size),
+                    part,
+                ),
+            }
+            filled = end;
+        }
+    }
 }
 
 impl Default for ChunkedModel {